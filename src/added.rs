@@ -0,0 +1,136 @@
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinSet,
+};
+
+use crate::{
+    config::{EnvValue, WatchProcess},
+    dependency::Exports,
+    event::{Event, Line, Message, Stream},
+    shutdown::{self, Shutdown},
+};
+
+/// Processes added to the running session with `ctl add`. They are started right away, without
+/// the phases, dependencies and quarantine of the processes of the config.
+pub struct Added {
+    sender: mpsc::Sender<Message>,
+    shutdown: watch::Receiver<Shutdown>,
+    exports: Exports,
+    session_id: String,
+    running: JoinSet<()>,
+}
+
+impl Added {
+    pub fn new(
+        sender: mpsc::Sender<Message>,
+        shutdown: watch::Receiver<Shutdown>,
+        exports: Exports,
+        session_id: String,
+    ) -> Self {
+        Self {
+            sender,
+            shutdown,
+            exports,
+            session_id,
+            running: JoinSet::new(),
+        }
+    }
+
+    /// Process added from the JSON of the request.
+    pub fn parse(process: serde_json::Value) -> Result<WatchProcess, String> {
+        let process = serde_json::from_value::<WatchProcess>(process)
+            .map_err(|error| format!("invalid process: {error}"))?;
+        if process.title.is_empty() {
+            return Err("process has no title".to_string());
+        }
+        Ok(process)
+    }
+
+    /// Start the process as the instance with the number, returning the channel restarting it.
+    pub fn start(
+        &mut self,
+        mut process: WatchProcess,
+        instance: usize,
+    ) -> Result<mpsc::Sender<()>, String> {
+        // output of processes is not read anymore once they have all exited
+        if *self.shutdown.borrow() != Shutdown::Running || self.sender.is_closed() {
+            return Err("watchmux is stopping".to_string());
+        }
+        let metadata = [
+            ("WATCHMUX_TITLE", process.title.clone()),
+            ("WATCHMUX_INSTANCE", instance.to_string()),
+            ("WATCHMUX_SESSION_ID", self.session_id.clone()),
+        ];
+        for (name, value) in metadata {
+            process
+                .env
+                .entry(name.to_string())
+                .or_insert(EnvValue::Value(value));
+        }
+        let (restart_tx, mut restart) = mpsc::channel(1);
+        let (_, shutdown) = shutdown::follow(self.shutdown.clone());
+        let sender = self.sender.clone();
+        let exports = self.exports.clone();
+        self.running.spawn(async move {
+            let run = process
+                .run(
+                    sender.clone(),
+                    None,
+                    false,
+                    shutdown,
+                    &exports,
+                    &mut restart,
+                )
+                .await;
+            if let Err(error) = run {
+                let error = error.to_string();
+                let _ = sender
+                    .send(Message::Line(Line::new(
+                        &process.title,
+                        Stream::Notice,
+                        format!("failed: {error}"),
+                    )))
+                    .await;
+                let _ = sender
+                    .send(Message::Event(Event::Failed {
+                        title: process.title.clone(),
+                        error,
+                    }))
+                    .await;
+            }
+        });
+
+        Ok(restart_tx)
+    }
+
+    /// Wait for an added process to exit, returning `false` when none is running.
+    pub async fn next(&mut self) -> bool {
+        self.running.join_next().await.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn added_process_is_parsed_as_in_the_config() {
+        let process = Added::parse(serde_json::json!({
+            "title": "bench",
+            "cmd": "cargo bench",
+            "type": "shell",
+        }))
+        .unwrap();
+        assert_eq!(process.title, "bench");
+        assert_eq!(process.cmd, "cargo bench");
+
+        let untitled = Added::parse(serde_json::json!({ "cmd": "cargo bench" }));
+        assert_eq!(untitled.unwrap_err(), "process has no title");
+        let invalid = Added::parse(serde_json::json!({ "title": "bench", "type": "nope" }));
+        assert!(invalid.unwrap_err().starts_with("invalid process: "));
+    }
+}
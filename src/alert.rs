@@ -0,0 +1,82 @@
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal, Write},
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::WatchProcess,
+    event::{Line, Stream},
+    summary::WATCHMUX_TITLE,
+};
+
+/// Output line of a process which must not be missed e.g. a panic.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Alert {
+    #[serde(with = "serde_regex")]
+    pub pattern: Regex,
+    /// Ring the terminal bell.
+    #[serde(default)]
+    pub bell: bool,
+    /// Write the line again when watchmux exits.
+    #[serde(default)]
+    pub sticky: bool,
+}
+
+/// Alerts of the processes and sticky lines matched so far.
+#[derive(Debug, Default)]
+pub struct Alerts {
+    /// Alerts by title of the process.
+    alerts: HashMap<String, Vec<Alert>>,
+    sticky: Vec<Line>,
+}
+
+impl Alerts {
+    pub fn new(processes: &[WatchProcess]) -> Self {
+        let alerts = processes
+            .iter()
+            .filter(|process| !process.alerts.is_empty())
+            .map(|process| (process.title.clone(), process.alerts.clone()))
+            .collect();
+
+        Self {
+            alerts,
+            sticky: Vec::new(),
+        }
+    }
+
+    /// Ring the bell or keep the line if it matches an alert of its process.
+    pub fn check(&mut self, line: &Line) {
+        if line.stream == Stream::Notice {
+            return;
+        }
+        let Some(alerts) = self.alerts.get(&line.title) else {
+            return;
+        };
+
+        let matched = alerts
+            .iter()
+            .filter(|alert| alert.pattern.is_match(&line.text))
+            .collect::<Vec<_>>();
+        if matched.iter().any(|alert| alert.bell) && io::stdout().is_terminal() {
+            // bell is not part of the output written to the sinks
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+        }
+        let sticky = matched.iter().any(|alert| alert.sticky);
+        if sticky {
+            self.sticky.push(Line::new(
+                WATCHMUX_TITLE,
+                Stream::Notice,
+                format!("{}: {}", line.title, line.text),
+            ));
+        }
+    }
+
+    /// Sticky lines to write again at exit.
+    pub fn lines(self) -> Vec<Line> {
+        self.sticky
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    fmt::Display,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{self, AsyncWrite},
+    sync::mpsc::{self, Sender},
+    time::{self, Instant},
+};
+
+use crate::{
+    event::{Line, Message, Stream, MESSAGE_BUFFER},
+    output::{Formatter, OutputMode, Terminal},
+    sink::{Sinks, StdoutSink},
+};
+
+/// Interval synthetic processes write their lines in.
+const TICK: Duration = Duration::from_millis(10);
+
+/// Statistics of the output pipeline under synthetic load.
+#[derive(Debug)]
+pub struct Report {
+    processes: usize,
+    lines_per_sec: u64,
+    elapsed: Duration,
+    target: u64,
+    produced: u64,
+    bytes: u64,
+    /// Time from sending each line to having it written, sorted.
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    fn latency(&self, percentile: f64) -> Duration {
+        let index = ((self.latencies.len() as f64 * percentile) as usize)
+            .min(self.latencies.len().saturating_sub(1));
+        self.latencies.get(index).copied().unwrap_or_default()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.elapsed.as_secs_f64();
+        let consumed = self.latencies.len() as u64;
+        let behind = self.target.saturating_sub(self.produced);
+
+        writeln!(
+            f,
+            "processes: {}, target: {} lines/s, duration: {:.1}s",
+            self.processes, self.lines_per_sec, secs
+        )?;
+        writeln!(
+            f,
+            "throughput: {:.0} lines/s, {:.2} MiB/s",
+            consumed as f64 / secs,
+            self.bytes as f64 / secs / (1024.0 * 1024.0)
+        )?;
+        writeln!(
+            f,
+            "latency: p50 {:?}, p99 {:?}, max {:?}",
+            self.latency(0.5),
+            self.latency(0.99),
+            self.latency(1.0)
+        )?;
+        write!(
+            f,
+            "behind schedule: {behind} of {} lines ({:.2}%) not produced on time because of backpressure",
+            self.target,
+            behind as f64 * 100.0 / self.target.max(1) as f64
+        )
+    }
+}
+
+// TODO: criterion benches of the formatter and sinks once the pipeline is split into a library
+// crate they can link against, this measures the pipeline only end to end.
+/// Run synthetic processes writing lines at the given total rate through the output pipeline
+/// to the terminal sink, discarding its output, for the duration.
+pub async fn bench(
+    processes: usize,
+    lines_per_sec: u64,
+    duration: Duration,
+    output: OutputMode,
+) -> io::Result<Report> {
+    let (tx, mut rx) = mpsc::channel::<Message>(MESSAGE_BUFFER);
+    let start = Instant::now();
+    let per_tick = lines_per_sec as f64 * TICK.as_secs_f64() / processes.max(1) as f64;
+
+    let producers = (0..processes)
+        .map(|index| tokio::spawn(produce(index, per_tick, start, duration, tx.clone())))
+        .collect::<Vec<_>>();
+    drop(tx);
+
+    // terminal sink writing to nowhere, so formatting and writing are measured without flooding it
+    let written = Discard::default();
+    let mut sinks = Sinks::default();
+    sinks.register(Box::new(StdoutSink::with_writer(
+        Formatter::new(Terminal::detect(), output),
+        written.clone(),
+    )));
+    let mut latencies = Vec::new();
+    while let Some(message) = rx.recv().await {
+        sinks.write(&message).await?;
+        if let Message::Line(line) = message {
+            let sent = line
+                .text
+                .split(' ')
+                .next()
+                .and_then(|micros| micros.parse().ok())
+                .map(Duration::from_micros)
+                .unwrap_or_default();
+            latencies.push(start.elapsed().saturating_sub(sent));
+        }
+    }
+    sinks.finish().await?;
+    let elapsed = start.elapsed();
+
+    let mut produced = 0;
+    for producer in producers {
+        produced += producer.await.unwrap_or_default();
+    }
+    latencies.sort();
+
+    Ok(Report {
+        processes,
+        lines_per_sec,
+        elapsed,
+        target: (lines_per_sec as f64 * duration.as_secs_f64()) as u64,
+        produced,
+        bytes: written.0.load(Ordering::Relaxed),
+        latencies,
+    })
+}
+
+/// Writer discarding everything written to it, counting the bytes.
+#[derive(Debug, Clone, Default)]
+struct Discard(Arc<AtomicU64>);
+
+impl AsyncWrite for Discard {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.0.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Write lines of synthetic process prefixed with the time they are sent, returning amount of
+/// lines written.
+async fn produce(
+    index: usize,
+    per_tick: f64,
+    start: Instant,
+    duration: Duration,
+    tx: Sender<Message>,
+) -> u64 {
+    let title = format!("bench-{index}");
+    let mut interval = time::interval(TICK);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+    let mut due = 0.0;
+    let mut produced = 0;
+
+    while start.elapsed() < duration {
+        interval.tick().await;
+        due += per_tick;
+        while due >= 1.0 {
+            due -= 1.0;
+            let text = format!(
+                "{} GET /api/v1/resources/{produced} 200 OK in 3ms",
+                start.elapsed().as_micros()
+            );
+            if tx
+                .send(Message::Line(Line::new(&title, Stream::Stdout, text)))
+                .await
+                .is_err()
+            {
+                return produced;
+            }
+            produced += 1;
+        }
+    }
+
+    produced
+}
@@ -0,0 +1,214 @@
+//! `cargo watchmux` runs watchmux with `cargo watch` processes generated for the members of the
+//! cargo workspace.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use clap::{ArgEnum, Parser};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+#[derive(Parser, Debug)]
+#[clap(bin_name = "cargo")]
+enum Cargo {
+    /// Run watchmux with `cargo watch` processes generated for the members of the workspace.
+    Watchmux(Args),
+}
+
+#[derive(clap::Args, Debug)]
+#[clap(version)]
+struct Args {
+    /// Path to Cargo.toml of the workspace, defaults to the one of the current directory.
+    #[clap(long, value_name = "PATH")]
+    manifest_path: Option<PathBuf>,
+
+    /// Cargo commands run for each member on changes, `run` runs each binary of the member.
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "COMMANDS",
+        use_value_delimiter = true,
+        default_value = "check"
+    )]
+    commands: Vec<CargoCommand>,
+
+    /// Members to generate processes for, defaults to every member of the workspace.
+    #[clap(short, long, value_name = "MEMBERS", use_value_delimiter = true)]
+    package: Vec<String>,
+
+    /// Print the generated config instead of running it e.g. to save it as `.watchmuxrc.yaml`.
+    #[clap(long)]
+    print: bool,
+
+    /// Options passed to watchmux e.g. `cargo watchmux -- --tags api`.
+    #[clap(last = true, value_name = "WATCHMUX OPTIONS")]
+    watchmux: Vec<String>,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum CargoCommand {
+    Check,
+    Test,
+    Run,
+}
+
+/// Parts of the output of `cargo metadata` the processes are generated from.
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_members: Vec<String>,
+    workspace_root: PathBuf,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    id: String,
+    name: String,
+    targets: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+    kind: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+enum CargoWatchmuxError {
+    #[error("failed to run cargo metadata: {0}")]
+    Metadata(io::Error),
+
+    #[error("cargo metadata failed: {0}")]
+    MetadataFailed(String),
+
+    #[error("failed to parse cargo metadata: {0}")]
+    ParseMetadata(#[from] serde_json::Error),
+
+    #[error("no member of the workspace matches {0}")]
+    NoMembers(String),
+
+    #[error("failed to generate config: {0}")]
+    Config(#[from] serde_yaml::Error),
+
+    #[error("failed to write config: {0}")]
+    Write(io::Error),
+
+    #[error("failed to run watchmux: {0}")]
+    Watchmux(io::Error),
+}
+
+fn main() -> Result<(), CargoWatchmuxError> {
+    let Cargo::Watchmux(args) = Cargo::parse();
+
+    let metadata = metadata(args.manifest_path.as_deref())?;
+    let processes = processes(&metadata, &args.commands, &args.package);
+    if processes.is_empty() {
+        return Err(CargoWatchmuxError::NoMembers(args.package.join(", ")));
+    }
+    let config = serde_yaml::to_string(&json!({ "processes": processes }))?;
+    if args.print {
+        print!("{config}");
+        return Ok(());
+    }
+
+    // config is kept in the target directory so the workspace has the same config on each run
+    let path = metadata.target_directory.join("watchmux.yaml");
+    fs::create_dir_all(&metadata.target_directory).map_err(CargoWatchmuxError::Write)?;
+    fs::write(&path, config).map_err(CargoWatchmuxError::Write)?;
+
+    let mut watchmux = Command::new(watchmux_bin());
+    watchmux
+        .arg("-c")
+        .arg(&path)
+        .args(&args.watchmux)
+        .current_dir(&metadata.workspace_root);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        // watchmux handles the signals of the terminal as if it was run directly
+        Err(CargoWatchmuxError::Watchmux(watchmux.exec()))
+    }
+    #[cfg(not(unix))]
+    {
+        let status = watchmux.status().map_err(CargoWatchmuxError::Watchmux)?;
+        std::process::exit(status.code().unwrap_or(1))
+    }
+}
+
+fn metadata(manifest_path: Option<&Path>) -> Result<Metadata, CargoWatchmuxError> {
+    let mut cargo = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()));
+    cargo.args(["metadata", "--no-deps", "--format-version", "1"]);
+    if let Some(manifest_path) = manifest_path {
+        cargo.arg("--manifest-path").arg(manifest_path);
+    }
+    let output = cargo.output().map_err(CargoWatchmuxError::Metadata)?;
+    if !output.status.success() {
+        return Err(CargoWatchmuxError::MetadataFailed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Processes of the commands for the selected members in order of the members, tagged with the
+/// command and the name of the member.
+fn processes(metadata: &Metadata, commands: &[CargoCommand], selected: &[String]) -> Vec<Value> {
+    let members = metadata.packages.iter().filter(|package| {
+        metadata.workspace_members.contains(&package.id)
+            && (selected.is_empty() || selected.contains(&package.name))
+    });
+
+    let mut processes = Vec::new();
+    for package in members {
+        for command in commands {
+            let name = &package.name;
+            let runs = match command {
+                CargoCommand::Check => vec![(format!("check-{name}"), format!("check -p {name}"))],
+                CargoCommand::Test => vec![(format!("test-{name}"), format!("test -p {name}"))],
+                CargoCommand::Run => package
+                    .targets
+                    .iter()
+                    .filter(|target| target.kind.iter().any(|kind| kind == "bin"))
+                    .map(|target| {
+                        let bin = &target.name;
+                        (format!("run-{bin}"), format!("run -p {name} --bin {bin}"))
+                    })
+                    .collect(),
+            };
+            let tag = match command {
+                CargoCommand::Check => "check",
+                CargoCommand::Test => "test",
+                CargoCommand::Run => "run",
+            };
+
+            processes.extend(runs.into_iter().map(|(title, cargo)| {
+                json!({
+                    "title": title,
+                    "type": "shell",
+                    "cmd": format!("cargo watch -x '{cargo}'"),
+                    "tags": [tag, name],
+                    "requires": { "bin": "cargo-watch" },
+                })
+            }));
+        }
+    }
+
+    processes
+}
+
+/// watchmux installed next to this binary, or the one found in PATH.
+fn watchmux_bin() -> PathBuf {
+    let name = format!("watchmux{}", env::consts::EXE_SUFFIX);
+    env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(&name)))
+        .filter(|watchmux| watchmux.is_file())
+        .unwrap_or_else(|| PathBuf::from("watchmux"))
+}
@@ -0,0 +1,86 @@
+use std::{
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{self, AsyncRead, AsyncWriteExt, ReadBuf},
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+/// Stdout of the process written to a file as it was read, besides the lines of the output.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Capture {
+    /// File written again on each run of the process.
+    pub file: PathBuf,
+    #[serde(default)]
+    pub mode: CaptureMode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureMode {
+    /// Exact bytes of the output, safe for binary output.
+    #[default]
+    Raw,
+}
+
+/// Reader passing the bytes it reads to the capture file, if any.
+pub struct CaptureReader<T> {
+    inner: T,
+    bytes: Option<mpsc::UnboundedSender<Vec<u8>>>,
+}
+
+impl<T> CaptureReader<T> {
+    /// Reader of `inner` with the task writing the capture file, which completes once the reader
+    /// is dropped.
+    pub async fn new(
+        inner: T,
+        capture: Option<&Capture>,
+    ) -> io::Result<(Self, Option<JoinHandle<io::Result<()>>>)> {
+        let Some(capture) = capture else {
+            return Ok((Self { inner, bytes: None }, None));
+        };
+
+        let mut file = File::create(&capture.file).await?;
+        let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+        let writer = tokio::spawn(async move {
+            while let Some(bytes) = rx.recv().await {
+                file.write_all(&bytes).await?;
+            }
+            file.flush().await
+        });
+
+        Ok((
+            Self {
+                inner,
+                bytes: Some(tx),
+            },
+            Some(writer),
+        ))
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for CaptureReader<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(bytes)) = (&poll, &self.bytes) {
+            let read = &buf.filled()[filled..];
+            if !read.is_empty() {
+                // writer stops only on error, which is returned when it is awaited
+                let _ = bytes.send(read.to_vec());
+            }
+        }
+
+        poll
+    }
+}
@@ -0,0 +1,186 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Formats process output sections and failures for a CI system so logs collapse per process.
+pub trait CiFormatter: Send {
+    /// Marker starting section of contiguous output of the process.
+    fn open_section(&self, title: &str) -> String;
+
+    /// Marker ending section of the process opened with [`CiFormatter::open_section`].
+    fn close_section(&self, title: &str) -> String;
+
+    /// Failure annotation of the process.
+    fn error(&self, title: &str, message: &str) -> String;
+}
+
+/// GitHub Actions workflow commands.
+pub struct GitHubActions;
+
+impl CiFormatter for GitHubActions {
+    fn open_section(&self, title: &str) -> String {
+        format!("::group::{}\n", gha_escape_data(title))
+    }
+
+    fn close_section(&self, _: &str) -> String {
+        "::endgroup::\n".to_string()
+    }
+
+    fn error(&self, title: &str, message: &str) -> String {
+        format!(
+            "::error title={}::{}\n",
+            gha_escape_data(title)
+                .replace(':', "%3A")
+                .replace(',', "%2C"),
+            gha_escape_data(message)
+        )
+    }
+}
+
+fn gha_escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Buildkite collapsed log sections.
+pub struct Buildkite;
+
+impl CiFormatter for Buildkite {
+    fn open_section(&self, title: &str) -> String {
+        format!("--- {title}\n")
+    }
+
+    fn close_section(&self, _: &str) -> String {
+        // section lasts until next section header
+        String::new()
+    }
+
+    fn error(&self, title: &str, message: &str) -> String {
+        // expand the previous section to show the failure
+        format!("^^^ +++\n{title}: {message}\n")
+    }
+}
+
+/// GitLab CI collapsible sections.
+pub struct GitLab;
+
+impl GitLab {
+    fn section_name(title: &str) -> String {
+        title
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
+}
+
+impl CiFormatter for GitLab {
+    fn open_section(&self, title: &str) -> String {
+        format!(
+            "\x1b[0Ksection_start:{}:{}[collapsed=true]\r\x1b[0K{title}\n",
+            unix_timestamp(),
+            GitLab::section_name(title)
+        )
+    }
+
+    fn close_section(&self, title: &str) -> String {
+        format!(
+            "\x1b[0Ksection_end:{}:{}\r\x1b[0K\n",
+            unix_timestamp(),
+            GitLab::section_name(title)
+        )
+    }
+
+    fn error(&self, title: &str, message: &str) -> String {
+        format!("\x1b[31;1mERROR: {title}: {message}\x1b[0m\n")
+    }
+}
+
+/// TeamCity service messages.
+pub struct TeamCity;
+
+impl TeamCity {
+    fn escape(value: &str) -> String {
+        value
+            .replace('|', "||")
+            .replace('\'', "|'")
+            .replace('\n', "|n")
+            .replace('\r', "|r")
+            .replace('[', "|[")
+            .replace(']', "|]")
+    }
+}
+
+impl CiFormatter for TeamCity {
+    fn open_section(&self, title: &str) -> String {
+        format!(
+            "##teamcity[blockOpened name='{}']\n",
+            TeamCity::escape(title)
+        )
+    }
+
+    fn close_section(&self, title: &str) -> String {
+        format!(
+            "##teamcity[blockClosed name='{}']\n",
+            TeamCity::escape(title)
+        )
+    }
+
+    fn error(&self, title: &str, message: &str) -> String {
+        format!(
+            "##teamcity[buildProblem description='{}']\n",
+            TeamCity::escape(&format!("{title}: {message}"))
+        )
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn github_actions_escapes_data_and_title_properties() {
+        assert_eq!(
+            GitHubActions.open_section("api 100%\nnext"),
+            "::group::api 100%25%0Anext\n"
+        );
+        assert_eq!(
+            GitHubActions.error("db:test, 1", "exited\r\nwith 1"),
+            "::error title=db%3Atest%2C 1::exited%0D%0Awith 1\n"
+        );
+    }
+
+    #[test]
+    fn teamcity_escapes_service_message_values() {
+        assert_eq!(
+            TeamCity.open_section("it's [a|b]"),
+            "##teamcity[blockOpened name='it|'s |[a||b|]']\n"
+        );
+        assert_eq!(
+            TeamCity.error("api", "failed\nwith 1"),
+            "##teamcity[buildProblem description='api: failed|nwith 1']\n"
+        );
+    }
+
+    #[test]
+    fn gitlab_section_names_replace_other_characters() {
+        assert_eq!(
+            GitLab::section_name("web app:dev.1-a_b"),
+            "web_app_dev.1-a_b"
+        );
+        assert!(GitLab
+            .close_section("web app")
+            .contains(":web_app\r\x1b[0K\n"));
+    }
+}
@@ -0,0 +1,42 @@
+use std::{collections::BTreeSet, io};
+
+use clap::Command;
+use clap_complete::Shell;
+
+use crate::config::Config;
+
+/// Write completions of the shell to stdout. Titles, tags and aliases of the config are completed
+/// as well when the config is found.
+pub fn write<'a>(shell: Shell, mut command: Command<'a>, config: Option<&'a Config>) {
+    if let Some(config) = config {
+        let titles = config
+            .processes
+            .iter()
+            .map(|process| process.title.as_str())
+            .collect::<Vec<_>>();
+        let tags = config
+            .processes
+            .iter()
+            .flat_map(|process| process.tags.iter().map(String::as_str))
+            .collect::<BTreeSet<_>>();
+
+        command = command
+            .mut_arg("tags", |arg| arg.possible_values(tags))
+            .mut_subcommand("logs", |logs| {
+                logs.mut_arg("title", |arg| arg.possible_values(titles.iter().copied()))
+            })
+            .mut_subcommand("test", |test| {
+                // default title is not required to be one of the titles completed
+                test.mut_arg("process", |arg| {
+                    arg.default_values(&[])
+                        .possible_values(titles.iter().copied())
+                })
+            });
+        for (name, alias) in &config.aliases {
+            command = command.subcommand(Command::new(name.as_str()).about(alias.as_str()));
+        }
+    }
+
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut io::stdout());
+}
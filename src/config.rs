@@ -1,24 +1,87 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     env,
+    future::Future,
     path::{Path, PathBuf},
+    pin::Pin,
     process::{ExitStatus, Stdio},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
 };
 
-use ansi_term::{Color, Style};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::{
     fs,
-    io::{self, AsyncBufRead, AsyncBufReadExt, BufReader, Lines},
-    process::{Child, Command},
-    sync::mpsc::{error::SendError, Sender},
-    task::JoinError,
+    io::{self, AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, Command},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{self, error::SendError, Sender},
+        watch, Notify,
+    },
+    task::{JoinError, JoinHandle},
+    time::{self, Instant},
+};
+
+use crate::{
+    alert::Alert,
+    capture::{Capture, CaptureReader},
+    control::HttpControl,
+    correlation::Correlation,
+    dependency::Exports,
+    event::{EndedBy, Event, Line, Message, Stream},
+    extends::{self, ExtendsError},
+    history::RunHistory,
+    hyperlink::Hyperlinks,
+    lock::LockError,
+    matrix::{self, MatrixError},
+    phase::PhaseConfig,
+    ports::PortAllocation,
+    reader::LineReader,
+    readiness::{ProcessState, Readiness},
+    remote::{self, RemoteError},
+    requires::{Requires, RequiresError},
+    resources::StartWhen,
+    schedule::TimeOfDay,
+    session::SessionError,
+    shell,
+    shutdown::Shutdown,
+    sink::SinkConfig,
+    size::ByteSize,
+    transform::{MakeDirectory, TransformConfig, Transforms},
 };
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     pub processes: Vec<WatchProcess>,
+    #[serde(default)]
+    pub path_prepend: Vec<PathBuf>,
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    /// Wrap long lines to the terminal width.
+    #[serde(default)]
+    pub wrap: bool,
+    /// Local time of the day processes are stopped at.
+    pub stop_at: Option<TimeOfDay>,
+    /// File stopping watchmux gracefully when it appears, or the processes titled in it.
+    pub stop_file: Option<PathBuf>,
+    /// Destinations of the output, defaults to stdout.
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+    pub hyperlinks: Option<Hyperlinks>,
+    /// Color ids correlating lines across processes.
+    pub correlation: Option<Correlation>,
+    /// Name of built-in theme or path to theme file coloring the output.
+    pub theme: Option<String>,
+    /// Groups of processes started one after another.
+    #[serde(default)]
+    pub phases: Vec<PhaseConfig>,
+    /// Memory of the latest output lines kept for `ctl search`.
+    pub buffer_memory: Option<ByteSize>,
+    /// Control API served over HTTP besides the socket of the session.
+    pub http_control: Option<HttpControl>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -27,20 +90,450 @@ pub enum RunType {
     Shell,
     #[serde(rename = "cmd")]
     Cmd,
+    #[serde(rename = "kubectl-logs")]
+    KubectlLogs,
+    #[serde(rename = "script")]
+    Script,
+    #[serde(rename = "npm")]
+    Npm,
+    #[serde(rename = "make")]
+    Make,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WatchProcess {
-    title: String,
-    cmd: String,
+    /// Title of the process, derived from name of the command when not given.
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub cmd: String,
     #[serde(default = "default_true")]
-    log: bool,
+    pub log: bool,
     #[serde(rename = "type")]
-    run_type: Option<RunType>,
+    pub run_type: Option<RunType>,
+    #[serde(default)]
+    pub env: HashMap<String, EnvValue>,
+    #[serde(default)]
+    pub wait_for: WaitFor,
+    /// Hide output of `wait_for` unless it fails.
+    #[serde(default)]
+    pub wait_for_quiet: bool,
+    #[serde(default)]
+    pub stdin: StdinMode,
+    #[serde(default)]
+    pub path_prepend: Vec<PathBuf>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub restart_on: Option<RestartOn>,
+    pub watchdog: Option<Watchdog>,
+    pub stop_cmd: Option<String>,
+    /// Kubectl context of `type: kubectl-logs`.
+    pub context: Option<String>,
+    /// Namespace of the pods of `type: kubectl-logs`.
+    pub namespace: Option<String>,
+    /// Label selector of the pods of `type: kubectl-logs`.
+    pub selector: Option<String>,
+    /// Script file of `type: script`.
+    pub file: Option<PathBuf>,
+    /// Arguments passed to the script file of `type: script`, package script of `type: npm` or
+    /// make of `type: make`.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Package script of `type: npm` e.g. `dev`.
+    pub script: Option<String>,
+    /// Target of `type: make`, the default target of the makefile when not given.
+    pub target: Option<String>,
+    /// Directory of the makefile of `type: make`.
+    pub directory: Option<PathBuf>,
+    /// Titles of processes this process is stopped with when they fail.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// CPUs the process is allowed to run on, only supported on Linux.
+    #[serde(default)]
+    pub cpu_affinity: Vec<usize>,
+    /// Write the command as the first line of each run of the process.
+    #[serde(default)]
+    pub echo_cmd: bool,
+    /// Names of variables the process publishes with `WATCHMUX_EXPORT` lines. Dependents are
+    /// started once all of them are published.
+    #[serde(default)]
+    pub exports: Vec<String>,
+    /// Patterns whose named capture groups publish variables from the output lines of the
+    /// process like `exports`, e.g. `url: (?P<DATABASE_URL>postgres://\S+)`.
+    #[serde(default, with = "serde_regex")]
+    pub export_patterns: Vec<Regex>,
+    /// Transforms applied to the output lines in order e.g. redacting secrets.
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub transforms: Vec<TransformConfig>,
+    /// Separate output of each restart from the output of the previous run with a rule.
+    #[serde(default)]
+    pub clear_on_restart: bool,
+    /// Keep stderr as a separate stream or merge it to stdout preserving order of the lines.
+    #[serde(default)]
+    pub stderr: StderrMode,
+    /// Title of the process stdout lines of this process are written to stdin of.
+    pub pipe_to: Option<String>,
+    /// Lines which ring the bell or are written again at exit.
+    #[serde(default)]
+    pub alerts: Vec<Alert>,
+    /// Forward multi-line blocks e.g. stack traces contiguously without output of other processes
+    /// in between.
+    pub multiline: Option<Multiline>,
+    /// Command run with the shell when the process exits successfully.
+    pub on_success: Option<String>,
+    /// Command run with the shell when the process exits unsuccessfully.
+    pub on_failure: Option<String>,
+    /// Emoji or nerd font glyph shown before the title e.g. `"🦀"`.
+    pub icon: Option<String>,
+    /// Host, container or cluster the process runs on shown before the title e.g. `[ prod:api ]`.
+    pub origin: Option<String>,
+    /// Share the temporary directory of the system or give each run its own.
+    #[serde(default)]
+    pub tmpdir: TmpDirMode,
+    /// Program checked to be installed before the process is started.
+    pub requires: Option<Requires>,
+    /// Question answered before the process is started, it is left out unless confirmed.
+    pub confirm: Option<String>,
+    /// Env variables given a free port at startup, also given to the dependents.
     #[serde(default)]
-    env: HashMap<String, String>,
+    pub ports: HashMap<String, PortAllocation>,
+    /// Skip the process when it has run successfully with the same command, env and
+    /// `fingerprint` files.
     #[serde(default)]
-    wait_for: String,
+    pub run_once: bool,
+    /// Files and directories whose contents are part of the cache key of `run_once`.
+    #[serde(default)]
+    pub fingerprint: Vec<PathBuf>,
+    /// File stdout of the process is written to as raw bytes.
+    pub capture: Option<Capture>,
+    /// Output of a single run after which further output is dropped e.g. `50MB`.
+    pub max_output: Option<ByteSize>,
+    /// Drop further output or kill the process once `max_output` is exceeded.
+    #[serde(default)]
+    pub max_output_action: MaxOutputAction,
+    /// Give the terminal of watchmux to the process as is, for REPLs and other interactive
+    /// programs. Only one process can have it.
+    #[serde(default)]
+    pub foreground: bool,
+    /// Defer starting the process until the machine has the headroom.
+    pub start_when: Option<StartWhen>,
+    /// Replace output written before the process is ready with a single line unless it fails.
+    #[serde(default)]
+    pub suppress_until_ready: bool,
+    /// Start the process with watchmux, or only once requested with `ctl start` when `false`.
+    #[serde(default = "default_true")]
+    pub autostart: bool,
+    /// Stop the running instance before restarting, or only once a new instance is ready.
+    #[serde(default)]
+    pub restart_strategy: RestartStrategy,
+}
+
+/// Block of lines starting with a line matching `start_pattern` followed by lines matching
+/// `continuation_pattern`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Multiline {
+    #[serde(with = "serde_regex")]
+    pub start_pattern: Regex,
+    #[serde(with = "serde_regex")]
+    pub continuation_pattern: Regex,
+}
+
+/// Restart the process when its output matches the pattern.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RestartOn {
+    #[serde(with = "serde_regex")]
+    pub pattern: Regex,
+    /// Minimum time between consecutive starts of the process, grown exponentially for
+    /// processes restarting repeatedly.
+    #[serde(default = "default_cooldown", with = "humantime_serde")]
+    pub cooldown: Duration,
+}
+
+/// Act on a process which has not written any output for a while, likely being hung.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Watchdog {
+    #[serde(with = "humantime_serde")]
+    pub no_output_for: Duration,
+    #[serde(default)]
+    pub action: WatchdogAction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchdogAction {
+    /// Restart the process with backoff of `restart_on` cooldown.
+    Restart,
+    /// Write a notice each time the process has been silent for the duration.
+    #[default]
+    Warn,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MaxOutputAction {
+    /// Drop further output of the run.
+    #[default]
+    Drop,
+    /// Kill the process, dropping the output it writes until it exits.
+    Kill,
+}
+
+/// Output of a run counted against `max_output` of the process.
+struct OutputLimit {
+    max: ByteSize,
+    action: MaxOutputAction,
+    written: AtomicU64,
+    exceeded: Notify,
+}
+
+impl OutputLimit {
+    /// Wait until the limit of a process killed by it is exceeded, never completing without
+    /// `limit`.
+    async fn exceeded(limit: Option<&OutputLimit>) {
+        match limit.filter(|limit| limit.action == MaxOutputAction::Kill) {
+            Some(limit) => limit.exceeded.notified().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Count the lines written. Returns `Ok` when they are within the limit, otherwise the
+    /// notice to write once the lines exceed the limit.
+    fn count(&self, lines: &[Line]) -> Result<(), Option<String>> {
+        let bytes = lines
+            .iter()
+            .map(|line| line.text.len() as u64 + 1)
+            .sum::<u64>();
+        let written = self.written.fetch_add(bytes, Ordering::Relaxed);
+        if written + bytes <= self.max.0 {
+            return Ok(());
+        }
+        if written > self.max.0 {
+            return Err(None);
+        }
+
+        self.exceeded.notify_one();
+        Err(Some(match self.action {
+            MaxOutputAction::Drop => format!(
+                "output exceeded max_output of {}, further output of this run is dropped",
+                self.max
+            ),
+            MaxOutputAction::Kill => format!(
+                "output exceeded max_output of {}, killing the process",
+                self.max
+            ),
+        }))
+    }
+}
+
+const KILL_OUTPUT_GRACE: Duration = Duration::from_secs(1);
+/// Last lines of `wait_for` output written again when it fails.
+const WAIT_FOR_OUTPUT_LINES: usize = 50;
+/// Package managers by their lockfile.
+const NPM_LOCKFILES: [(&str, &str); 3] = [
+    ("pnpm-lock.yaml", "pnpm"),
+    ("yarn.lock", "yarn"),
+    ("package-lock.json", "npm"),
+];
+/// Delay before reconnecting ended pod log stream, grown exponentially on failing reconnects.
+const KUBECTL_RECONNECT: Duration = Duration::from_secs(2);
+
+fn default_cooldown() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// Value of an environment variable, either given as is or resolved at startup.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum EnvValue {
+    Value(String),
+    From(EnvSource),
+    /// Secret resolved from the source.
+    #[serde(skip)]
+    Resolved(String),
+}
+
+impl EnvValue {
+    /// Value of the variable unless it is not resolved yet.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            EnvValue::Value(value) | EnvValue::Resolved(value) => Some(value),
+            EnvValue::From(_) => None,
+        }
+    }
+}
+
+/// Source secrets are read from so they need not to be written to the config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "from", rename_all = "snake_case")]
+pub enum EnvSource {
+    /// Standard output of the command run with the shell e.g. `op read op://dev/api/key`.
+    Command { cmd: String },
+    /// Content of the file.
+    File { path: PathBuf },
+}
+
+impl EnvSource {
+    async fn resolve(&self) -> Result<String, String> {
+        let value = match self {
+            EnvSource::Command { cmd } => {
+                let output = shell::command(cmd)
+                    .stdin(Stdio::inherit())
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .await
+                    .map_err(|error| error.to_string())?;
+                if !output.status.success() {
+                    return Err(format!("`{cmd}` exited with {}", output.status));
+                }
+                String::from_utf8(output.stdout).map_err(|error| error.to_string())?
+            }
+            EnvSource::File { path } => fs::read_to_string(path)
+                .await
+                .map_err(|error| format!("{}: {error}", path.display()))?,
+        };
+
+        Ok(value.trim_end_matches(['\n', '\r']).to_string())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StderrMode {
+    #[default]
+    Separate,
+    /// Stderr is written to the stdout pipe like `2>&1`, only supported on unix.
+    Merge,
+}
+
+/// How a running process is restarted with a key, signal or `ctl`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartStrategy {
+    /// Stop the running instance before starting a new one.
+    #[default]
+    Recreate,
+    /// Start a new instance and stop the running one once the new one is ready.
+    BlueGreen,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TmpDirMode {
+    #[default]
+    Shared,
+    /// Fresh `TMPDIR` under the session directory for each run, removed when the run exits.
+    Isolated,
+}
+
+/// What the process waits for before its `cmd` is run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum WaitFor {
+    /// Command run with the shell which needs to complete successfully.
+    Command(String),
+    /// State of another process e.g. `{ process: db, state: ready }`.
+    Process {
+        process: String,
+        #[serde(default)]
+        state: ProcessState,
+    },
+}
+
+impl Default for WaitFor {
+    fn default() -> Self {
+        WaitFor::Command(String::new())
+    }
+}
+
+impl WaitFor {
+    /// Command to wait for, `None` when waiting for a process or for nothing.
+    pub fn command(&self) -> Option<&str> {
+        match self {
+            WaitFor::Command(command) if !command.is_empty() => Some(command),
+            _ => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == WaitFor::default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub enum StdinMode {
+    #[default]
+    #[serde(rename = "broadcast")]
+    Broadcast,
+    #[serde(rename = "ignore")]
+    Ignore,
+}
+
+impl Config {
+    /// Resolve env values read from commands and files.
+    pub async fn resolve_env(&mut self) -> Result<(), ConfigError> {
+        for process in &mut self.processes {
+            for (name, value) in &mut process.env {
+                if let EnvValue::From(source) = value {
+                    let resolved = source.resolve().await.map_err(|reason| ConfigError::Env {
+                        title: process.title.clone(),
+                        name: name.clone(),
+                        reason,
+                    })?;
+                    *value = EnvValue::Resolved(resolved);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Title processes without one by name of their command, suffixed with a number when the
+    /// title is already taken e.g. `cargo-2`.
+    fn derive_titles(&mut self) {
+        let mut taken = self
+            .processes
+            .iter()
+            .map(|process| process.title.clone())
+            .filter(|title| !title.is_empty())
+            .collect::<Vec<_>>();
+
+        for process in &mut self.processes {
+            if !process.title.is_empty() {
+                continue;
+            }
+            let name = process.program_name();
+            let title = (1..)
+                .map(|index| match index {
+                    1 => name.clone(),
+                    index => format!("{name}-{index}"),
+                })
+                .find(|title| !taken.contains(title))
+                .unwrap_or(name);
+            taken.push(title.clone());
+            process.title = title;
+        }
+    }
+
+    /// Retain processes matching the tag selection. Process is selected when it has any of the
+    /// tags and none of the tags prefixed with `!`. Without plain tags all processes are
+    /// considered to match.
+    pub fn select_tags(&mut self, selection: &[String]) {
+        let (excluded, included): (Vec<&str>, Vec<&str>) = selection
+            .iter()
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .partition(|tag| tag.starts_with('!'));
+        let excluded = excluded
+            .into_iter()
+            .map(|tag| tag.trim_start_matches('!'))
+            .collect::<Vec<_>>();
+
+        self.processes.retain(|process| {
+            let has_tag = |tag: &&str| process.tags.iter().any(|process_tag| process_tag == tag);
+
+            (included.is_empty() || included.iter().any(has_tag)) && !excluded.iter().any(has_tag)
+        });
+    }
 }
 
 fn default_true() -> bool {
@@ -48,86 +541,798 @@ fn default_true() -> bool {
 }
 
 impl WatchProcess {
-    pub async fn run(&self, tx: Sender<String>) -> Result<(), WatchError> {
-        if !self.wait_for.is_empty() {
-            let child = Command::new("bash")
-                .arg("-c")
-                .arg(&self.wait_for)
+    /// Run the process until it exits by itself or is stopped. Returns status of the last run
+    /// when the process exited by itself. Running process is restarted on `restart`. Stdin of
+    /// watchmux is inherited without lines of `stdin` unless it is read for keys.
+    pub async fn run(
+        &self,
+        tx: Sender<Message>,
+        stdin: Option<broadcast::Receiver<String>>,
+        inherit_stdin: bool,
+        mut shutdown: watch::Receiver<Shutdown>,
+        exports: &Exports,
+        restart: &mut mpsc::Receiver<()>,
+    ) -> Result<Option<ExitStatus>, WatchError> {
+        if let Some(requires) = &self.requires {
+            let requires = requires.clone();
+            let path = self
+                .envs()
+                .remove("PATH")
+                .or_else(|| env::var("PATH").ok())
+                .unwrap_or_default();
+            tokio::task::spawn_blocking(move || requires.check(&path)).await??;
+        }
+
+        if !exports.ready(&self.depends_on) {
+            tx.send(Message::Line(Line::new(
+                &self.title,
+                Stream::Notice,
+                format!("waiting for exports of {}", self.depends_on.join(", ")),
+            )))
+            .await?;
+            tokio::select! {
+                _ = exports.wait(&self.depends_on) => (),
+                _ = shutdown.changed() => return Ok(None),
+            }
+        }
+
+        if let Some(wait_for) = self.wait_for.command() {
+            let child = shell::command(wait_for)
                 .stdout(Stdio::piped())
                 .stderr(Stdio::piped())
-                .envs(&self.env)
+                .envs(self.envs())
                 .spawn()
                 .map_err(WatchError::IoChildProcess)?;
 
-            self.execute_and_await(child, tx.clone(), &self.title)
-                .await
-                .and_then(|status| {
-                    if status.success() {
-                        Ok(())
-                    } else {
-                        Err(WatchError::AwaitFor(status))
+            // stderr, and stdout when quiet, is kept to be written again if wait_for fails
+            let (output_tx, mut output_rx) = mpsc::channel::<Message>(16);
+            let collect = async {
+                let mut output = VecDeque::with_capacity(WAIT_FOR_OUTPUT_LINES);
+                while let Some(message) = output_rx.recv().await {
+                    let lines = message.lines().iter().filter(|line| {
+                        line.stream == Stream::Stderr
+                            || (self.wait_for_quiet && line.stream == Stream::Stdout)
+                    });
+                    for line in lines.cloned() {
+                        if output.len() == WAIT_FOR_OUTPUT_LINES {
+                            output.pop_front();
+                        }
+                        output.push_back(line);
                     }
-                })?;
+                    if !self.wait_for_quiet || matches!(message, Message::Event(_)) {
+                        tx.send(message).await?;
+                    }
+                }
+                Ok::<_, WatchError>(output)
+            };
+            let (exit, output) = tokio::join!(
+                self.execute_and_await(child, output_tx, None, false, None, &mut shutdown),
+                collect
+            );
+            match exit? {
+                ProcessExit::Exited(status) if status.success() => (),
+                ProcessExit::Stopped(_) => return Ok(None),
+                ProcessExit::Exited(status) | ProcessExit::Restart(status, _) => {
+                    let output = output?;
+                    if !output.is_empty() {
+                        tx.send(Message::Line(Line::new(
+                            &self.title,
+                            Stream::Notice,
+                            format!("wait_for failed with {status}, its last output:"),
+                        )))
+                        .await?;
+                        tx.send(Message::Block(output.into())).await?;
+                    }
+                    return Err(WatchError::AwaitFor(status));
+                }
+            };
         };
 
         let ty = self.run_type.as_ref().unwrap_or(&RunType::Cmd);
-        if *ty == RunType::Cmd {
-            let (cmd, args) =
-                self.cmd
-                    .split(' ')
-                    .fold(("", Vec::<&str>::new()), |(mut cmd, mut args), item| {
-                        if cmd.is_empty() {
-                            cmd = item;
+        match ty {
+            RunType::KubectlLogs if self.selector.is_none() => {
+                return Err(WatchError::MissingField("kubectl-logs", "selector"))
+            }
+            RunType::Script if self.file.is_none() => {
+                return Err(WatchError::MissingField("script", "file"))
+            }
+            RunType::Npm if self.script.is_none() => {
+                return Err(WatchError::MissingField("npm", "script"))
+            }
+            _ => (),
+        }
+
+        let mut history = RunHistory::default();
+        let mut next_start: Option<Instant> = None;
+        let mut stream_ended: Option<Instant> = None;
+        // instance started by blue-green restart which has taken over from the previous one
+        let mut handed_over: Option<Instance> = None;
+        loop {
+            if let (Some(next_start), None) = (next_start, &handed_over) {
+                tokio::select! {
+                    _ = time::sleep_until(next_start) => (),
+                    _ = shutdown.changed() => (),
+                    _ = restart.recv() => (),
+                }
+            }
+            if handed_over.is_none() && *shutdown.borrow() != Shutdown::Running {
+                break;
+            }
+            let started = Instant::now();
+            // restart requested while the process was not running is already served
+            while restart.try_recv().is_ok() {}
+
+            let mut instance = match handed_over.take() {
+                Some(instance) => instance,
+                None => {
+                    let since = stream_ended.map(|ended| ended.elapsed());
+                    self.start(
+                        tx.clone(),
+                        stdin.as_ref(),
+                        inherit_stdin,
+                        since,
+                        exports,
+                        &shutdown,
+                    )
+                    .await?
+                }
+            };
+            tx.send(Message::Event(Event::Started {
+                title: self.title.clone(),
+                pid: instance.pid,
+            }))
+            .await?;
+            if self.echo_cmd {
+                tx.send(Message::Line(Line::new(
+                    &self.title,
+                    Stream::Notice,
+                    format!("$ {}", self.echo()),
+                )))
+                .await?;
+            }
+
+            let mut replacement = None;
+            let mut exit = loop {
+                tokio::select! {
+                    exit = &mut instance.exit => break exit,
+                    Some(()) = restart.recv(),
+                        if replacement.is_none() && handed_over.is_none() => {
+                        if self.restart_strategy == RestartStrategy::BlueGreen {
+                            let new = self
+                                .replace(&tx, stdin.as_ref(), inherit_stdin, exports, &shutdown)
+                                .await?;
+                            replacement = Some(new);
                         } else {
-                            args.push(item)
+                            let _ = instance.restart.try_send(());
                         }
+                    }
+                    exit = Replacement::ready(&mut replacement) => {
+                        let Some(mut new) = replacement.take() else {
+                            continue;
+                        };
+                        match exit {
+                            None => {
+                                let _ = instance.restart.try_send(());
+                                handed_over = Some(new.instance);
+                            }
+                            Some(exit) => self.discard(&mut new, exit?, &tx).await?,
+                        }
+                    }
+                }
+            };
+            // new instance takes over from the one exiting before it was replaced, unless stopping
+            if let Some(new) = replacement {
+                match exit {
+                    Ok(ProcessExit::Exited(status) | ProcessExit::Restart(status, _)) => {
+                        exit = Ok(ProcessExit::Restart(status, RestartReason::Manual));
+                        handed_over = Some(new.instance);
+                    }
+                    _ => {
+                        let mut new = new.instance;
+                        let _ = (&mut new.exit).await;
+                        new.finish().await;
+                    }
+                }
+            }
+            instance.finish().await;
+            // temporary directory is shared with the instance taking over
+            if handed_over.is_none() {
+                WatchProcess::remove_tmpdir(instance.tmpdir).await;
+            }
+            let exit = exit?;
+            let status = match exit {
+                ProcessExit::Exited(status)
+                | ProcessExit::Restart(status, _)
+                | ProcessExit::Stopped(status) => status,
+            };
+            history.push(status.code());
+            tx.send(Message::Event(Event::Exited {
+                title: self.title.clone(),
+                code: status.code(),
+                success: status.success(),
+                ended_by: match exit {
+                    ProcessExit::Exited(_) => None,
+                    ProcessExit::Restart(..) => Some(EndedBy::Restart),
+                    ProcessExit::Stopped(_) => Some(EndedBy::Stop),
+                },
+            }))
+            .await?;
+            if !matches!(exit, ProcessExit::Stopped(_)) {
+                self.run_exit_action(&status, &tx).await?;
+            }
+            // instance taking over is awaited next, also when it is stopping with this one
+            if handed_over.is_some() {
+                if !matches!(exit, ProcessExit::Stopped(_)) {
+                    let flap_score = history.flap_score();
+                    tx.send(Message::Line(Line::new(
+                        &self.title,
+                        Stream::Notice,
+                        format!("replaced by new instance (flap score: {flap_score})"),
+                    )))
+                    .await?;
+                    tx.send(Message::Event(Event::Restarted {
+                        title: self.title.clone(),
+                        reason: RestartReason::Manual.as_str().to_string(),
+                        flap_score,
+                    }))
+                    .await?;
+                }
+                continue;
+            }
 
-                        (cmd, args)
-                    });
+            let (cooldown, reason, notice) = match (&exit, ty) {
+                (ProcessExit::Restart(_, reason), _) => (
+                    match reason {
+                        RestartReason::Manual => Duration::ZERO,
+                        _ => self
+                            .restart_on
+                            .as_ref()
+                            .map(|restart_on| restart_on.cooldown)
+                            .unwrap_or_else(default_cooldown),
+                    },
+                    reason.as_str(),
+                    match reason {
+                        RestartReason::RestartOn => {
+                            "restarting, output matched restart_on pattern".to_string()
+                        }
+                        RestartReason::Watchdog => format!(
+                            "restarting, no output for {}",
+                            humantime::format_duration(self.no_output_for())
+                        ),
+                        RestartReason::Manual => {
+                            "restarting, requested with key or signal".to_string()
+                        }
+                    },
+                ),
+                // pods matching the selector were replaced or kubectl lost the connection
+                (ProcessExit::Exited(_), RunType::KubectlLogs) => {
+                    stream_ended = Some(Instant::now());
+                    (
+                        KUBECTL_RECONNECT,
+                        "stream_ended",
+                        "pod log stream ended, reconnecting".to_string(),
+                    )
+                }
+                (ProcessExit::Exited(status), ..) => return Ok(Some(*status)),
+                _ => break,
+            };
 
-            let child = Command::new(cmd)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .args(args.iter())
-                .envs(&self.env)
-                .spawn()
-                .map_err(WatchError::IoChildProcess)?;
+            let flap_score = history.flap_score();
+            let backoff = history.backoff(cooldown);
+            next_start = Some(started + backoff);
+            tx.send(Message::Line(Line::new(
+                &self.title,
+                Stream::Notice,
+                format!(
+                    "{notice} (flap score: {flap_score}, backoff: {})",
+                    humantime::format_duration(backoff)
+                ),
+            )))
+            .await?;
+            tx.send(Message::Event(Event::Restarted {
+                title: self.title.clone(),
+                reason: reason.to_string(),
+                flap_score,
+            }))
+            .await?;
+        }
 
-            self.execute_and_await(child, tx, &self.title).await?
-        } else {
-            let child = Command::new("bash")
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .arg("-c")
-                .arg(&self.cmd)
-                .envs(&self.env)
-                .spawn()
-                .map_err(WatchError::IoChildProcess)?;
+        Ok(None)
+    }
 
-            self.execute_and_await(child, tx, &self.title).await?
+    /// Start a run of the process writing its output to `tx`. Pod logs of `type: kubectl-logs`
+    /// are continued from `since` ago.
+    async fn start<'a>(
+        &'a self,
+        tx: Sender<Message>,
+        stdin: Option<&broadcast::Receiver<String>>,
+        inherit_stdin: bool,
+        since: Option<Duration>,
+        exports: &Exports,
+        shutdown: &watch::Receiver<Shutdown>,
+    ) -> Result<Instance<'a>, WatchError> {
+        let tmpdir = self.isolated_tmpdir();
+        if let Some(tmpdir) = &tmpdir {
+            tokio::fs::create_dir_all(tmpdir)
+                .await
+                .map_err(WatchError::TmpDir)?;
+        }
+        let child_stdin = match (stdin, &self.stdin) {
+            (Some(_), StdinMode::Broadcast) => Stdio::piped(),
+            (Some(_), StdinMode::Ignore) => Stdio::null(),
+            (None, _) if inherit_stdin => Stdio::inherit(),
+            (None, _) => Stdio::null(),
+        };
+        let mut command = self.command(child_stdin, since);
+        // env of the process takes precedence over the exports of its dependencies
+        command.envs(
+            exports
+                .of(&self.depends_on)
+                .into_iter()
+                .filter(|(name, _)| !self.env.contains_key(name)),
+        );
+        let child = match command.spawn() {
+            Ok(child) => child,
+            Err(error) => {
+                WatchProcess::remove_tmpdir(tmpdir).await;
+                return Err(WatchError::IoChildProcess(error));
+            }
         };
 
+        let pid = child.id();
+        let stdin = stdin.map(broadcast::Receiver::resubscribe);
+        let mut shutdown = shutdown.clone();
+        let (restart, mut restart_rx) = mpsc::channel(1);
+        let exit = Box::pin(async move {
+            self.execute_and_await(child, tx, stdin, true, Some(&mut restart_rx), &mut shutdown)
+                .await
+        });
+
+        Ok(Instance {
+            pid,
+            tmpdir,
+            exit,
+            restart,
+            forward: None,
+        })
+    }
+
+    /// Start a new instance of the process for blue-green restart. It is ready once it has
+    /// published the variables it exports, apart from the ones published by the running
+    /// instance.
+    async fn replace<'a>(
+        &'a self,
+        tx: &Sender<Message>,
+        stdin: Option<&broadcast::Receiver<String>>,
+        inherit_stdin: bool,
+        exports: &Exports,
+        shutdown: &watch::Receiver<Shutdown>,
+    ) -> Result<Replacement<'a>, WatchError> {
+        tx.send(Message::Line(Line::new(
+            &self.title,
+            Stream::Notice,
+            "restarting, new instance replaces this one once it is ready",
+        )))
+        .await?;
+        let (output_tx, mut output) = mpsc::channel::<Message>(16);
+        let mut instance = self
+            .start(output_tx, stdin, inherit_stdin, None, exports, shutdown)
+            .await?;
+
+        let instance_exports = Exports::new(std::slice::from_ref(self));
+        let readiness = Readiness::new(std::slice::from_ref(&self.title), &instance_exports);
+        readiness.record(&Event::Started {
+            title: self.title.clone(),
+            pid: instance.pid,
+        });
+        let tx = tx.clone();
+        instance.forward = Some(tokio::spawn(async move {
+            while let Some(message) = output.recv().await {
+                for line in message.lines() {
+                    instance_exports.capture(line);
+                }
+                if tx.send(message).await.is_err() {
+                    break;
+                }
+            }
+        }));
+        let shutdown = shutdown.clone();
+        let ready = Box::pin(async move {
+            // stopping instance is awaited to exit instead
+            if readiness
+                .wait(&self.title, ProcessState::Ready, shutdown)
+                .await
+                != Ok(true)
+            {
+                std::future::pending().await
+            }
+        });
+
+        Ok(Replacement { instance, ready })
+    }
+
+    /// Keep the running instance as the new instance of blue-green restart exited before it was
+    /// ready.
+    async fn discard(
+        &self,
+        replacement: &mut Replacement<'_>,
+        exit: ProcessExit,
+        tx: &Sender<Message>,
+    ) -> Result<(), WatchError> {
+        replacement.instance.finish().await;
+        if let ProcessExit::Exited(status) | ProcessExit::Restart(status, _) = exit {
+            tx.send(Message::Line(Line::new(
+                &self.title,
+                Stream::Notice,
+                format!("new instance exited with {status} before it was ready, keeping this one"),
+            )))
+            .await?;
+        }
+
         Ok(())
     }
 
+    /// Command running the process. Pod logs of `type: kubectl-logs` are continued from `since`
+    /// ago when the stream is reconnected.
+    fn command(&self, stdin: Stdio, since: Option<Duration>) -> Command {
+        let mut command = match self.run_type.as_ref().unwrap_or(&RunType::Cmd) {
+            RunType::Cmd => {
+                let (cmd, args) = self.cmd_args();
+                let mut command = Command::new(cmd);
+                command.args(args.iter());
+                command
+            }
+            RunType::Shell => shell::command(&self.cmd),
+            RunType::KubectlLogs => {
+                let mut command = Command::new("kubectl");
+                command.args(self.kubectl_logs_args());
+                if let Some(since) = since {
+                    command.arg(format!("--since={}s", since.as_secs() + 1));
+                }
+                command
+            }
+            RunType::Script => {
+                let (program, args) = self.script_args();
+                let mut command = Command::new(program);
+                command.args(args);
+                command
+            }
+            RunType::Npm => {
+                let (manager, args) = self.npm_args();
+                let mut command = Command::new(manager);
+                command.args(args);
+                command
+            }
+            RunType::Make => {
+                let mut command = Command::new("make");
+                command.args(self.make_args());
+                command
+            }
+        };
+
+        command
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .envs(self.envs());
+        if self.foreground {
+            command
+                .stdin(Stdio::inherit())
+                .stdout(Stdio::inherit())
+                .stderr(Stdio::inherit());
+        }
+        #[cfg(unix)]
+        if self.stop_cmd.is_some() {
+            // keep signals of the terminal from reaching the process, it is stopped with stop_cmd
+            command.process_group(0);
+        }
+        #[cfg(unix)]
+        if self.stderr == StderrMode::Merge && !self.foreground {
+            command.stderr(Stdio::inherit());
+            // SAFETY: dup2 is async signal safe
+            unsafe {
+                command.pre_exec(
+                    || match libc::dup2(libc::STDOUT_FILENO, libc::STDERR_FILENO) {
+                        -1 => Err(std::io::Error::last_os_error()),
+                        _ => Ok(()),
+                    },
+                )
+            };
+        }
+        #[cfg(target_os = "linux")]
+        if !self.cpu_affinity.is_empty() {
+            let cpus = self.cpu_affinity.clone();
+            // SAFETY: sched_setaffinity is async signal safe and only touches the cpu set
+            unsafe { command.pre_exec(move || set_cpu_affinity(&cpus)) };
+        }
+
+        command
+    }
+
+    /// Arguments of `kubectl` following logs of all containers of pods matching the selector.
+    pub fn kubectl_logs_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "logs".to_string(),
+            "--follow".to_string(),
+            "--prefix".to_string(),
+            "--all-containers".to_string(),
+            "--ignore-errors".to_string(),
+            format!(
+                "--selector={}",
+                self.selector.as_deref().unwrap_or_default()
+            ),
+        ];
+        if let Some(context) = &self.context {
+            args.push(format!("--context={context}"));
+        }
+        if let Some(namespace) = &self.namespace {
+            args.push(format!("--namespace={namespace}"));
+        }
+
+        args
+    }
+
+    /// Origin shown before the title, the `context` or `namespace` of `type: kubectl-logs` unless
+    /// given. Empty origin hides it.
+    pub fn origin(&self) -> Option<&str> {
+        let derived = match self.run_type {
+            Some(RunType::KubectlLogs) => self.context.as_ref().or(self.namespace.as_ref()),
+            _ => None,
+        };
+        self.origin
+            .as_ref()
+            .or(derived)
+            .map(String::as_str)
+            .filter(|origin| !origin.is_empty())
+    }
+
+    /// File name of the program the process runs, or name of the package script.
+    fn program_name(&self) -> String {
+        let program = match self.run_type.as_ref().unwrap_or(&RunType::Cmd) {
+            RunType::Cmd => self.cmd_args().0,
+            RunType::Shell => self.cmd.split_whitespace().next().unwrap_or_default(),
+            RunType::KubectlLogs => "kubectl",
+            RunType::Script => self
+                .file
+                .as_deref()
+                .and_then(Path::to_str)
+                .unwrap_or_default(),
+            RunType::Npm => self.script.as_deref().unwrap_or_default(),
+            RunType::Make => self.target.as_deref().unwrap_or("make"),
+        };
+
+        Path::new(program)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "process".to_string())
+    }
+
+    /// Command of the process as it is run. Variables of shell scripts are expanded with the env
+    /// of the process, and values of resolved secrets are masked.
+    fn echo(&self) -> String {
+        match self.run_type.as_ref().unwrap_or(&RunType::Cmd) {
+            RunType::Cmd => self.cmd.clone(),
+            RunType::Shell => {
+                let pattern =
+                    Regex::new(r"\$\{(\w+)\}|\$(\w+)").expect("variable pattern must be valid");
+                let envs = self.envs();
+                pattern
+                    .replace_all(&self.cmd, |captures: &regex::Captures| {
+                        let name = captures
+                            .get(1)
+                            .or_else(|| captures.get(2))
+                            .map(|name| name.as_str())
+                            .unwrap_or_default();
+                        match self.env.get(name) {
+                            Some(EnvValue::Resolved(_)) => "***".to_string(),
+                            _ => envs
+                                .get(name)
+                                .cloned()
+                                .or_else(|| env::var(name).ok())
+                                .unwrap_or_else(|| captures[0].to_string()),
+                        }
+                    })
+                    .into_owned()
+            }
+            RunType::KubectlLogs => format!("kubectl {}", self.kubectl_logs_args().join(" ")),
+            RunType::Script => {
+                let (program, args) = self.script_args();
+                format!("{program} {}", args.join(" "))
+            }
+            RunType::Npm => {
+                let (manager, args) = self.npm_args();
+                format!("{manager} {}", args.join(" "))
+            }
+            RunType::Make => format!("make {}", self.make_args().join(" ")),
+        }
+    }
+
+    /// Temporary directory of `tmpdir: isolated` given to the process in `TMPDIR`.
+    fn isolated_tmpdir(&self) -> Option<PathBuf> {
+        let tmpdir = self.env.get("TMPDIR").and_then(EnvValue::as_str)?;
+        (self.tmpdir == TmpDirMode::Isolated).then(|| PathBuf::from(tmpdir))
+    }
+
+    async fn remove_tmpdir(tmpdir: Option<PathBuf>) {
+        if let Some(tmpdir) = tmpdir {
+            // files left open by descendants of the process are removed on a best effort basis
+            let _ = tokio::fs::remove_dir_all(tmpdir).await;
+        }
+    }
+
+    /// Arguments of `make` building the target of `type: make` in the directory of the makefile.
+    pub fn make_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(directory) = &self.directory {
+            args.push("-C".to_string());
+            args.push(directory.to_string_lossy().to_string());
+        }
+        args.extend(self.target.iter().cloned());
+        args.extend(self.args.iter().cloned());
+
+        args
+    }
+
+    /// Directory make of `type: make` is run in, resolved like make resolves it so it can be
+    /// compared with the directories make enters.
+    fn make_directory(&self) -> PathBuf {
+        let current_dir = env::current_dir().unwrap_or_default();
+        let directory = match &self.directory {
+            Some(directory) => current_dir.join(directory),
+            None => current_dir,
+        };
+
+        directory.canonicalize().unwrap_or(directory)
+    }
+
+    /// Package manager and arguments running the package script of `type: npm`. Package manager
+    /// is detected by the lockfile found in the current directory or its ancestors.
+    pub fn npm_args(&self) -> (&'static str, Vec<String>) {
+        let current_dir = env::current_dir().unwrap_or_default();
+        let manager = current_dir
+            .ancestors()
+            .find_map(|dir| {
+                NPM_LOCKFILES
+                    .iter()
+                    .find(|(lockfile, _)| dir.join(lockfile).is_file())
+            })
+            .map_or("npm", |(_, manager)| manager);
+
+        let mut args = vec!["run".to_string(), self.script.clone().unwrap_or_default()];
+        if !self.args.is_empty() {
+            // npm passes arguments after `--` to the script, yarn and pnpm pass them as is
+            if manager == "npm" {
+                args.push("--".to_string());
+            }
+            args.extend(self.args.iter().cloned());
+        }
+
+        (manager, args)
+    }
+
+    /// Program and arguments running the script file of `type: script`. Script without execute
+    /// permission is run with the interpreter of its shebang.
+    pub fn script_args(&self) -> (String, Vec<String>) {
+        let file = self.file.clone().unwrap_or_default();
+        let file_arg = file.to_string_lossy().to_string();
+        let interpreter = (!is_executable(&file))
+            .then(|| std::fs::read_to_string(&file).ok())
+            .flatten()
+            .and_then(|script| {
+                let shebang = script
+                    .lines()
+                    .next()?
+                    .strip_prefix("#!")?
+                    .trim()
+                    .to_string();
+                let mut parts = shebang.split_whitespace().map(str::to_string);
+                Some((parts.next()?, parts.collect::<Vec<_>>()))
+            });
+
+        match interpreter {
+            Some((interpreter, mut args)) => {
+                args.push(file_arg);
+                args.extend(self.args.iter().cloned());
+                (interpreter, args)
+            }
+            // relative path must contain a separator to be run instead of looked up from PATH
+            None if file.is_relative() && file.components().count() == 1 => {
+                (format!("./{file_arg}"), self.args.clone())
+            }
+            None => (file_arg, self.args.clone()),
+        }
+    }
+
+    /// Split `cmd` of type `cmd` to the program and its arguments.
+    pub fn cmd_args(&self) -> (&str, Vec<&str>) {
+        self.cmd
+            .split(' ')
+            .fold(("", Vec::<&str>::new()), |(mut cmd, mut args), item| {
+                if cmd.is_empty() {
+                    cmd = item;
+                } else {
+                    args.push(item)
+                }
+
+                (cmd, args)
+            })
+    }
+
+    /// Environment of the process with `path_prepend` entries added to front of the PATH.
+    /// Values not resolved with [`Config::resolve_env`] are left out.
+    pub fn envs(&self) -> HashMap<String, String> {
+        let mut envs = self
+            .env
+            .iter()
+            .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+            .collect::<HashMap<_, _>>();
+        if self.path_prepend.is_empty() {
+            return envs;
+        }
+
+        let current_dir = env::current_dir().unwrap_or_default();
+        let path = envs
+            .get("PATH")
+            .cloned()
+            .or_else(|| env::var("PATH").ok())
+            .unwrap_or_default();
+        let paths = self
+            .path_prepend
+            .iter()
+            .map(|prepend| current_dir.join(prepend))
+            .chain(env::split_paths(&path));
+
+        if let Ok(path) = env::join_paths(paths) {
+            envs.insert("PATH".to_string(), path.to_string_lossy().to_string());
+        }
+
+        envs
+    }
+
+    /// Inactivity duration of the watchdog, zero without watchdog.
+    fn no_output_for(&self) -> Duration {
+        self.watchdog
+            .as_ref()
+            .map(|watchdog| watchdog.no_output_for)
+            .unwrap_or_default()
+    }
+
     async fn listen_out<T>(
-        mut out: Lines<T>,
-        title: String,
-        color: u8,
-        sender: Sender<String>,
+        mut out: LineReader<T>,
+        line: impl Fn(String) -> Line,
+        sender: Sender<Message>,
+        restart_on: Option<(&Regex, &Sender<RestartReason>)>,
+        output_at: &watch::Sender<Instant>,
+        transforms: &Transforms,
+        limit: Option<&OutputLimit>,
     ) -> Result<(), WatchError>
     where
-        T: Unpin + Send + AsyncBufRead + 'static,
+        T: Unpin + Send + AsyncRead + 'static,
     {
-        while let Ok(Some(line)) = out.next_line().await {
-            let title = Style::new()
-                .on(Color::Fixed(color))
-                .paint(format!("[ {title} ] "));
+        while let Ok(Some(block)) = out.next_block().await {
+            output_at.send_replace(Instant::now());
+            if let Some((_, restart)) =
+                restart_on.filter(|(pattern, _)| block.iter().any(|line| pattern.is_match(line)))
+            {
+                let _ = restart.try_send(RestartReason::RestartOn);
+            }
 
-            sender
-                .send(format!("{title} {line}\n"))
-                .await
-                .map_err(WatchError::SendError)?
+            let mut lines = block
+                .into_iter()
+                .map(|text| line(transforms.apply(text)))
+                .collect::<Vec<_>>();
+            if let Some(Err(notice)) = limit.map(|limit| limit.count(&lines)) {
+                if let Some(notice) = notice {
+                    let notice = Line::new(&lines[0].title, Stream::Notice, notice);
+                    sender.send(Message::Line(notice)).await?;
+                }
+                continue;
+            }
+            let message = match lines.len() {
+                1 => Message::Line(lines.remove(0)),
+                _ => Message::Block(lines),
+            };
+            sender.send(message).await.map_err(WatchError::SendError)?
         }
 
         Ok(())
@@ -136,18 +1341,174 @@ impl WatchProcess {
     async fn execute_and_await(
         &self,
         mut child: Child,
-        sender: Sender<String>,
-        title: &str,
-    ) -> Result<ExitStatus, WatchError> {
-        let stdout = child.stdout.take().unwrap();
-        let stderr = child.stderr.take().unwrap();
-        let stdout_lines = BufReader::new(stdout).lines();
-        let stderr_lines = BufReader::new(stderr).lines();
+        sender: Sender<Message>,
+        stdin: Option<broadcast::Receiver<String>>,
+        supervise: bool,
+        mut manual_restart: Option<&mut mpsc::Receiver<()>>,
+        shutdown: &mut watch::Receiver<Shutdown>,
+    ) -> Result<ProcessExit, WatchError> {
+        let title = self.title.as_str();
+        let restart_on = self
+            .restart_on
+            .as_ref()
+            .filter(|_| supervise)
+            .map(|restart_on| &restart_on.pattern);
+        let watchdog = self.watchdog.as_ref().filter(|_| supervise);
+        let stdin_forward = match (child.stdin.take(), stdin) {
+            (Some(child_stdin), Some(stdin)) => Some(tokio::spawn(WatchProcess::forward_stdin(
+                child_stdin,
+                stdin,
+            ))),
+            _ => None,
+        };
+        let pid = child.id();
+        // stderr merged to stdout is not piped, neither is the output of foreground process
+        let stderr = child.stderr.take();
+        let capture = self.capture.as_ref().filter(|_| supervise);
+        let (stdout_lines, capture) = match child.stdout.take() {
+            Some(stdout) => {
+                let (stdout, capture) = CaptureReader::new(stdout, capture)
+                    .await
+                    .map_err(WatchError::Capture)?;
+                (
+                    Some(LineReader::new(stdout).with_multiline(self.multiline.as_ref())),
+                    capture,
+                )
+            }
+            None => (None, None),
+        };
 
-        let (out, err) = tokio::join!(
-            WatchProcess::listen_out(stdout_lines, title.to_string(), 173, sender.clone()),
-            WatchProcess::listen_out(stderr_lines, title.to_string(), 167, sender),
-        );
+        let stop_sender = sender.clone();
+        let (restart_tx, mut restart_rx) = mpsc::channel::<RestartReason>(1);
+        let limit = self
+            .max_output
+            .filter(|_| supervise)
+            .map(|max| OutputLimit {
+                max,
+                action: self.max_output_action,
+                written: AtomicU64::new(0),
+                exceeded: Notify::new(),
+            });
+        let (output_at_tx, output_at) = watch::channel(Instant::now());
+        let mut transforms = Transforms::new(&self.transforms);
+        if self.run_type == Some(RunType::Make) {
+            transforms.prepend(Box::new(MakeDirectory::new(self.make_directory())));
+        }
+        let stdout_sender = sender.clone();
+        // boxed to be dropped with the capture reader once the output has ended
+        let mut readers = Box::pin(async {
+            tokio::join!(
+                async {
+                    match stdout_lines {
+                        Some(stdout_lines) => {
+                            WatchProcess::listen_out(
+                                stdout_lines,
+                                |text| Line::new(title, Stream::Stdout, text).with_pid(pid),
+                                stdout_sender,
+                                restart_on.map(|pattern| (pattern, &restart_tx)),
+                                &output_at_tx,
+                                &transforms,
+                                limit.as_ref(),
+                            )
+                            .await
+                        }
+                        None => Ok(()),
+                    }
+                },
+                async {
+                    match stderr {
+                        Some(stderr) => {
+                            WatchProcess::listen_out(
+                                LineReader::new(stderr).with_multiline(self.multiline.as_ref()),
+                                |text| Line::new(title, Stream::Stderr, text).with_pid(pid),
+                                sender,
+                                restart_on.map(|pattern| (pattern, &restart_tx)),
+                                &output_at_tx,
+                                &transforms,
+                                limit.as_ref(),
+                            )
+                            .await
+                        }
+                        None => Ok(()),
+                    }
+                },
+            )
+        });
+
+        let mut restart = None;
+        let mut stopped = false;
+        let mut stop_cmd = None;
+        let mut killed_at: Option<Instant> = None;
+        let (out, err) = loop {
+            tokio::select! {
+                // without output to read the foreground process is complete once it exits
+                result = &mut readers, if !self.foreground => break result,
+                _ = child.wait(), if self.foreground => break (Ok(()), Ok(())),
+                _ = OutputLimit::exceeded(limit.as_ref()), if killed_at.is_none() && !stopped => {
+                    killed_at = Some(Instant::now());
+                    self.kill(&mut child);
+                }
+                Some(reason) = restart_rx.recv(), if killed_at.is_none() && !stopped => {
+                    restart = Some(reason);
+                    killed_at = Some(Instant::now());
+                    self.kill(&mut child);
+                }
+                Some(()) = WatchProcess::restart_requested(manual_restart.as_deref_mut()),
+                    if killed_at.is_none() && !stopped => {
+                    restart = Some(RestartReason::Manual);
+                    killed_at = Some(Instant::now());
+                    self.kill(&mut child);
+                }
+                // deadline is checked again as output might have been written while sleeping
+                _ = time::sleep_until(*output_at.borrow() + self.no_output_for()),
+                    if watchdog.is_some() && killed_at.is_none() && !stopped => {
+                    let silent = output_at.borrow().elapsed();
+                    match watchdog.map(|watchdog| &watchdog.action) {
+                        Some(_) if silent < self.no_output_for() => (),
+                        Some(WatchdogAction::Restart) => {
+                            restart = Some(RestartReason::Watchdog);
+                            killed_at = Some(Instant::now());
+                            self.kill(&mut child);
+                        }
+                        _ => {
+                            output_at_tx.send_replace(Instant::now());
+                            stop_sender
+                                .send(Message::Line(Line::new(
+                                    title,
+                                    Stream::Notice,
+                                    format!(
+                                        "no output for {}, process might be hung",
+                                        humantime::format_duration(self.no_output_for())
+                                    ),
+                                )))
+                                .await?;
+                        }
+                    }
+                }
+                Ok(()) = shutdown.changed() => {
+                    let state = *shutdown.borrow_and_update();
+                    match state {
+                        Shutdown::Stop { forward } => {
+                            stopped = true;
+                            stop_cmd = self.stop(&child, forward, stop_sender.clone()).await?;
+                        }
+                        Shutdown::Kill => {
+                            stopped = true;
+                            killed_at = Some(Instant::now());
+                            self.kill(&mut child);
+                        }
+                        Shutdown::Running => (),
+                    }
+                }
+                // descendants of the killed child might still hold the output pipes open
+                _ = time::sleep_until(killed_at.unwrap_or_else(Instant::now) + KILL_OUTPUT_GRACE),
+                    if killed_at.is_some() => break (Ok(()), Ok(())),
+            }
+        };
+        drop(readers);
+        if let Some(capture) = capture {
+            capture.await?.map_err(WatchError::Capture)?;
+        }
         let child_process = tokio::spawn(async move { child.wait().await });
 
         if [out, err]
@@ -158,7 +1519,297 @@ impl WatchProcess {
             child_process.abort()
         };
 
-        child_process.await?.map_err(WatchError::IoChildProcess)
+        if let Some(stdin_forward) = stdin_forward {
+            stdin_forward.abort()
+        };
+
+        let status = child_process.await?.map_err(WatchError::IoChildProcess)?;
+        if let Some(stop_cmd) = stop_cmd {
+            stop_cmd.await??;
+        }
+
+        if stopped {
+            Ok(ProcessExit::Stopped(status))
+        } else if let Some(reason) = restart {
+            Ok(ProcessExit::Restart(status, reason))
+        } else {
+            Ok(ProcessExit::Exited(status))
+        }
+    }
+
+    /// Stop the process with `stop_cmd` if defined, otherwise forward the signal to the process.
+    async fn stop(
+        &self,
+        child: &Child,
+        forward: Option<i32>,
+        sender: Sender<Message>,
+    ) -> Result<Option<JoinHandle<Result<(), WatchError>>>, WatchError> {
+        let Some(stop_cmd) = &self.stop_cmd else {
+            #[cfg(unix)]
+            if let (Some(signal), Some(pid)) = (forward, child.id()) {
+                // SAFETY: kill only sends the signal to the process
+                unsafe { libc::kill(pid as i32, signal) };
+            }
+            #[cfg(not(unix))]
+            let _ = (child, forward);
+            return Ok(None);
+        };
+
+        sender
+            .send(Message::Line(Line::new(
+                &self.title,
+                Stream::Notice,
+                format!("stopping with stop_cmd: {stop_cmd}"),
+            )))
+            .await?;
+        let mut stop = shell::command(stop_cmd)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .envs(self.envs())
+            .spawn()
+            .map_err(WatchError::IoChildProcess)?;
+        let title = self.title.clone();
+        let transforms = Transforms::new(&self.transforms);
+
+        Ok(Some(tokio::spawn(async move {
+            WatchProcess::forward_output(&mut stop, title, sender, &transforms).await?;
+            stop.wait().await?;
+
+            Ok(())
+        })))
+    }
+
+    /// Run `on_success` or `on_failure` command of the exited process with the exit code and
+    /// signal of it in `WATCHMUX_EXIT_CODE` and `WATCHMUX_EXIT_SIGNAL`.
+    async fn run_exit_action(
+        &self,
+        status: &ExitStatus,
+        sender: &Sender<Message>,
+    ) -> Result<(), WatchError> {
+        let (name, action) = match status.success() {
+            true => ("on_success", &self.on_success),
+            false => ("on_failure", &self.on_failure),
+        };
+        let Some(action) = action else {
+            return Ok(());
+        };
+
+        sender
+            .send(Message::Line(Line::new(
+                &self.title,
+                Stream::Notice,
+                format!("running {name}: {action}"),
+            )))
+            .await?;
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(status);
+        #[cfg(not(unix))]
+        let signal: Option<i32> = None;
+        let mut command = shell::command(action)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .envs(self.envs())
+            .env(
+                "WATCHMUX_EXIT_CODE",
+                status
+                    .code()
+                    .map(|code| code.to_string())
+                    .unwrap_or_default(),
+            )
+            .env(
+                "WATCHMUX_EXIT_SIGNAL",
+                signal.map(|signal| signal.to_string()).unwrap_or_default(),
+            )
+            .spawn()
+            .map_err(WatchError::IoChildProcess)?;
+        let transforms = Transforms::new(&self.transforms);
+        WatchProcess::forward_output(
+            &mut command,
+            self.title.clone(),
+            sender.clone(),
+            &transforms,
+        )
+        .await?;
+        command.wait().await?;
+
+        Ok(())
+    }
+
+    /// Forward output of a command run for the process, such as `stop_cmd`, as output of it.
+    async fn forward_output(
+        command: &mut Child,
+        title: String,
+        sender: Sender<Message>,
+        transforms: &Transforms,
+    ) -> Result<(), WatchError> {
+        let pid = command.id();
+        let stdout = LineReader::new(command.stdout.take().unwrap());
+        let stderr = LineReader::new(command.stderr.take().unwrap());
+        let (output_at, _) = watch::channel(Instant::now());
+        let (out, err) = tokio::join!(
+            WatchProcess::listen_out(
+                stdout,
+                |text| Line::new(&title, Stream::Stdout, text).with_pid(pid),
+                sender.clone(),
+                None,
+                &output_at,
+                transforms,
+                None,
+            ),
+            WatchProcess::listen_out(
+                stderr,
+                |text| Line::new(&title, Stream::Stderr, text).with_pid(pid),
+                sender,
+                None,
+                &output_at,
+                transforms,
+                None,
+            ),
+        );
+        out?;
+        err
+    }
+
+    /// Kill the process, or the whole process group of it when it is stopped with `stop_cmd`.
+    fn kill(&self, child: &mut Child) {
+        #[cfg(unix)]
+        if let (Some(_), Some(pid)) = (&self.stop_cmd, child.id()) {
+            // SAFETY: killpg only sends the signal to the process group
+            unsafe { libc::killpg(pid as i32, libc::SIGKILL) };
+            return;
+        }
+
+        let _ = child.start_kill();
+    }
+
+    /// Wait for restart requested with a key, never completing without `restart`.
+    async fn restart_requested(restart: Option<&mut mpsc::Receiver<()>>) -> Option<()> {
+        match restart {
+            Some(restart) => restart.recv().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    async fn forward_stdin(mut child_stdin: ChildStdin, mut stdin: broadcast::Receiver<String>) {
+        loop {
+            match stdin.recv().await {
+                Ok(line) => {
+                    if child_stdin.write_all(line.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Restrict the current process to run on the cpus.
+#[cfg(target_os = "linux")]
+fn set_cpu_affinity(cpus: &[usize]) -> std::io::Result<()> {
+    // SAFETY: cpu_set_t is plain bitmask and the CPU_* macros stay within its size
+    unsafe {
+        let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+        libc::CPU_ZERO(&mut set);
+        for cpu in cpus {
+            if *cpu >= libc::CPU_SETSIZE as usize {
+                return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+            }
+            libc::CPU_SET(*cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Single run of a process awaited until it exits.
+struct Instance<'a> {
+    pid: Option<u32>,
+    tmpdir: Option<PathBuf>,
+    exit: Pin<Box<dyn Future<Output = Result<ProcessExit, WatchError>> + Send + 'a>>,
+    /// Kill the instance in order to restart it.
+    restart: mpsc::Sender<()>,
+    /// Forwarding of the output of the instance started by blue-green restart.
+    forward: Option<JoinHandle<()>>,
+}
+
+impl Instance<'_> {
+    /// Wait for the output of the exited instance to be forwarded.
+    async fn finish(&mut self) {
+        if let Some(forward) = self.forward.take() {
+            let _ = forward.await;
+        }
+    }
+}
+
+/// New instance of blue-green restart replacing the running one once it is ready.
+struct Replacement<'a> {
+    instance: Instance<'a>,
+    ready: Pin<Box<dyn Future<Output = ()> + Send + 'a>>,
+}
+
+impl Replacement<'_> {
+    /// Wait for the new instance to be ready, or to exit before that returning how it exited.
+    /// Never completes without a replacement.
+    async fn ready(
+        replacement: &mut Option<Replacement<'_>>,
+    ) -> Option<Result<ProcessExit, WatchError>> {
+        let Some(replacement) = replacement else {
+            return std::future::pending().await;
+        };
+        tokio::select! {
+            _ = &mut replacement.ready => None,
+            exit = &mut replacement.instance.exit => Some(exit),
+        }
+    }
+}
+
+/// How a single run of a process ended.
+enum ProcessExit {
+    /// Process exited by itself.
+    Exited(ExitStatus),
+    /// Process was killed in order to restart it.
+    Restart(ExitStatus, RestartReason),
+    /// Process was stopped by shutdown of watchmux.
+    Stopped(ExitStatus),
+}
+
+/// Why the process is restarted.
+#[derive(Debug, Clone, Copy)]
+enum RestartReason {
+    RestartOn,
+    Watchdog,
+    /// Restart requested with a key.
+    Manual,
+}
+
+impl RestartReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RestartReason::RestartOn => "restart_on",
+            RestartReason::Watchdog => "watchdog",
+            RestartReason::Manual => "manual",
+        }
     }
 }
 
@@ -171,10 +1822,43 @@ pub enum WatchError {
     ChildProcessExecute(#[from] JoinError),
 
     #[error("send failed to parent")]
-    SendError(#[from] SendError<String>),
+    SendError(#[from] SendError<Message>),
 
     #[error("await for failed with status: {0}, cannot proceed to run command!")]
     AwaitFor(ExitStatus),
+
+    #[error("output sink failed: {0}")]
+    Sink(io::Error),
+
+    #[error("failed to write result file: {0}")]
+    ResultFile(io::Error),
+
+    #[error("failed to create temporary directory: {0}")]
+    TmpDir(io::Error),
+
+    #[error("failed to write capture file: {0}")]
+    Capture(io::Error),
+
+    #[error("failed to allocate port: {0}")]
+    Ports(io::Error),
+
+    #[error("failed to compute run_once key of {0}: {1}")]
+    RunOnce(String, io::Error),
+
+    #[error("type {0} requires {1}")]
+    MissingField(&'static str, &'static str),
+
+    #[error("{0}")]
+    Requires(#[from] RequiresError),
+
+    #[error("{0}")]
+    Session(#[from] SessionError),
+
+    #[error("{0}")]
+    Lock(#[from] LockError),
+
+    #[error("failed to listen for ctl requests: {0}")]
+    Control(io::Error),
 }
 
 #[derive(Error, Debug)]
@@ -190,19 +1874,50 @@ pub enum ConfigError {
 
     #[error("io failed to read file from path")]
     Io(#[from] io::Error),
+
+    #[error("{0}")]
+    Extends(#[from] ExtendsError),
+
+    #[error("{0}")]
+    Matrix(#[from] MatrixError),
+
+    #[error("{0}")]
+    Remote(#[from] RemoteError),
+
+    #[error("failed to resolve env {name} of {title}: {reason}")]
+    Env {
+        title: String,
+        name: String,
+        reason: String,
+    },
+
+    #[error("only one process can have foreground, found in {0}")]
+    Foreground(String),
 }
 
 pub async fn load(path: Option<PathBuf>) -> Result<Config, ConfigError> {
-    match path {
+    let mut config = match path {
         Some(path) => {
             if path.as_path().as_os_str() == "-" {
                 read_config_file_stdin().await
+            } else if let Some(url) = path.to_str().filter(|path| remote::is_url(path)) {
+                // extends of config fetched from URL are relative to the current directory
+                parse(&remote::fetch(url).await?, &env::current_dir()?)
             } else {
                 read_config_file_path(path.as_path()).await
             }
         }
         None => read_config_from_rc_file().await,
+    }?;
+
+    for process in &mut config.processes {
+        process
+            .path_prepend
+            .extend(config.path_prepend.iter().cloned());
     }
+    config.derive_titles();
+
+    Ok(config)
 }
 
 async fn read_config_file_stdin() -> Result<Config, ConfigError> {
@@ -219,14 +1934,37 @@ async fn read_config_file_stdin() -> Result<Config, ConfigError> {
     if config.is_empty() {
         Err(ConfigError::Missing)
     } else {
-        serde_yaml::from_str(config.as_str()).map_err(ConfigError::Parse)
+        parse(&config, &env::current_dir()?)
     }
 }
 
 async fn read_config_file_path<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
     let config = fs::read_to_string(path.as_ref()).await?;
 
-    serde_yaml::from_str(config.as_str()).map_err(ConfigError::Parse)
+    parse(
+        &config,
+        path.as_ref().parent().unwrap_or_else(|| Path::new("")),
+    )
+}
+
+/// Parse config resolving `extends` of the processes relative to `dir`.
+fn parse(config: &str, dir: &Path) -> Result<Config, ConfigError> {
+    let mut config = serde_yaml::from_str(config)?;
+    extends::resolve(&mut config, dir)?;
+    matrix::expand(&mut config)?;
+
+    let config: Config = serde_yaml::from_value(config)?;
+    let foreground = config
+        .processes
+        .iter()
+        .filter(|process| process.foreground)
+        .map(|process| process.title.clone())
+        .collect::<Vec<_>>();
+    if foreground.len() > 1 {
+        return Err(ConfigError::Foreground(foreground.join(", ")));
+    }
+
+    Ok(config)
 }
 
 async fn read_config_from_rc_file() -> Result<Config, ConfigError> {
@@ -238,3 +1976,68 @@ async fn read_config_from_rc_file() -> Result<Config, ConfigError> {
         Err(_) => Err(ConfigError::NoRcFile),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn blue_green_restart_stops_running_instance_once_new_one_is_ready() {
+        let process: WatchProcess = serde_yaml::from_str(
+            r#"
+title: api
+type: shell
+restart_strategy: blue-green
+exports: [PORT]
+cmd: sleep 0.2; echo "WATCHMUX_EXPORT PORT=$$"; exec sleep 10
+"#,
+        )
+        .unwrap();
+        let exports = Exports::new(std::slice::from_ref(&process));
+        let (tx, mut rx) = mpsc::channel(16);
+        let (shutdown_tx, shutdown) = watch::channel(Shutdown::Running);
+        let (restart_tx, mut restart) = mpsc::channel(1);
+        let run = tokio::spawn(async move {
+            let run = process.run(tx, None, false, shutdown, &exports, &mut restart);
+            run.await.is_ok()
+        });
+
+        let mut order = Vec::new();
+        while let Some(message) = rx.recv().await {
+            let step = match &message {
+                Message::Event(Event::Started { .. }) => "started",
+                Message::Event(Event::Exited { .. }) => "exited",
+                Message::Event(Event::Restarted { .. }) => "restarted",
+                _ if message
+                    .lines()
+                    .iter()
+                    .any(|line| line.text.starts_with("WATCHMUX_EXPORT")) =>
+                {
+                    "ready"
+                }
+                _ => continue,
+            };
+            order.push(step);
+            match order[..] {
+                ["started", "ready"] => restart_tx.send(()).await.unwrap(),
+                [.., "restarted", "started"] => break,
+                _ => (),
+            }
+        }
+        shutdown_tx.send_replace(Shutdown::Kill);
+
+        assert_eq!(
+            order,
+            [
+                "started",
+                "ready",
+                "ready",
+                "exited",
+                "restarted",
+                "started"
+            ]
+        );
+        assert!(run.await.unwrap());
+    }
+}
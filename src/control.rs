@@ -0,0 +1,343 @@
+use std::{path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tokio::{
+    io,
+    sync::{mpsc, oneshot},
+};
+
+use crate::event::Line;
+
+/// Environment variable of the token of HTTP requests when the config does not give it.
+const TOKEN_ENV: &str = "WATCHMUX_CONTROL_TOKEN";
+/// Largest body of HTTP request read.
+const HTTP_BODY_LIMIT: usize = 1 << 20;
+
+/// Request to the running watchmux sent with `watchmux ctl`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// Attach the note to the process, shown in the status and stamped into the output.
+    Annotate { title: String, note: String },
+    /// Show the process with the new title from now on.
+    Retitle { title: String, new_title: String },
+    /// Leave output of the process out of the terminal, apart from a periodic line telling how
+    /// much of it was left out.
+    Mute { title: String },
+    /// Write output of the muted process to the terminal again.
+    Unmute { title: String },
+    /// Start the process with `autostart: false` waiting to be started.
+    Start { title: String },
+    /// Add the process, given as in the config, to the session and start it.
+    Add { process: serde_json::Value },
+    /// Mark the current moment in the output with a rule labeled with the label and the time.
+    Mark { label: Option<String> },
+    /// Reply the recent output lines matching the pattern, of the process with the title and
+    /// received within `since` when given.
+    Search {
+        pattern: String,
+        title: Option<String>,
+        #[serde(default, with = "humantime_serde")]
+        since: Option<Duration>,
+    },
+    /// Reply the summary of the processes, and the memory used by the buffered output lines with
+    /// `buffers`.
+    Status {
+        #[serde(default)]
+        buffers: bool,
+    },
+}
+
+/// Request with the channel its outcome, and the output lines replied to it, is replied to.
+pub type Control = (ControlRequest, oneshot::Sender<Result<Vec<Line>, String>>);
+
+/// Requests served over HTTP for remote tooling e.g. IDE plugins and dashboards, as `POST
+/// /control` with the request as JSON body authorized with `Authorization: Bearer {token}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HttpControl {
+    /// Address listened on e.g. `127.0.0.1:7777`.
+    pub address: String,
+    /// Token authorizing the requests, `WATCHMUX_CONTROL_TOKEN` of the environment when not
+    /// given.
+    pub token: Option<String>,
+}
+
+/// Listen for requests on the unix socket at the path, never receiving any on platforms without
+/// them, and over HTTP when it is configured.
+pub async fn listen(
+    path: &Path,
+    http: Option<&HttpControl>,
+) -> io::Result<mpsc::Receiver<Control>> {
+    let (tx, rx) = mpsc::channel(4);
+
+    if let Some(http) = http {
+        let token = http
+            .token
+            .clone()
+            .or_else(|| std::env::var(TOKEN_ENV).ok())
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| {
+                io::Error::other(format!("http_control needs token or {TOKEN_ENV} to be set"))
+            })?;
+        let listener = tokio::net::TcpListener::bind(&http.address).await?;
+        let requests = tx.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_http(stream, token.clone(), requests.clone()));
+            }
+        });
+    }
+
+    #[cfg(unix)]
+    {
+        // socket left behind by an instance of the session which did not exit cleanly
+        let _ = std::fs::remove_file(path);
+        let listener = tokio::net::UnixListener::bind(path)?;
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, tx.clone()));
+            }
+        });
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        drop(tx);
+    }
+
+    Ok(rx)
+}
+
+/// Outcome of the request given as JSON, `None` when watchmux no longer takes requests.
+async fn apply(
+    request: &str,
+    requests: &mpsc::Sender<Control>,
+) -> Option<Result<Vec<Line>, String>> {
+    let request = match serde_json::from_str::<ControlRequest>(request) {
+        Ok(request) => request,
+        Err(error) => return Some(Err(format!("invalid request: {error}"))),
+    };
+    let (reply, outcome) = oneshot::channel();
+    requests.send((request, reply)).await.ok()?;
+
+    Some(
+        outcome
+            .await
+            .unwrap_or_else(|_| Err("watchmux is stopping".to_string())),
+    )
+}
+
+/// Reply `ok` or `error: {reason}` to each request line of the connection, preceded by the
+/// output lines replied to it as JSON.
+#[cfg(unix)]
+async fn serve(stream: tokio::net::UnixStream, requests: mpsc::Sender<Control>) -> io::Result<()> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let Some(outcome) = apply(&line, &requests).await else {
+            break;
+        };
+        let reply = match outcome {
+            Ok(lines) => {
+                let mut reply = lines
+                    .iter()
+                    .map(|line| serde_json::to_string(line).unwrap_or_default() + "\n")
+                    .collect::<String>();
+                reply.push_str("ok\n");
+                reply
+            }
+            Err(reason) => format!("error: {reason}\n"),
+        };
+        writer.write_all(reply.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+/// Reply to the single HTTP request of the connection with `{"lines": [...]}` of the output lines
+/// replied to it, or `{"error": reason}`.
+async fn serve_http(
+    stream: tokio::net::TcpStream,
+    token: String,
+    requests: mpsc::Sender<Control>,
+) -> io::Result<()> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    let mut stream = BufReader::new(stream);
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    let mut length = 0;
+    let mut authorized = false;
+    loop {
+        let mut header = String::new();
+        if stream.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.trim_end().split_once(':') else {
+            continue;
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => length = value.trim().parse().unwrap_or(0),
+            "authorization" => authorized = value.trim().strip_prefix("Bearer ") == Some(&token),
+            _ => (),
+        }
+    }
+
+    let (status, body) = match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        _ if !authorized => (401, json!({ "error": "unauthorized" })),
+        ["POST", "/control"] if length <= HTTP_BODY_LIMIT => {
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await?;
+            match apply(&String::from_utf8_lossy(&body), &requests).await {
+                Some(Ok(lines)) => (200, json!({ "lines": lines })),
+                Some(Err(reason)) => (400, json!({ "error": reason })),
+                None => (503, json!({ "error": "watchmux is stopping" })),
+            }
+        }
+        ["POST", "/control"] => (413, json!({ "error": "request is too large" })),
+        _ => (
+            404,
+            json!({ "error": "not found, requests are posted to /control" }),
+        ),
+    };
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {status} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\
+        connection: close\r\n\r\n{body}",
+        reason_phrase(status),
+        body.len()
+    );
+    stream.get_mut().write_all(response.as_bytes()).await?;
+    stream.get_mut().shutdown().await
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Not Found",
+    }
+}
+
+/// Send the request to watchmux listening on the socket at the path, returning the output lines
+/// replied to it.
+#[cfg(unix)]
+pub async fn send(path: &Path, request: &ControlRequest) -> Result<Vec<Line>, ControlError> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let mut stream = tokio::net::UnixStream::connect(path)
+        .await
+        .map_err(ControlError::Connect)?;
+    let mut request = serde_json::to_string(request).map_err(io::Error::from)?;
+    request.push('\n');
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut lines = Vec::new();
+    let mut replies = BufReader::new(stream).lines();
+    while let Some(reply) = replies.next_line().await? {
+        match reply.as_str() {
+            "ok" => return Ok(lines),
+            line if line.starts_with('{') => {
+                lines.push(serde_json::from_str(line).map_err(io::Error::from)?);
+            }
+            reply => {
+                return Err(ControlError::Rejected(
+                    reply.strip_prefix("error: ").unwrap_or(reply).to_string(),
+                ))
+            }
+        }
+    }
+
+    Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+}
+
+#[cfg(not(unix))]
+pub async fn send(_: &Path, _: &ControlRequest) -> Result<Vec<Line>, ControlError> {
+    Err(ControlError::Unsupported)
+}
+
+#[derive(Error, Debug)]
+pub enum ControlError {
+    #[error("no watchmux is running in the current directory, select its session with --session")]
+    NoSession,
+
+    #[error("{0} sessions are running in the current directory, select one with --session")]
+    Sessions(usize),
+
+    #[error("failed to connect to watchmux: {0}")]
+    Connect(io::Error),
+
+    #[error("{0}")]
+    Rejected(String),
+
+    #[error("failed to send request: {0}")]
+    Io(#[from] io::Error),
+
+    #[cfg(not(unix))]
+    #[error("ctl is not supported on this platform")]
+    Unsupported,
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    /// Response of the HTTP request served with the token `secret`, replying the request with
+    /// its command.
+    async fn respond(request: &str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let (tx, mut rx) = mpsc::channel::<Control>(1);
+        tokio::spawn(async move {
+            while let Some((request, reply)) = rx.recv().await {
+                let command = serde_json::to_value(&request).unwrap()["command"].to_string();
+                let _ = reply.send(Ok(vec![Line::new(
+                    "api",
+                    crate::event::Stream::Notice,
+                    command,
+                )]));
+            }
+        });
+        tokio::spawn(serve_http(stream, "secret".to_string(), tx));
+
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    fn post(authorization: &str, body: &str) -> String {
+        format!(
+            "POST /control HTTP/1.1\r\nauthorization: {authorization}\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[tokio::test]
+    async fn http_requests_are_authorized_with_bearer_token() {
+        let response = respond(&post("Bearer secret", r#"{"command": "status"}"#)).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(r#""text":"\"status\"""#));
+
+        let response = respond(&post("Bearer guess", r#"{"command": "status"}"#)).await;
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        let response = respond(&post("Bearer secret", r#"{"command": "nope"}"#)).await;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.contains("invalid request"));
+        let response =
+            respond("GET /status HTTP/1.1\r\nauthorization: Bearer secret\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
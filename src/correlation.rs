@@ -0,0 +1,83 @@
+use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use ansi_term::{Color, Style};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::output::ColorSupport;
+
+/// Colors of correlation ids in terminals with 256 colors.
+const FIXED_COLORS: [u8; 12] = [39, 41, 75, 79, 105, 114, 141, 148, 171, 180, 208, 213];
+/// Colors of correlation ids in terminals with basic colors.
+const BASIC_COLORS: [Color; 4] = [Color::Cyan, Color::Green, Color::Blue, Color::Purple];
+
+/// Color ids correlating lines across processes e.g. trace id of a request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Correlation {
+    /// Pattern of the id with the id as its first capture group e.g. `trace_id=(\w+)`.
+    #[serde(default, with = "serde_regex")]
+    pub pattern: Option<Regex>,
+    /// Field of the id in lines written as JSON objects e.g. `trace_id`.
+    pub field: Option<String>,
+}
+
+/// Colors ids of the lines with color chosen by the id so lines sharing the id stand out.
+#[derive(Debug)]
+pub struct Correlator {
+    correlation: Correlation,
+    colors: ColorSupport,
+}
+
+impl Correlator {
+    pub fn new(correlation: &Correlation, colors: ColorSupport) -> Self {
+        Self {
+            correlation: correlation.clone(),
+            colors,
+        }
+    }
+
+    pub fn highlight<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self.id(text) {
+            Some(id) if !id.is_empty() => {
+                let style = Style::new().fg(self.color(&id)).bold();
+                text.replace(&id, &style.paint(id.as_str()).to_string())
+                    .into()
+            }
+            _ => text.into(),
+        }
+    }
+
+    fn id(&self, text: &str) -> Option<String> {
+        let matched = self
+            .correlation
+            .pattern
+            .as_ref()
+            .and_then(|pattern| pattern.captures(text)?.get(1))
+            .map(|id| id.as_str().to_string());
+
+        matched.or_else(|| {
+            let field = self.correlation.field.as_ref()?;
+            let value = serde_json::from_str::<serde_json::Value>(text).ok()?;
+            match value.get(field)? {
+                serde_json::Value::String(id) => Some(id.clone()),
+                serde_json::Value::Number(id) => Some(id.to_string()),
+                _ => None,
+            }
+        })
+    }
+
+    fn color(&self, id: &str) -> Color {
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        let hash = hasher.finish() as usize;
+
+        match self.colors {
+            ColorSupport::Fixed => Color::Fixed(FIXED_COLORS[hash % FIXED_COLORS.len()]),
+            _ => BASIC_COLORS[hash % BASIC_COLORS.len()],
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use regex::Regex;
+use tokio::sync::watch;
+
+use crate::{
+    config::WatchProcess,
+    event::{Line, Stream},
+    shutdown::{self, Shutdown},
+};
+
+/// Prefix of output lines publishing a variable to the dependents e.g. `WATCHMUX_EXPORT PORT=49152`.
+const EXPORT_PREFIX: &str = "WATCHMUX_EXPORT ";
+
+/// Dependents of processes by `depends_on` able to stop the dependents of a failed process.
+#[derive(Debug, Default)]
+pub struct Dependencies {
+    /// Shutdown channel of each process by title.
+    shutdowns: HashMap<String, watch::Sender<Shutdown>>,
+    /// Titles of processes depending on the process by title.
+    dependents: HashMap<String, Vec<String>>,
+}
+
+impl Dependencies {
+    /// Add process with its own shutdown channel.
+    pub fn add(&mut self, process: &WatchProcess, shutdown: watch::Sender<Shutdown>) {
+        self.shutdowns.insert(process.title.clone(), shutdown);
+        for dependency in &process.depends_on {
+            self.dependents
+                .entry(dependency.clone())
+                .or_default()
+                .push(process.title.clone());
+        }
+    }
+
+    /// Stop the process as if watchmux was terminated, returning `false` when there is no such
+    /// process or it is already stopping.
+    pub fn stop(&self, title: &str) -> bool {
+        self.shutdowns.get(title).is_some_and(shutdown::terminate)
+    }
+
+    /// Stop dependents of the failed process and their dependents in turn, returning
+    /// `(title, dependency)` of each process which was not already stopped.
+    pub fn block_dependents(&self, failed: &str) -> Vec<(String, String)> {
+        let mut blocked = Vec::new();
+        let mut queue = VecDeque::from([failed.to_string()]);
+
+        while let Some(dependency) = queue.pop_front() {
+            for dependent in self.dependents.get(&dependency).into_iter().flatten() {
+                let stopped = self.shutdowns.get(dependent).map(shutdown::terminate);
+
+                if stopped == Some(true) {
+                    blocked.push((dependent.clone(), dependency.clone()));
+                    queue.push_back(dependent.clone());
+                }
+            }
+        }
+
+        blocked
+    }
+}
+
+/// Variables published by the processes with `WATCHMUX_EXPORT NAME=value` output lines or
+/// output lines matching their `export_patterns`.
+#[derive(Debug, Clone)]
+pub struct Exports {
+    /// Names of variables declared with `exports` and named groups of `export_patterns` by
+    /// title of the process.
+    declared: Arc<HashMap<String, Vec<String>>>,
+    /// `export_patterns` by title of the process.
+    patterns: Arc<HashMap<String, Vec<Regex>>>,
+    /// Published variables by title of the process.
+    published: watch::Sender<HashMap<String, HashMap<String, String>>>,
+}
+
+impl Exports {
+    pub fn new(processes: &[WatchProcess]) -> Self {
+        let declared = processes
+            .iter()
+            .map(|process| {
+                let mut declared = process.exports.clone();
+                let names = process
+                    .export_patterns
+                    .iter()
+                    .flat_map(Regex::capture_names)
+                    .flatten()
+                    .map(str::to_string);
+                for name in names {
+                    if !declared.contains(&name) {
+                        declared.push(name);
+                    }
+                }
+                (process.title.clone(), declared)
+            })
+            .collect();
+        let patterns = processes
+            .iter()
+            .filter(|process| !process.export_patterns.is_empty())
+            .map(|process| (process.title.clone(), process.export_patterns.clone()))
+            .collect();
+
+        Self {
+            declared: Arc::new(declared),
+            patterns: Arc::new(patterns),
+            published: watch::Sender::new(HashMap::new()),
+        }
+    }
+
+    /// Publish the variables if the line is an export line of the process or matches its
+    /// `export_patterns`.
+    pub fn capture(&self, line: &Line) {
+        if line.stream == Stream::Notice {
+            return;
+        }
+        let mut variables = line
+            .text
+            .strip_prefix(EXPORT_PREFIX)
+            .and_then(|export| export.trim().split_once('='))
+            .filter(|(name, _)| !name.is_empty())
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .into_iter()
+            .collect::<Vec<_>>();
+        for pattern in self.patterns.get(&line.title).into_iter().flatten() {
+            let Some(captures) = pattern.captures(&line.text) else {
+                continue;
+            };
+            for name in pattern.capture_names().flatten() {
+                if let Some(value) = captures.name(name) {
+                    variables.push((name.to_string(), value.as_str().to_string()));
+                }
+            }
+        }
+        if variables.is_empty() {
+            return;
+        }
+
+        self.published.send_modify(|published| {
+            published
+                .entry(line.title.clone())
+                .or_default()
+                .extend(variables);
+        });
+    }
+
+    /// Whether the dependencies have published every variable they declare.
+    pub fn ready(&self, dependencies: &[String]) -> bool {
+        Self::published_all(&self.declared, &self.published.borrow(), dependencies)
+    }
+
+    /// Wait until the dependencies have published every variable they declare.
+    pub async fn wait(&self, dependencies: &[String]) {
+        let mut published = self.published.subscribe();
+        // sender is held by `self` so the channel cannot close while waiting
+        let _ = published
+            .wait_for(|published| Self::published_all(&self.declared, published, dependencies))
+            .await;
+    }
+
+    /// Variables published by the dependencies.
+    pub fn of(&self, dependencies: &[String]) -> HashMap<String, String> {
+        let published = self.published.borrow();
+        dependencies
+            .iter()
+            .filter_map(|dependency| published.get(dependency))
+            .flat_map(|variables| variables.clone())
+            .collect()
+    }
+
+    fn published_all(
+        declared: &HashMap<String, Vec<String>>,
+        published: &HashMap<String, HashMap<String, String>>,
+        dependencies: &[String],
+    ) -> bool {
+        dependencies.iter().all(|dependency| {
+            declared.get(dependency).into_iter().flatten().all(|name| {
+                published
+                    .get(dependency)
+                    .is_some_and(|variables| variables.contains_key(name))
+            })
+        })
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    env,
+    fmt::Display,
+    net::TcpListener,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{Config, RunType, WatchProcess},
+    lint,
+    shell::{Shell, SHELL_ENV},
+};
+
+/// File descriptors needed per process for stdin, stdout and stderr pipes.
+const FDS_PER_PROCESS: u64 = 6;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Level {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Result of single environment check.
+#[derive(Debug)]
+pub struct Check {
+    pub level: Level,
+    message: String,
+}
+
+impl Check {
+    fn new<T: Into<String>>(level: Level, message: T) -> Self {
+        Self {
+            level,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self.level {
+            Level::Ok => "ok",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        };
+        write!(f, "{level}: {}", self.message)
+    }
+}
+
+/// Check that environment is able to run processes of the config.
+pub fn doctor(config: &Config) -> Vec<Check> {
+    let mut checks = vec![check_shell(config)];
+    checks.extend(check_limits(config));
+
+    for process in &config.processes {
+        checks.push(check_command(process));
+        checks.extend(check_requires(process));
+        checks.extend(check_ports(process));
+    }
+
+    checks.extend(lint::lint(config).into_iter().map(|lint| {
+        Check::new(
+            Level::Warning,
+            format!("[ {} ] {}", lint.title, lint.message),
+        )
+    }));
+
+    checks
+}
+
+fn check_shell(config: &Config) -> Check {
+    let needs_shell = config.processes.iter().any(|process| {
+        process.run_type.as_ref() == Some(&RunType::Shell) || process.wait_for.command().is_some()
+    });
+    let path = env::var("PATH").unwrap_or_default();
+
+    match (Shell::resolve(), needs_shell) {
+        (Shell::Custom(shell), needs_shell) => match find_executable(&shell, &path) {
+            Some(found) => Check::new(
+                Level::Ok,
+                format!("{shell} of {SHELL_ENV} found at {}", found.display()),
+            ),
+            None if needs_shell => Check::new(
+                Level::Error,
+                format!("{shell} of {SHELL_ENV} not found in PATH but required by `type: shell` and `wait_for`"),
+            ),
+            None => Check::new(Level::Warning, format!("{shell} of {SHELL_ENV} not found in PATH")),
+        },
+        (Shell::Bash, _) => match find_executable("bash", &path) {
+            Some(bash) => Check::new(Level::Ok, format!("bash found at {}", bash.display())),
+            None => Check::new(Level::Warning, "bash not found in PATH"),
+        },
+        (Shell::Sh, true) => Check::new(
+            Level::Warning,
+            format!("bash not found in PATH, `type: shell` and `wait_for` are run with sh, install bash or set {SHELL_ENV}"),
+        ),
+        (Shell::Sh, false) => Check::new(Level::Warning, "bash not found in PATH"),
+    }
+}
+
+fn check_command(process: &WatchProcess) -> Check {
+    if process.run_type.as_ref() == Some(&RunType::Shell) {
+        return Check::new(
+            Level::Ok,
+            format!(
+                "[ {} ] shell script is run with {}",
+                process.title,
+                Shell::resolve().program()
+            ),
+        );
+    }
+
+    if process.run_type.as_ref() == Some(&RunType::Script) {
+        match &process.file {
+            Some(file) if !file.is_file() => {
+                return Check::new(
+                    Level::Error,
+                    format!("[ {} ] script {} not found", process.title, file.display()),
+                )
+            }
+            None => {
+                return Check::new(
+                    Level::Error,
+                    format!("[ {} ] `type: script` requires `file`", process.title),
+                )
+            }
+            Some(_) => (),
+        }
+    }
+
+    if process.run_type.as_ref() == Some(&RunType::Make) {
+        if let Some(directory) = process
+            .directory
+            .as_ref()
+            .filter(|directory| !directory.is_dir())
+        {
+            return Check::new(
+                Level::Error,
+                format!(
+                    "[ {} ] directory {} not found",
+                    process.title,
+                    directory.display()
+                ),
+            );
+        }
+    }
+
+    let script;
+    let cmd = match process.run_type.as_ref() {
+        Some(RunType::KubectlLogs) => "kubectl",
+        Some(RunType::Npm) => process.npm_args().0,
+        Some(RunType::Make) => "make",
+        Some(RunType::Script) => {
+            script = process.script_args().0;
+            &script
+        }
+        _ => process.cmd_args().0,
+    };
+    let path = process
+        .envs()
+        .remove("PATH")
+        .or_else(|| env::var("PATH").ok())
+        .unwrap_or_default();
+
+    match find_executable(cmd, &path) {
+        Some(executable) => Check::new(
+            Level::Ok,
+            format!("[ {} ] `{cmd}` found at {}", process.title, executable.display()),
+        ),
+        None => Check::new(
+            Level::Error,
+            format!(
+                "[ {} ] `{cmd}` not found in PATH, install it or add its directory to `path_prepend`",
+                process.title
+            ),
+        ),
+    }
+}
+
+/// Check program and version the process `requires`.
+fn check_requires(process: &WatchProcess) -> Option<Check> {
+    let requires = process.requires.as_ref()?;
+    let path = process
+        .envs()
+        .remove("PATH")
+        .or_else(|| env::var("PATH").ok())
+        .unwrap_or_default();
+
+    Some(match requires.check(&path) {
+        Ok(()) => Check::new(
+            Level::Ok,
+            format!(
+                "[ {} ] requires {} {}",
+                process.title,
+                requires.bin,
+                requires.version.as_deref().unwrap_or_default()
+            )
+            .trim_end(),
+        ),
+        Err(error) => Check::new(Level::Error, format!("[ {} ] {error}", process.title)),
+    })
+}
+
+/// Check ports of `*PORT` env variables of the process are free.
+fn check_ports(process: &WatchProcess) -> Vec<Check> {
+    process
+        .env
+        .iter()
+        .filter(|(name, _)| name.to_uppercase().ends_with("PORT"))
+        .filter_map(|(name, value)| Some((name, value.as_str()?.parse::<u16>().ok()?)))
+        .map(
+            |(name, port)| match TcpListener::bind(("127.0.0.1", port)) {
+                Ok(_) => Check::new(
+                    Level::Ok,
+                    format!("[ {} ] port {port} of {name} is free", process.title),
+                ),
+                Err(_) => Check::new(
+                    Level::Error,
+                    format!(
+                        "[ {} ] port {port} of {name} is already in use, stop the process using it or change {name}",
+                        process.title
+                    ),
+                ),
+            },
+        )
+        .collect()
+}
+
+#[cfg(unix)]
+fn check_limits(config: &Config) -> Vec<Check> {
+    let processes = config.processes.len() as u64;
+    let mut checks = Vec::new();
+
+    if let Some(nofile) = soft_limit(libc::RLIMIT_NOFILE) {
+        let needed = processes * FDS_PER_PROCESS;
+        checks.push(if nofile < needed {
+            Check::new(
+                Level::Error,
+                format!(
+                    "open file limit {nofile} is too low for {processes} processes, raise it with `ulimit -n {needed}`"
+                ),
+            )
+        } else {
+            Check::new(Level::Ok, format!("open file limit {nofile}"))
+        });
+    }
+
+    if let Some(nproc) = soft_limit(libc::RLIMIT_NPROC) {
+        checks.push(if nproc < processes * 2 {
+            Check::new(
+                Level::Warning,
+                format!(
+                    "process limit {nproc} might be too low for {processes} processes, raise it with `ulimit -u`"
+                ),
+            )
+        } else {
+            Check::new(Level::Ok, format!("process limit {nproc}"))
+        });
+    }
+
+    checks
+}
+
+#[cfg(not(unix))]
+fn check_limits(_: &Config) -> Vec<Check> {
+    Vec::new()
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type Resource = libc::c_int;
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // rlim_t is not u64 on every platform
+fn soft_limit(resource: Resource) -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit only writes to the provided rlimit struct
+    let result = unsafe { libc::getrlimit(resource, &mut limit) };
+
+    (result == 0 && limit.rlim_cur != libc::RLIM_INFINITY).then_some(limit.rlim_cur as u64)
+}
+
+/// Find executable from the path or from the current directory if it contains a path separator.
+pub fn find_executable(cmd: &str, path: &str) -> Option<PathBuf> {
+    if cmd.contains(std::path::MAIN_SEPARATOR) {
+        let cmd = PathBuf::from(cmd);
+        return is_executable(&cmd).then_some(cmd);
+    }
+
+    env::split_paths(path)
+        .map(|dir| dir.join(cmd))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Amount of messages buffered between processes and the output loop.
+pub const MESSAGE_BUFFER: usize = 1024;
+
+/// Whether timestamps of the records are a counter instead of the time.
+static LOGICAL_CLOCK: AtomicBool = AtomicBool::new(false);
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// Sequence number of the latest output line.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Use a counter incremented by each record as the timestamp of the records for output which is
+/// the same across runs.
+pub fn use_logical_clock() {
+    LOGICAL_CLOCK.store(true, Ordering::Relaxed);
+}
+
+/// Message sent from running processes to the output loop.
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Output line of a process.
+    Line(Line),
+    /// Output lines of a process forwarded contiguously e.g. a stack trace.
+    Block(Vec<Line>),
+    /// Lifecycle event of a process.
+    Event(Event),
+}
+
+/// Single line of output without the line ending.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event", rename = "line")]
+pub struct Line {
+    pub title: String,
+    pub stream: Stream,
+    pub text: String,
+    /// Pid of the process which wrote the line, `None` for notices.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Order the line was received in among the lines of every process, starting from 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// Microseconds since unix epoch the line was received at.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub received: Option<u128>,
+}
+
+impl Line {
+    /// Line received now. Sequence number and receive time are left out with logical clock as
+    /// they differ between runs.
+    pub fn new<T: Into<String>>(title: &str, stream: Stream, text: T) -> Self {
+        let logical = LOGICAL_CLOCK.load(Ordering::Relaxed);
+        Self {
+            title: title.to_string(),
+            stream,
+            text: text.into(),
+            pid: None,
+            seq: (!logical).then(|| SEQUENCE.fetch_add(1, Ordering::Relaxed) + 1),
+            received: (!logical).then(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_micros())
+                    .unwrap_or_default()
+            }),
+        }
+    }
+
+    /// Line written by the process of the pid, left out with logical clock.
+    pub fn with_pid(mut self, pid: Option<u32>) -> Self {
+        self.pid = pid.filter(|_| !LOGICAL_CLOCK.load(Ordering::Relaxed));
+        self
+    }
+
+    /// Serialize the line as JSON line with milliseconds since unix epoch.
+    pub fn to_json_line(&self) -> String {
+        json_line(self)
+    }
+}
+
+/// Origin of the output line.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+    /// Notice written by watchmux about the process.
+    Notice,
+}
+
+/// Lifecycle event of a process written as NDJSON to the events file.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Started {
+        title: String,
+        pid: Option<u32>,
+    },
+    Exited {
+        title: String,
+        code: Option<i32>,
+        success: bool,
+        /// Why watchmux ended the run, `None` when the process exited by itself.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        ended_by: Option<EndedBy>,
+    },
+    Restarted {
+        title: String,
+        reason: String,
+        /// Amount of unsuccessful runs within last 5 minutes.
+        flap_score: u32,
+    },
+    Failed {
+        title: String,
+        error: String,
+    },
+    /// Process was stopped or not started because a process it depends on failed.
+    Blocked {
+        title: String,
+        dependency: String,
+    },
+    /// Process is not started until the machine has the headroom of its `start_when`.
+    Deferred {
+        title: String,
+        reason: String,
+    },
+    /// Process is not run as nothing has changed since its last successful run with `run_once`,
+    /// followed by its successful exit.
+    Skipped {
+        title: String,
+    },
+}
+
+/// Reason watchmux ended a run of a process, which is not a failure of the process.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EndedBy {
+    /// Process was stopped with the others or alone e.g. with `stop_file`.
+    Stop,
+    /// Process was killed to be restarted.
+    Restart,
+}
+
+impl Event {
+    pub fn title(&self) -> &str {
+        match self {
+            Event::Started { title, .. }
+            | Event::Exited { title, .. }
+            | Event::Restarted { title, .. }
+            | Event::Failed { title, .. }
+            | Event::Blocked { title, .. }
+            | Event::Deferred { title, .. }
+            | Event::Skipped { title } => title,
+        }
+    }
+
+    pub fn title_mut(&mut self) -> &mut String {
+        match self {
+            Event::Started { title, .. }
+            | Event::Exited { title, .. }
+            | Event::Restarted { title, .. }
+            | Event::Failed { title, .. }
+            | Event::Blocked { title, .. }
+            | Event::Deferred { title, .. }
+            | Event::Skipped { title } => title,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Record<'a, T> {
+    timestamp: u128,
+    #[serde(flatten)]
+    record: &'a T,
+}
+
+impl Message {
+    /// Output lines of the message, none for an event.
+    pub fn lines(&self) -> &[Line] {
+        match self {
+            Message::Line(line) => std::slice::from_ref(line),
+            Message::Block(lines) => lines,
+            Message::Event(_) => &[],
+        }
+    }
+
+    /// Serialize the message as single JSON line with milliseconds since unix epoch.
+    pub fn to_json_line(&self) -> String {
+        match self {
+            Message::Line(line) => json_line(line),
+            Message::Block(lines) => lines.iter().map(json_line).collect(),
+            Message::Event(event) => json_line(event),
+        }
+    }
+}
+
+fn json_line<T: Serialize>(record: &T) -> String {
+    let timestamp = if LOGICAL_CLOCK.load(Ordering::Relaxed) {
+        TICKS.fetch_add(1, Ordering::Relaxed) as u128 + 1
+    } else {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default()
+    };
+    let mut line = serde_json::to_string(&Record { timestamp, record }).unwrap_or_default();
+    line.push('\n');
+
+    line
+}
@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use clap::ArgEnum;
+use serde_json::{json, Value};
+use thiserror::Error;
+use tokio::{fs, io};
+
+use crate::config::{Config, RunType};
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum ExportTarget {
+    /// VS Code `tasks.json`.
+    Vscode,
+}
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("io failed to write export: {0:?}")]
+    Io(#[from] io::Error),
+
+    #[error("failed to serialize export: {0:?}")]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// Export config to the target format and write it to output or stdout if output is not provided.
+pub async fn export(
+    config: &Config,
+    config_path: Option<PathBuf>,
+    target: ExportTarget,
+    output: Option<PathBuf>,
+) -> Result<(), ExportError> {
+    let exported = match target {
+        ExportTarget::Vscode => vscode_tasks(config, config_path),
+    };
+    let mut exported = serde_json::to_string_pretty(&exported)?;
+    exported.push('\n');
+
+    match output {
+        Some(output) => Ok(fs::write(output, exported).await?),
+        None => {
+            print!("{exported}");
+            Ok(())
+        }
+    }
+}
+
+fn vscode_tasks(config: &Config, config_path: Option<PathBuf>) -> Value {
+    let mut tasks = Vec::new();
+
+    for process in &config.processes {
+        let options = json!({
+            "env": process.envs(),
+            "shell": { "executable": "bash", "args": ["-c"] },
+        });
+        let mut task = match process.run_type.as_ref().unwrap_or(&RunType::Cmd) {
+            RunType::Cmd => {
+                let (cmd, args) = process.cmd_args();
+                json!({
+                    "label": process.title,
+                    "type": "process",
+                    "command": cmd,
+                    "args": args,
+                })
+            }
+            RunType::Shell => json!({
+                "label": process.title,
+                "type": "shell",
+                "command": process.cmd,
+            }),
+            RunType::KubectlLogs => json!({
+                "label": process.title,
+                "type": "process",
+                "command": "kubectl",
+                "args": process.kubectl_logs_args(),
+            }),
+            RunType::Script => {
+                let (program, args) = process.script_args();
+                json!({
+                    "label": process.title,
+                    "type": "process",
+                    "command": program,
+                    "args": args,
+                })
+            }
+            RunType::Npm => {
+                let (program, args) = process.npm_args();
+                json!({
+                    "label": process.title,
+                    "type": "process",
+                    "command": program,
+                    "args": args,
+                })
+            }
+            RunType::Make => json!({
+                "label": process.title,
+                "type": "process",
+                "command": "make",
+                "args": process.make_args(),
+            }),
+        };
+        task["options"] = options.clone();
+        task["isBackground"] = json!(true);
+        task["problemMatcher"] = json!([]);
+
+        if let Some(wait_for) = process.wait_for.command() {
+            let wait_for_label = format!("{} (wait_for)", process.title);
+            task["dependsOn"] = json!([wait_for_label]);
+            tasks.push(json!({
+                "label": wait_for_label,
+                "type": "shell",
+                "command": wait_for,
+                "options": options,
+                "problemMatcher": [],
+            }));
+        }
+
+        tasks.push(task);
+    }
+
+    let args = match config_path {
+        Some(path) if path.as_os_str() != "-" => {
+            vec!["-c".to_string(), path.to_string_lossy().to_string()]
+        }
+        _ => Vec::new(),
+    };
+    tasks.push(json!({
+        "label": "watchmux",
+        "type": "process",
+        "command": "watchmux",
+        "args": args,
+        "isBackground": true,
+        "problemMatcher": [],
+    }));
+
+    json!({
+        "version": "2.0.0",
+        "tasks": tasks,
+    })
+}
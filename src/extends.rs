@@ -0,0 +1,235 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_yaml::{Mapping, Value};
+use thiserror::Error;
+
+/// Maximum depth of processes extending processes extending others.
+const MAX_DEPTH: usize = 16;
+
+/// Replace processes of the config having `extends: { file, process }` with the process of the
+/// other config file merged with the keys of the extending process. Files are resolved
+/// relative to `dir`.
+pub fn resolve(config: &mut Value, dir: &Path) -> Result<(), ExtendsError> {
+    let Some(processes) = config
+        .get_mut("processes")
+        .and_then(Value::as_sequence_mut)
+    else {
+        return Ok(());
+    };
+
+    for process in processes {
+        resolve_process(process, dir, 0)?;
+    }
+
+    Ok(())
+}
+
+fn resolve_process(process: &mut Value, dir: &Path, depth: usize) -> Result<(), ExtendsError> {
+    let Some(extends) = process
+        .as_mapping_mut()
+        .and_then(|process| process.remove("extends"))
+    else {
+        return Ok(());
+    };
+    let file = extends
+        .get("file")
+        .and_then(Value::as_str)
+        .ok_or(ExtendsError::MissingKey("file"))?;
+    let title = extends
+        .get("process")
+        .and_then(Value::as_str)
+        .ok_or(ExtendsError::MissingKey("process"))?;
+    if depth == MAX_DEPTH {
+        return Err(ExtendsError::TooDeep(title.to_string()));
+    }
+
+    let path = dir.join(file);
+    let base: Value = serde_yaml::from_str(&fs::read_to_string(&path).map_err(|error| {
+        ExtendsError::Read {
+            path: path.clone(),
+            error,
+        }
+    })?)
+    .map_err(|error| ExtendsError::Parse {
+        path: path.clone(),
+        error,
+    })?;
+    let mut base = base
+        .get("processes")
+        .and_then(Value::as_sequence)
+        .and_then(|processes| {
+            processes
+                .iter()
+                .find(|process| process.get("title").and_then(Value::as_str) == Some(title))
+        })
+        .cloned()
+        .ok_or_else(|| ExtendsError::NoProcess {
+            path: path.clone(),
+            title: title.to_string(),
+        })?;
+    resolve_process(
+        &mut base,
+        path.parent().unwrap_or_else(|| Path::new("")),
+        depth + 1,
+    )?;
+
+    merge(&mut base, std::mem::take(process));
+    *process = base;
+
+    Ok(())
+}
+
+/// Merge keys of `overrides` to `base`, maps are merged key by key and other values replaced.
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Mapping(base), Value::Mapping(overrides)) => merge_mapping(base, overrides),
+        (base, overrides) => *base = overrides,
+    }
+}
+
+fn merge_mapping(base: &mut Mapping, overrides: Mapping) {
+    for (key, value) in overrides {
+        match base.get_mut(&key) {
+            Some(existing) => merge(existing, value),
+            None => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ExtendsError {
+    #[error("extends is missing {0}")]
+    MissingKey(&'static str),
+
+    #[error("extends of {0} is nested too deep, are processes extending each other?")]
+    TooDeep(String),
+
+    #[error("failed to read {path}: {error}")]
+    Read { path: PathBuf, error: std::io::Error },
+
+    #[error("failed to parse {path}: {error}")]
+    Parse {
+        path: PathBuf,
+        error: serde_yaml::Error,
+    },
+
+    #[error("no process {title} in {path}")]
+    NoProcess { path: PathBuf, title: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Directory with the config files by name.
+    fn config_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("watchmux-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (file, config) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, config).unwrap();
+        }
+        dir
+    }
+
+    fn resolved(dir: &Path, processes: &str) -> Result<Value, ExtendsError> {
+        let mut config: Value = serde_yaml::from_str(&format!("processes:\n{processes}")).unwrap();
+        resolve(&mut config, dir)?;
+        Ok(config["processes"][0].clone())
+    }
+
+    #[test]
+    fn keys_of_extending_process_override_base() {
+        let dir = config_dir(
+            "extends-override",
+            &[(
+                "base.yaml",
+                "
+processes:
+  - title: api
+    cmd: cargo run
+    env: { RUST_LOG: info, PORT: 8080 }
+    tags: [backend]
+",
+            )],
+        );
+        let process = resolved(
+            &dir,
+            "
+  - extends: { file: base.yaml, process: api }
+    cmd: cargo run --release
+    env: { RUST_LOG: debug }
+",
+        )
+        .unwrap();
+
+        assert_eq!(process["title"], "api");
+        assert_eq!(process["cmd"], "cargo run --release");
+        assert_eq!(process["env"]["RUST_LOG"], "debug");
+        assert_eq!(process["env"]["PORT"], 8080);
+        assert_eq!(process["tags"][0], "backend");
+        assert!(process.get("extends").is_none());
+    }
+
+    #[test]
+    fn base_extending_other_process_is_resolved_relative_to_its_file() {
+        let dir = config_dir(
+            "extends-nested",
+            &[
+                (
+                    "shared/base.yaml",
+                    "
+processes:
+  - title: api
+    extends: { file: rust.yaml, process: rust }
+    cmd: cargo run
+",
+                ),
+                (
+                    "shared/rust.yaml",
+                    "
+processes:
+  - title: rust
+    cmd: cargo build
+    env: { RUST_BACKTRACE: 1 }
+",
+                ),
+            ],
+        );
+        let process = resolved(
+            &dir,
+            "  - { extends: { file: shared/base.yaml, process: api }, title: api-dev }",
+        )
+        .unwrap();
+
+        assert_eq!(process["title"], "api-dev");
+        assert_eq!(process["cmd"], "cargo run");
+        assert_eq!(process["env"]["RUST_BACKTRACE"], 1);
+    }
+
+    #[test]
+    fn processes_extending_each_other_are_rejected() {
+        let dir = config_dir(
+            "extends-cycle",
+            &[(
+                "base.yaml",
+                "
+processes:
+  - { title: a, extends: { file: base.yaml, process: b } }
+  - { title: b, extends: { file: base.yaml, process: a } }
+",
+            )],
+        );
+        let error = resolved(&dir, "  - extends: { file: base.yaml, process: a }").unwrap_err();
+
+        assert!(matches!(error, ExtendsError::TooDeep(_)), "{error}");
+        let missing = resolved(&dir, "  - extends: { file: base.yaml, process: c }");
+        assert!(matches!(missing, Err(ExtendsError::NoProcess { .. })));
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+
+use tokio::sync::watch;
+
+use crate::{
+    event::Event,
+    shutdown::{self, Shutdown},
+};
+
+/// Runs the processes as an integration test with `watchmux test`. The test process is started
+/// once every other process has started, and the first exit of it decides the exit code of
+/// watchmux. Processes are then stopped one by one in reverse order of the config.
+pub struct Harness {
+    /// Title of the test process when the config has it.
+    test: Option<String>,
+    /// Processes other than the test process which have not started yet.
+    starting: HashSet<String>,
+    ready: watch::Sender<bool>,
+    /// Shutdown channels of the processes in order of the config.
+    shutdowns: Vec<watch::Sender<Shutdown>>,
+    /// Exit code once the test is decided.
+    pub code: Option<i32>,
+}
+
+impl Harness {
+    pub fn new(test: &str, titles: &[String]) -> Self {
+        Self {
+            test: titles
+                .iter()
+                .any(|title| title == test)
+                .then(|| test.to_string()),
+            starting: titles
+                .iter()
+                .filter(|title| *title != test)
+                .cloned()
+                .collect(),
+            ready: watch::Sender::new(false),
+            shutdowns: Vec::new(),
+            code: None,
+        }
+    }
+
+    /// Add shutdown channel of the next process of the config.
+    pub fn add(&mut self, shutdown: watch::Sender<Shutdown>) {
+        self.shutdowns.push(shutdown);
+    }
+
+    pub fn is_test(&self, title: &str) -> bool {
+        self.test.as_deref() == Some(title)
+    }
+
+    /// Readiness of the other processes the test process waits for with [`wait_ready`].
+    pub fn ready(&self) -> watch::Receiver<bool> {
+        self.ready.subscribe()
+    }
+
+    /// Track the event, returning the exit code when it decides the test. Test fails when a
+    /// process fails before the others have started, and without the test process it succeeds
+    /// once every process has started.
+    pub fn record(&mut self, event: &Event) -> Option<i32> {
+        if self.code.is_some() {
+            return None;
+        }
+
+        let code = match event {
+            // deferred processes are not waited for to start the test, skipped ones are done
+            Event::Started { title, .. }
+            | Event::Deferred { title, .. }
+            | Event::Skipped { title } => {
+                self.starting.remove(title);
+                match (self.starting.is_empty(), &self.test) {
+                    (true, Some(_)) => {
+                        self.ready.send_replace(true);
+                        None
+                    }
+                    (true, None) => Some(0),
+                    (false, _) => None,
+                }
+            }
+            Event::Exited { title, code, .. } if self.is_test(title) => Some(code.unwrap_or(1)),
+            Event::Exited {
+                success: false,
+                ended_by: None,
+                title,
+                ..
+            }
+            | Event::Failed { title, .. }
+            | Event::Blocked { title, .. }
+                if self.is_test(title) || self.starting.contains(title) =>
+            {
+                Some(1)
+            }
+            _ => None,
+        };
+        self.code = code;
+
+        code
+    }
+
+    /// Stop the processes one by one in reverse order of the config, waiting for each to exit
+    /// before stopping the previous one.
+    pub fn teardown(&self) {
+        let shutdowns = self.shutdowns.clone();
+        tokio::spawn(async move {
+            for shutdown in shutdowns.iter().rev() {
+                shutdown::terminate(shutdown);
+                shutdown.closed().await;
+            }
+        });
+    }
+}
+
+/// Wait until every other process has started. Returns `false` when the test process is stopped
+/// before that.
+pub async fn wait_ready(
+    mut ready: watch::Receiver<bool>,
+    mut shutdown: watch::Receiver<Shutdown>,
+) -> bool {
+    tokio::select! {
+        _ = ready.wait_for(|ready| *ready) => true,
+        _ = shutdown.wait_for(|state| *state != Shutdown::Running) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn harness(test: &str) -> Harness {
+        Harness::new(test, &["db".to_string(), "test".to_string()])
+    }
+
+    fn started(title: &str) -> Event {
+        Event::Started {
+            title: title.to_string(),
+            pid: None,
+        }
+    }
+
+    fn exited(title: &str, code: i32) -> Event {
+        Event::Exited {
+            title: title.to_string(),
+            code: Some(code),
+            success: code == 0,
+            ended_by: None,
+        }
+    }
+
+    #[test]
+    fn test_process_is_ready_once_others_have_started() {
+        let mut harness = harness("test");
+        let ready = harness.ready();
+
+        assert_eq!(harness.record(&started("db")), None);
+        assert!(*ready.borrow());
+        assert_eq!(harness.record(&started("test")), None);
+        assert_eq!(harness.record(&exited("test", 3)), Some(3));
+        assert_eq!(harness.record(&exited("test", 0)), None);
+        assert_eq!(harness.code, Some(3));
+    }
+
+    #[test]
+    fn failure_before_start_fails_the_test() {
+        let mut harness = harness("test");
+
+        assert_eq!(harness.record(&exited("db", 1)), Some(1));
+        assert!(!*harness.ready().borrow());
+    }
+
+    #[test]
+    fn failure_after_start_is_left_to_the_test_process() {
+        let mut harness = harness("test");
+
+        harness.record(&started("db"));
+        assert_eq!(harness.record(&exited("db", 1)), None);
+    }
+
+    #[test]
+    fn without_test_process_test_passes_once_every_process_has_started() {
+        let mut harness = harness("missing");
+
+        assert_eq!(harness.record(&started("db")), None);
+        assert_eq!(harness.record(&started("test")), Some(0));
+    }
+
+    #[test]
+    fn skipped_run_once_process_has_started() {
+        let mut harness = harness("test");
+
+        let skipped = Event::Skipped {
+            title: "db".to_string(),
+        };
+        assert_eq!(harness.record(&skipped), None);
+        assert_eq!(harness.record(&exited("db", 0)), None);
+        assert!(*harness.ready().borrow());
+    }
+
+    #[test]
+    fn process_blocked_by_failed_phase_fails_the_test() {
+        let mut harness = harness("test");
+
+        let blocked = Event::Blocked {
+            title: "db".to_string(),
+            dependency: "migrate".to_string(),
+        };
+        assert_eq!(harness.record(&blocked), Some(1));
+    }
+}
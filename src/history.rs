@@ -0,0 +1,52 @@
+use std::{collections::VecDeque, time::Duration};
+
+use tokio::time::Instant;
+
+/// Amount of runs kept in the history.
+const HISTORY_SIZE: usize = 20;
+/// Time window unsuccessful runs are counted to the flap score.
+const FLAP_WINDOW: Duration = Duration::from_secs(5 * 60);
+/// Upper limit of the backoff between restarts.
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Ended run of a process.
+#[derive(Debug)]
+struct Run {
+    ended: Instant,
+    code: Option<i32>,
+}
+
+/// Rolling history of exit codes of a process used to detect flapping processes.
+#[derive(Debug, Default)]
+pub struct RunHistory {
+    runs: VecDeque<Run>,
+}
+
+impl RunHistory {
+    pub fn push(&mut self, code: Option<i32>) {
+        if self.runs.len() == HISTORY_SIZE {
+            self.runs.pop_front();
+        }
+        self.runs.push_back(Run {
+            ended: Instant::now(),
+            code,
+        });
+    }
+
+    /// Amount of unsuccessful runs within the flap window.
+    pub fn flap_score(&self) -> u32 {
+        self.runs
+            .iter()
+            .filter(|run| run.ended.elapsed() <= FLAP_WINDOW && run.code != Some(0))
+            .count() as u32
+    }
+
+    /// Exponentially grow the cooldown by the flap score.
+    pub fn backoff(&self, cooldown: Duration) -> Duration {
+        let exponent = self.flap_score().saturating_sub(1).min(16);
+
+        cooldown
+            .saturating_mul(2_u32.pow(exponent))
+            .min(MAX_BACKOFF.max(cooldown))
+    }
+}
@@ -0,0 +1,57 @@
+use std::{borrow::Cow, env, path::PathBuf};
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+/// Make file references of the output clickable with OSC 8 hyperlinks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hyperlinks {
+    /// Url of the link with `{path}`, `{line}` and `{column}` placeholders e.g.
+    /// `vscode://file{path}:{line}:{column}`.
+    #[serde(default = "default_url")]
+    pub url: String,
+}
+
+fn default_url() -> String {
+    "file://{path}".to_string()
+}
+
+/// Wraps `path/to/file.rs:12:5` style references of existing files to hyperlinks.
+#[derive(Debug)]
+pub struct Hyperlinker {
+    pattern: Regex,
+    url: String,
+    current_dir: PathBuf,
+}
+
+impl Hyperlinker {
+    pub fn new(hyperlinks: &Hyperlinks) -> Self {
+        Self {
+            pattern: Regex::new(r"([\w./\\-]*\w\.\w+):(\d+)(?::(\d+))?")
+                .expect("file reference pattern must be valid"),
+            url: hyperlinks.url.clone(),
+            current_dir: env::current_dir().unwrap_or_default(),
+        }
+    }
+
+    pub fn link<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.pattern.replace_all(text, |captures: &Captures| {
+            let reference = &captures[0];
+            let path = self
+                .current_dir
+                .join(&captures[1])
+                .components()
+                .collect::<PathBuf>();
+            if !path.is_file() {
+                return reference.to_string();
+            }
+
+            let url = self
+                .url
+                .replace("{path}", &path.to_string_lossy())
+                .replace("{line}", &captures[2])
+                .replace("{column}", captures.get(3).map_or("1", |column| column.as_str()));
+            format!("\x1b]8;;{url}\x1b\\{reference}\x1b]8;;\x1b\\")
+        })
+    }
+}
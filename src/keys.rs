@@ -0,0 +1,197 @@
+use std::io::{self, IsTerminal, Read};
+
+use tokio::sync::mpsc;
+
+/// Key command typed to watchmux in stream output mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// Restart process by its 1-based number.
+    Restart(usize),
+    /// Toggle muting output of the process by its 1-based number.
+    Mute(usize),
+    /// Skip retrying the quarantined process by its 1-based number.
+    Skip(usize),
+    /// Show the latest output of the process by its 1-based number.
+    Output(usize),
+    /// Mark the current moment in the output with a labeled rule.
+    Mark,
+    Quit,
+    Help,
+}
+
+/// Whether keys can be read from stdin without taking it from the processes or the terminal user.
+pub fn available() -> bool {
+    cfg!(unix) && io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Terminal of stdin switched to read keys without waiting for enter or echoing them. Previous
+/// mode is restored on drop.
+pub struct KeyMode {
+    #[cfg(unix)]
+    previous: libc::termios,
+}
+
+impl KeyMode {
+    #[cfg(unix)]
+    pub fn enable() -> io::Result<Self> {
+        // SAFETY: termios is plain data initialized by tcgetattr before it is read
+        unsafe {
+            let mut previous = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut previous) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // output processing and signals of <C-c> are left as is unlike in raw mode
+            let mut termios = previous;
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            termios.c_cc[libc::VMIN] = 1;
+            termios.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self { previous })
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn enable() -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl Drop for KeyMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: restores the termios read from the same file descriptor
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.previous);
+        }
+    }
+}
+
+/// Read keys typed to stdin for `processes` amount of processes.
+pub fn listen(processes: usize) -> mpsc::Receiver<Key> {
+    let (tx, rx) = mpsc::channel(16);
+
+    // tokio stdin cannot be cancelled and would block shutdown of the runtime
+    std::thread::spawn(move || {
+        let mut parser = KeyParser::new(processes);
+        for byte in io::stdin().lock().bytes().map_while(Result::ok) {
+            if let Some(key) = parser.feed(byte) {
+                if tx.blocking_send(key).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+/// Next key read with [`listen`], never completing without `keys`.
+pub async fn next(keys: Option<&mut mpsc::Receiver<Key>>) -> Option<Key> {
+    match keys {
+        Some(keys) => keys.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Parses `r`, `m`, `s` and `o` followed by number of the process, which is complete once no other
+/// process number starts with it or after enter.
+struct KeyParser {
+    processes: usize,
+    command: Option<u8>,
+    number: usize,
+}
+
+impl KeyParser {
+    fn new(processes: usize) -> Self {
+        Self {
+            processes,
+            command: None,
+            number: 0,
+        }
+    }
+
+    fn feed(&mut self, byte: u8) -> Option<Key> {
+        match (self.command, byte) {
+            (Some(_), b'0'..=b'9') => {
+                self.number = self.number * 10 + (byte - b'0') as usize;
+                if self.number * 10 > self.processes {
+                    return self.complete();
+                }
+                None
+            }
+            (Some(_), b'\n' | b'\r') => self.complete(),
+            (_, b'r' | b'm' | b's' | b'o') => {
+                self.command = Some(byte);
+                self.number = 0;
+                None
+            }
+            (_, b'q') => {
+                self.command = None;
+                Some(Key::Quit)
+            }
+            (_, b'-') => {
+                self.command = None;
+                Some(Key::Mark)
+            }
+            (_, b'?') => {
+                self.command = None;
+                Some(Key::Help)
+            }
+            _ => {
+                self.command = None;
+                None
+            }
+        }
+    }
+
+    fn complete(&mut self) -> Option<Key> {
+        let number = self.number;
+        match self.command.take() {
+            Some(b'r') => Some(Key::Restart(number)),
+            Some(b'm') => Some(Key::Mute(number)),
+            Some(b's') => Some(Key::Skip(number)),
+            Some(b'o') => Some(Key::Output(number)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(processes: usize, typed: &str) -> Vec<Key> {
+        let mut parser = KeyParser::new(processes);
+        typed.bytes().filter_map(|byte| parser.feed(byte)).collect()
+    }
+
+    #[test]
+    fn number_is_complete_once_no_other_process_starts_with_it() {
+        assert_eq!(keys(9, "r3"), [Key::Restart(3)]);
+        assert_eq!(keys(12, "m2"), [Key::Mute(2)]);
+        assert_eq!(keys(12, "m1"), []);
+        assert_eq!(keys(12, "m12"), [Key::Mute(12)]);
+        assert_eq!(keys(120, "o105"), [Key::Output(105)]);
+        assert_eq!(keys(12, "s1s2"), [Key::Skip(2)]);
+    }
+
+    #[test]
+    fn enter_completes_number_and_escape_cancels_it() {
+        assert_eq!(keys(12, "r1\n"), [Key::Restart(1)]);
+        assert_eq!(keys(12, "r1\r"), [Key::Restart(1)]);
+        assert_eq!(keys(12, "r1\x1b\n"), []);
+        assert_eq!(keys(12, "\n"), []);
+    }
+
+    #[test]
+    fn single_keys_are_read_and_unknown_keys_ignored() {
+        assert_eq!(keys(3, "q"), [Key::Quit]);
+        assert_eq!(keys(3, "?-"), [Key::Help, Key::Mark]);
+        assert_eq!(keys(3, "x1r2"), [Key::Restart(2)]);
+        assert_eq!(keys(3, "r-"), [Key::Mark]);
+        assert_eq!(keys(3, "rx2"), []);
+    }
+}
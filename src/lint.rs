@@ -0,0 +1,185 @@
+use std::fmt::Display;
+
+use crate::config::{Config, RunType, WatchProcess};
+
+/// Warning about suspicious construct found from the config.
+#[derive(Debug)]
+pub struct Lint {
+    pub title: String,
+    pub message: &'static str,
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "warning: [ {} ] {}", self.title, self.message)
+    }
+}
+
+const SHELL_OPERATORS: [&str; 4] = ["&&", "||", "|", ";"];
+
+/// Check config for constructs that are likely not doing what was intended.
+pub fn lint(config: &Config) -> Vec<Lint> {
+    config
+        .processes
+        .iter()
+        .flat_map(|process| {
+            [
+                shell_operator_in_cmd,
+                quotes_in_cmd,
+                wait_for_loop_without_sleep,
+                unbounded_loop_without_log,
+                env_shadows_path,
+            ]
+            .into_iter()
+            .filter_map(|check| check(process))
+            .map(|message| Lint {
+                title: process.title.clone(),
+                message,
+            })
+        })
+        .collect()
+}
+
+fn shell_operator_in_cmd(process: &WatchProcess) -> Option<&'static str> {
+    let ty = process.run_type.as_ref().unwrap_or(&RunType::Cmd);
+    let cmd = unquoted(&process.cmd);
+    let has_operator = SHELL_OPERATORS
+        .iter()
+        .any(|operator| cmd.contains(operator));
+
+    (*ty == RunType::Cmd && has_operator)
+        .then_some("cmd uses shell operators but type is `cmd`, use `type: shell` instead")
+}
+
+/// `type: cmd` is split on spaces, so quotes and backslashes are passed to the program as they are.
+fn quotes_in_cmd(process: &WatchProcess) -> Option<&'static str> {
+    let ty = process.run_type.as_ref().unwrap_or(&RunType::Cmd);
+    let has_quotes = process.cmd.contains(['\'', '"', '\\']);
+
+    (*ty == RunType::Cmd && has_quotes).then_some(
+        "cmd has quotes or backslashes but type `cmd` passes them as is, use `type: shell` instead",
+    )
+}
+
+/// Command without its quoted and escaped characters, which are arguments rather than operators.
+fn unquoted(cmd: &str) -> String {
+    let mut unquoted = String::new();
+    let mut quote = None;
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None | Some('"'), '\\') => {
+                chars.next();
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => (),
+            (None, _) => unquoted.push(c),
+        }
+    }
+
+    unquoted
+}
+
+fn wait_for_loop_without_sleep(process: &WatchProcess) -> Option<&'static str> {
+    let wait_for = process.wait_for.command()?;
+    let is_loop = ["while", "until"]
+        .iter()
+        .any(|keyword| contains_word(wait_for, keyword));
+
+    (is_loop && !contains_word(wait_for, "sleep"))
+        .then_some("wait_for loop without sleep will busy loop the CPU")
+}
+
+fn unbounded_loop_without_log(process: &WatchProcess) -> Option<&'static str> {
+    let is_shell = process.run_type.as_ref() == Some(&RunType::Shell);
+    let is_unbounded = ["while true", "while :", "while [[ true == true ]]"]
+        .iter()
+        .any(|pattern| process.cmd.contains(pattern));
+    let logs = process.log
+        && ["echo", "printf"]
+            .iter()
+            .any(|word| contains_word(&process.cmd, word));
+
+    (is_shell && is_unbounded && !logs)
+        .then_some("shell script loops forever without logging anything")
+}
+
+fn env_shadows_path(process: &WatchProcess) -> Option<&'static str> {
+    process
+        .env
+        .contains_key("PATH")
+        .then_some("env overrides PATH, commands might not be found")
+}
+
+fn contains_word(text: &str, word: &str) -> bool {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .any(|item| item == word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lints(processes: &str) -> Vec<&'static str> {
+        let config: Config = serde_yaml::from_str(&format!("processes:\n{processes}")).unwrap();
+        lint(&config).into_iter().map(|lint| lint.message).collect()
+    }
+
+    #[test]
+    fn shell_operators_without_spaces_are_found() {
+        for cmd in [
+            "make&&run",
+            "a|b",
+            "a||b",
+            "a;b",
+            "cargo build && cargo run",
+        ] {
+            assert_eq!(
+                lints(&format!("  - title: a\n    cmd: \"{cmd}\"")),
+                ["cmd uses shell operators but type is `cmd`, use `type: shell` instead"],
+                "{cmd}"
+            );
+        }
+    }
+
+    #[test]
+    fn quoted_or_escaped_shell_operators_are_arguments_passed_as_is() {
+        for cmd in [
+            r#"grep 'a|b' file"#,
+            r#"echo "a && b""#,
+            r#"find . -exec rm {} \;"#,
+        ] {
+            assert_eq!(
+                lints(&format!(
+                    "  - title: a\n    cmd: '{}'",
+                    cmd.replace('\'', "''")
+                )),
+                ["cmd has quotes or backslashes but type `cmd` passes them as is, use `type: shell` instead"],
+                "{cmd}"
+            );
+        }
+    }
+
+    #[test]
+    fn quotes_are_allowed_in_shell() {
+        assert!(lints("  - title: a\n    type: shell\n    cmd: grep 'a b' file").is_empty());
+    }
+
+    #[test]
+    fn shell_operators_are_allowed_in_shell() {
+        assert!(lints("  - title: a\n    type: shell\n    cmd: make&&run").is_empty());
+    }
+
+    #[test]
+    fn wait_for_loop_needs_sleep() {
+        let busy = "  - title: a\n    cmd: run\n    wait_for: until nc -z localhost 80; do :; done";
+        assert_eq!(
+            lints(busy),
+            ["wait_for loop without sleep will busy loop the CPU"]
+        );
+        let sleeping =
+            "  - title: a\n    cmd: run\n    wait_for: until nc -z localhost 80; do sleep 1; done";
+        assert!(lints(sleeping).is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::time::{self, Instant};
+
+use crate::remote;
+
+/// Time the replaced instance has to stop its processes and release the lock.
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Advisory lock of the config held while this instance runs it, so the same project is not run
+/// twice. The lock is released by the system when the instance exits, even when it crashes.
+pub struct ConfigLock {
+    _file: File,
+}
+
+impl ConfigLock {
+    /// Lock the config identified by `config`, stopping the instance holding the lock first with
+    /// `replace`.
+    pub async fn acquire(config: &str, replace: bool) -> Result<Self, LockError> {
+        let dir = dir();
+        fs::create_dir_all(&dir)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(format!("{}.lock", sha256(config))))?;
+
+        if !try_lock(&file)? {
+            let pid = holder(&mut file);
+            match (replace, pid) {
+                (true, Some(pid)) => {
+                    stop(pid);
+                    let deadline = Instant::now() + REPLACE_TIMEOUT;
+                    while !try_lock(&file)? {
+                        if Instant::now() >= deadline {
+                            return Err(LockError::Replace(pid));
+                        }
+                        time::sleep(Duration::from_millis(100)).await;
+                    }
+                }
+                _ => {
+                    return Err(LockError::Locked {
+                        config: config.to_string(),
+                        pid: pid
+                            .map(|pid| format!(" with pid {pid}"))
+                            .unwrap_or_default(),
+                    })
+                }
+            }
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Config identifying the project in its lock, `None` for config read from stdin.
+pub fn key(config: Option<&Path>) -> Option<String> {
+    let path = match config {
+        Some(path) if path.as_os_str() == "-" => return None,
+        Some(path) => path.to_path_buf(),
+        None => PathBuf::from(".watchmuxrc.yaml"),
+    };
+
+    Some(match path.to_str().filter(|path| remote::is_url(path)) {
+        Some(url) => url.to_string(),
+        None => path.canonicalize().unwrap_or(path).display().to_string(),
+    })
+}
+
+/// Pid of the instance holding the lock, `None` when it has not written it yet.
+fn holder(file: &mut File) -> Option<u32> {
+    let mut pid = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut pid).ok()?;
+    pid.trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: flock only locks the open file descriptor
+    match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } {
+        0 => Ok(true),
+        _ => match io::Error::last_os_error() {
+            error if error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            error => Err(error),
+        },
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock(_: &File) -> io::Result<bool> {
+    Ok(true)
+}
+
+/// Ask the instance to stop its processes gracefully as when it is terminated.
+fn stop(pid: u32) {
+    #[cfg(unix)]
+    // SAFETY: kill only sends the signal to the process
+    unsafe {
+        libc::kill(pid as i32, libc::SIGTERM);
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+}
+
+/// Directory of the locks in the runtime directory of the user.
+fn dir() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join("watchmux-locks")
+}
+
+fn sha256(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum LockError {
+    #[error("{config} is already run by other watchmux{pid}, stop it or take over with --replace")]
+    Locked { config: String, pid: String },
+
+    #[error("watchmux with pid {0} did not stop in time to be replaced")]
+    Replace(u32),
+
+    #[error("failed to lock config: {0}")]
+    Io(#[from] io::Error),
+}
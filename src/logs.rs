@@ -0,0 +1,80 @@
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use regex::Regex;
+use serde::Deserialize;
+use tokio::{
+    fs::File,
+    io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader},
+};
+
+use crate::{
+    event::{Line, Stream},
+    output::{Formatter, OutputMode, Terminal},
+};
+
+/// Record of the NDJSON file written by `json` sink.
+#[derive(Deserialize)]
+struct Record {
+    timestamp: u128,
+    event: String,
+    #[serde(default)]
+    title: String,
+    stream: Option<Stream>,
+    #[serde(default)]
+    text: String,
+}
+
+/// Lines of the recorded output to show.
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Show only lines recorded within the duration.
+    pub since: Option<Duration>,
+    pub grep: Option<Regex>,
+    /// Show only lines of the processes, all when empty.
+    pub titles: Vec<String>,
+}
+
+impl Filter {
+    fn matches(&self, record: &Record, now: u128) -> bool {
+        self.since
+            .map(|since| record.timestamp + since.as_millis() >= now)
+            .unwrap_or(true)
+            && (self.titles.is_empty() || self.titles.contains(&record.title))
+            && self
+                .grep
+                .as_ref()
+                .map(|grep| grep.is_match(&record.text))
+                .unwrap_or(true)
+    }
+}
+
+/// Write output lines recorded by `json` sink to stdout formatted as they were shown live.
+pub async fn logs(path: &Path, filter: &Filter, output: OutputMode) -> io::Result<()> {
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    let mut formatter = Formatter::new(Terminal::detect(), output);
+    let mut stdout = io::stdout();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or_default();
+
+    while let Some(line) = lines.next_line().await? {
+        // lifecycle events and lines not written by watchmux are left out
+        let Ok(record) = serde_json::from_str::<Record>(&line) else {
+            continue;
+        };
+        let Some(stream) = record.stream.filter(|_| record.event == "line") else {
+            continue;
+        };
+        if filter.matches(&record, now) {
+            let line = Line::new(&record.title, stream, record.text);
+            stdout.write_all(formatter.format(&line).as_bytes()).await?;
+        }
+    }
+    stdout.write_all(formatter.finish().as_bytes()).await?;
+
+    stdout.flush().await
+}
@@ -1,12 +1,108 @@
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    io::IsTerminal,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
-use clap::Parser;
-use config::{Config, ConfigError, WatchError};
+use added::Added;
+use alert::Alerts;
+use clap::{CommandFactory, Parser, Subcommand};
+use config::{Config, ConfigError, EnvValue, TmpDirMode, WaitFor, WatchError};
+use control::{ControlError, ControlRequest};
+use dependency::{Dependencies, Exports};
+use event::{Event, Line, Message, Stream, MESSAGE_BUFFER};
+use export::{ExportError, ExportTarget};
 use futures::future;
+use harness::Harness;
+use inquire::{Confirm, InquireError, MultiSelect};
+use keys::{Key, KeyMode};
+use lock::ConfigLock;
+use notify::Notifier;
+use output::{Formatter, OutputMode, Terminal};
+use permissions::FilePermissions;
+use phase::Phases;
+use pipe::Pipes;
+use ports::Ports;
+use progress::StartupProgress;
+use quarantine::{Decision, Quarantine, Scrollback};
+use readiness::{ProcessState, Readiness};
+use regex::Regex;
+use run_once::{Fingerprint, RunOnce};
+use search::SearchIndex;
+use service::ServiceError;
+use session::{Session, SessionInfo};
+use shutdown::Shutdown;
+use signals::ControlSignal;
+use sink::{JsonSink, SinkConfig, Sinks};
+use startup::StartupOrder;
+use stop_file::StopRequest;
+use summary::{Summary, WATCHMUX_TITLE};
+use suppress::Suppressions;
+use theme::{Theme, ThemeError};
 use thiserror::Error;
-use tokio::{io::AsyncWriteExt, sync::mpsc};
+use tokio::{
+    sync::{
+        broadcast,
+        mpsc::{self, error::TrySendError},
+        oneshot, watch,
+    },
+    time::{self, Instant},
+};
 
+mod added;
+mod alert;
+mod bench;
+mod capture;
+mod ci;
+mod completions;
 mod config;
+mod control;
+mod correlation;
+mod dependency;
+mod doctor;
+mod event;
+mod export;
+mod extends;
+mod harness;
+mod history;
+mod hyperlink;
+mod keys;
+mod lint;
+mod lock;
+mod logs;
+mod matrix;
+mod notify;
+mod output;
+mod permissions;
+mod phase;
+mod pipe;
+mod ports;
+mod progress;
+mod quarantine;
+mod reader;
+mod readiness;
+mod remote;
+mod requires;
+mod resources;
+mod run_once;
+mod schedule;
+mod search;
+mod service;
+mod session;
+mod shell;
+mod shutdown;
+mod signals;
+mod sink;
+mod size;
+mod startup;
+mod stop_file;
+mod summary;
+mod suppress;
+mod theme;
+mod transform;
 
 /// Multiplex your watch commands.
 ///
@@ -16,7 +112,7 @@ mod config;
 /// variables. Commands and shell scripts are executed in parallel and each output
 /// will be multiplexed to single stdout. Currently hard limit for concurrent
 /// processes is 1024. Program will exit when all processes complete or by pressing
-/// <C-c> to terminate program.
+/// <C-c> to stop processes gracefully, pressing <C-c> again kills them.
 ///
 /// Configuration file format is yaml listing processes to be executed:
 /// processes:
@@ -30,6 +126,7 @@ mod config;
 ///     wait_for: while [[ $status -ne 200 ]]; do $status=0; sleep 1; done
 ///
 /// * title: text shown left most of the output to distinct where the output is originated.
+///   Defaults to name of the program of `cmd`, suffixed with a number when the title is taken e.g. `cargo-2`.
 /// * cmd: the actual command or shell script to exeucte e.g `cargo run` or with type `shell`
 ///        this can multiline shell script e.g.
 ///        cmd: |
@@ -39,8 +136,191 @@ mod config;
 ///          done
 /// * type: `shell` or `cmd` which is default if not provided. `shell` for shell script which
 ///   are exeucted with `bash -c `cmd`. `cmd` is executed as is and is expected to be found from $PATH.
+///   Shell commands are run with sh when bash is not found, or with the shell of `WATCHMUX_SHELL` e.g. `WATCHMUX_SHELL=zsh`.
+///   `kubectl-logs` streams logs of the pods matching `selector` with `kubectl` instead of running `cmd`.
+///   `script` runs the script `file` directly honoring its shebang.
+///   `npm` runs the package `script` with npm, yarn or pnpm detected by the lockfile.
+///   `make` runs make with the `target` in the `directory` of the makefile.
 /// * env: map of environment variables to provided to `cmd`.
+///   Secrets can be resolved at startup from output of a command or content of a file instead of
+///   writing them to the config e.g. `API_KEY: { from: command, cmd: "op read op://dev/api/key" }`
+///   or `API_KEY: { from: file, path: .secrets/api-key }`.
+///   `WATCHMUX_TITLE`, `WATCHMUX_INSTANCE` number of the process as in keys and `WATCHMUX_SESSION_ID`
+///   unique to the run of watchmux are set for every process unless given in `env`.
 /// * wait_for: additonal command that need to complete before the `cmd` will be executed.
+///   When it fails its last lines of stderr are written again after the exit status. Instead of a command it can be
+///   a state of another process e.g. `{ process: db, state: ready }`, `ready` once the process has started after its
+///   own `wait_for` and published the variables of its `exports` and `export_patterns`, the same as for
+///   `suppress_until_ready`, and `finished` once it has exited successfully. When that process fails the process is
+///   blocked. Restarts by `restart_on` or `watchdog` are not failures.
+/// * wait_for_quiet: `true` hides output of `wait_for` unless it fails, then its last lines of output are written.
+/// * stdin: `broadcast` (default) or `ignore`. With `--broadcast-stdin` lines typed to watchmux
+///   are written to stdin of the `cmd`, `ignore` opts the process out of it.
+/// * path_prepend: list of directories added to front of `PATH` of the `cmd` e.g. `[./node_modules/.bin]`.
+///   Relative paths are resolved from current directory. Can be also set at top level of the config
+///   next to `processes` to apply for every process.
+/// * tags: list of tags of the process used to select processes with `--tags` e.g. `[backend, slow]`.
+/// * restart_on: restart the `cmd` when a line of its output matches the `pattern` regex e.g.
+///   `restart_on: { pattern: "OutOfMemoryError|EADDRINUSE", cooldown: 30s }`. `cooldown` is the
+///   minimum time between consecutive starts of the process, defaults to 10s.
+///   Cooldown doubles for each restart within 5 minutes up to 5 minutes to back off from flapping
+///   processes.
+/// * watchdog: act on a process which has not written any output for `no_output_for` e.g.
+///   `watchdog: { no_output_for: 5m, action: restart }`. `warn` (default) writes a notice of the silent
+///   process and `restart` restarts it with the backoff of `restart_on`.
+/// * aliases: top level map of alias names to watchmux arguments e.g. `dev: --tags frontend`. Alias is
+///   run as a command `watchmux dev` which expands it to the stored arguments.
+/// * wrap: top level `true` wraps lines longer than the terminal width indenting continuation
+///   lines under the text after the title prefix. Defaults to `false`.
+/// * stop_at: top level local time of the day in `HH:MM` format processes are stopped gracefully at
+///   e.g. `"18:30"`. Summary of the runs of the processes is printed after stopping.
+/// * stop_file: top level path of a kill-switch file e.g. `.watchmux-stop`. When the file appears watchmux stops
+///   gracefully, or when it contains titles of processes one per line, only those processes are stopped. The file is
+///   removed once read so it can be created again. A process stopped this way has not failed: it completes its phase
+///   and processes waiting for it to finish are started, and it does not stop the watchdog of systemd.
+/// * buffer_memory: top level memory the latest output lines kept for `ctl search` take at most, defaults to
+///   `16MiB`. When it is used up the oldest lines of the process taking the most of it are dropped first, so a chatty
+///   process does not push out the output of the others.
+/// * http_control: top level control API served over HTTP for remote tooling e.g. IDE plugins and dashboards, as
+///   `{ address: 127.0.0.1:7777, token: secret }`. The token defaults to `WATCHMUX_CONTROL_TOKEN` of the environment
+///   and one of them is required. Requests of `watchmux ctl` are posted as JSON to `/control` with
+///   `Authorization: Bearer {token}` e.g. `{"command": "status"}` or `{"command": "retitle", "title": "api",
+///   "new_title": "api v2"}`, and replied with `{"lines": [...]}` or `{"error": "..."}`.
+/// * phases: top level list of phases started one after another e.g.
+///   `[{ name: setup, processes: [migrate] }, { name: services, processes: [db, api], until: ready }]`. Processes
+///   of a phase are started once every process of the previous phase has exited successfully, or has started with
+///   `until: ready`. A titled separator is written when a phase starts and completes, and processes of later
+///   phases are not started when a phase fails. Processes in no phase are started right away.
+/// * sinks: top level list of destinations of the output by `type`, defaults to `[{ type: stdout }]`.
+///   `stdout` formatted output to the terminal, `file` plain lines appended to `path`, `json` lines and
+///   events as NDJSON appended to `path`, `syslog` lines to the local syslog with optional `ident` and
+///   `webhook` events posted as JSON to `url`. `nats` lines and events as JSON published to subject
+///   `{subject}.{title}` of the server at `address` e.g. `{ type: nats, address: "localhost:4222", subject: dev }`
+///   and `mqtt` to topic `{topic}/{title}` of the broker at `address`. Both default to `watchmux` and drop
+///   messages while the server is unreachable.
+///   `otlp` exports lines as OpenTelemetry log records and events as spans, a span covering each run of a
+///   process, with OTLP/HTTP to the collector at `endpoint` defaulting to `http://localhost:4318`. Each process is
+///   service `{title}` of `namespace`, which defaults to `watchmux`.
+///   `file` and `json` take optional `mode` in octal as text e.g. `"0600"` and `owner` as `user` or `user:group`,
+///   set when the file is opened so output with secrets is not readable by others on shared machines.
+///   They also take optional `rotate` e.g. `rotate: { max_size: 10MB, keep: 5, compress: true }` renaming the file to
+///   `{path}.1` before it would grow past `max_size`, keeping `keep` (default 5) older files up to `{path}.5`, which
+///   are compressed with gzip to `{path}.1.gz` and so on with `compress: true`.
+///   Lines as JSON have `pid` of the process, `seq` giving the order lines of every process were received in
+///   and `received` time in microseconds since unix epoch, which are left out with `--deterministic`.
+/// * hyperlinks: top level, makes `path/to/file.rs:12:5` references of existing files clickable in
+///   terminals supporting OSC 8 hyperlinks. `url` with `{path}`, `{line}` and `{column}` placeholders
+///   defaults to `file://{path}` e.g. `hyperlinks: { url: "vscode://file{path}:{line}:{column}" }`.
+/// * correlation: top level, colors ids correlating lines across processes, such as trace id of a
+///   request, with a color chosen by the id in terminals supporting colors. `pattern` captures the id in its
+///   first group and `field` reads it from lines written as JSON objects e.g.
+///   `correlation: { pattern: "trace_id=(\\w+)", field: trace_id }`.
+/// * theme: top level colors of the output, `dark` (default), `light`, `solarized`, `high-contrast` or path
+///   to YAML theme file. `--theme` overrides it. Theme file gives `stdout`, `stderr` and `notice` title prefix
+///   backgrounds and optional `prefix_text`, `stderr_text` and `origin` colors, each as `basic` color name for 8
+///   color terminals and `fixed` index of 256 color palette e.g. `stderr: { basic: red, fixed: 160 }`.
+/// * stop_cmd: command run with bash to stop the `cmd` when watchmux is terminated e.g. `docker compose down`.
+///   Process with `stop_cmd` does not receive signals of the terminal and is expected to exit after
+///   `stop_cmd`. Useful when the actual work is owned by another daemon.
+/// * selector: label selector of the pods of `type: kubectl-logs` e.g. `app=api`. Stream of the logs
+///   is reconnected when it ends e.g. on pod churn. `context` and `namespace` optionally select the
+///   kubectl context and namespace of the pods.
+/// * depends_on: list of titles of processes the process depends on e.g. `[db]`. When a dependency fails,
+///   exits unsuccessfully or fails to start, the process is stopped or not started and marked
+///   blocked instead of failing on connection errors.
+/// * cpu_affinity: list of CPUs the `cmd` is pinned to e.g. `[0, 1]` to keep heavy builds away from cores of
+///   latency sensitive processes. Only supported on Linux and ignored elsewhere.
+/// * extends: inherit process of another config file and override only the keys given next to it e.g.
+///   `extends: { file: ../base.yaml, process: api }`. Maps such as `env` are merged key by key. The
+///   file is resolved relative to the config file extending it.
+/// * echo_cmd: `true` writes the command as the first line of each run of the process. Variables of
+///   `shell` scripts are expanded with `env`, except values resolved from commands and files.
+/// * file: script file of `type: script` e.g. `./scripts/dev.sh`. Script without the execute
+///   permission is run with the interpreter of its shebang. `args` are passed to the script.
+/// * exports: list of variables the process publishes to its dependents by writing
+///   `WATCHMUX_EXPORT NAME=value` lines e.g. `WATCHMUX_EXPORT PORT=49152` for `exports: [PORT]`.
+///   Processes with the process in `depends_on` are started once all of the variables are published
+///   and get them in their env unless set in their own `env`. Restarted dependents get the latest values.
+/// * export_patterns: regexes publishing their named capture groups from output lines of the process like
+///   `exports` e.g. `export_patterns: ['url: (?P<DATABASE_URL>postgres://\S+)']` for an ephemeral database.
+/// * transforms: list of transforms applied in order to the output lines before they are written
+///   e.g. `[strip_ansi, redact: { pattern: "token=\\w+" }, replace: { from: "^DEBUG ", to: "" }]`.
+///   `strip_ansi` removes colors, `redact` replaces matches of `pattern` with `***` and `replace` replaces
+///   matches of `from` with `to` which may refer to capture groups e.g. `$1`.
+/// * script: package script of `type: npm` e.g. `dev`. Package manager is chosen by `pnpm-lock.yaml`,
+///   `yarn.lock` or `package-lock.json` in the current directory or its parents, defaulting to npm.
+///   `args` are passed to the script.
+/// * target: target of `type: make` e.g. `build`, defaults to the default target of the makefile.
+///   directory is the directory of the makefile and args are passed to make e.g. `[-j4, DEBUG=1]`.
+///   Lines of recursive make are prefixed with the directory make has entered e.g. `[lib] cc -c util.c`.
+/// * clear_on_restart: `true` writes a full width rule before the output of each restart of the process
+///   so output of the previous run, such as stale compiler errors, is easy to tell apart.
+/// * stderr: `separate` (default) or `merge`. `merge` writes stderr of the `cmd` to its stdout pipe
+///   like `2>&1` so lines of both streams are shown in the order they were written, as stdout.
+///   Only supported on unix.
+/// * pipe_to: title of the process stdout lines of the process are written to stdin of e.g. `ingest`,
+///   in addition to being shown. Lines are piped as shown after `transforms`. Stdin of the process piped
+///   to is not given lines of `--broadcast-stdin`.
+/// * alerts: lines of the process which must not be missed e.g.
+///   `alerts: [{ pattern: "panicked at", bell: true, sticky: true }]`. `bell` rings the terminal bell
+///   and `sticky` writes the line again when watchmux exits.
+/// * multiline: forward multi-line blocks e.g. stack traces without output of other processes in
+///   between, `multiline: { start_pattern: "^Traceback", continuation_pattern: "^\\s" }`. The block
+///   is complete at the first line not matching `continuation_pattern` or when no more lines arrive.
+/// * on_success: command run with bash when the process exits successfully e.g. `notify-send "build done"`.
+///   on_failure is run when it exits unsuccessfully. Exit code of the process is given in `WATCHMUX_EXIT_CODE`
+///   and the signal which terminated it in `WATCHMUX_EXIT_SIGNAL`. Not run when watchmux stops the process.
+/// * tmpdir: `shared` (default) or `isolated`. `isolated` gives each run of the process a fresh temporary
+///   directory in `TMPDIR`, `TMP` and `TEMP` under the directory of the session in the temporary
+///   directory of the system. It is removed when the run exits and the session directory when watchmux exits.
+/// * icon: emoji or nerd font glyph shown before the title e.g. `"🦀"` or `"\ue7a8"`. Icons are shown
+///   only when the locale is UTF-8 and the terminal is not the console of the Linux kernel.
+/// * origin: host, container or cluster the process runs on shown in its own color before the title e.g.
+///   `origin: prod-1` for `ssh prod-1 journalctl -f` shows `[ prod-1:api ]`, keeping mixed local and remote output
+///   apart. `type: kubectl-logs` shows its `context`, or `namespace`, unless given. `origin: ""` hides it.
+/// * matrix: expand the process to a process for each combination of the values e.g.
+///   `matrix: { service: [api, worker], mode: [debug, release] }`. `{service}` and `{mode}` in the title,
+///   `cmd`, `env` and other values of the process are replaced with the values of the combination.
+///   Values without a placeholder in the title are appended to it e.g. `build-api-debug`, and a matrix with no
+///   values is an error.
+/// * requires: program checked before the process is started e.g. `requires: { bin: node, version: ">=18" }`.
+///   `version` is compared to the first version printed by `bin --version` with comma separated `>=`, `>`,
+///   `<=`, `<` or `=` comparisons e.g. `">=18, <21"`. Version is compared only by as many components as
+///   given so `18` matches `18.2.0`. The process fails without starting when the check fails.
+/// * confirm: question asked before starting the process e.g. `"This will reset the database. Continue?"`.
+///   The process is left out unless it is confirmed. `--yes` starts it without asking.
+/// * ports: env variables given a free port at startup e.g. `ports: { API_PORT: auto }`. The port is also
+///   given to the processes depending on the process, directly or through other processes, and the allocated ports
+///   are written at startup. Ports are free when allocated but not reserved until the process binds them.
+/// * run_once: skip the process when it has exited successfully before with the same command, env and contents
+///   of `fingerprint` files e.g. `run_once: true` for migrations or code generation. `--force-run` runs it anyway.
+/// * fingerprint: files and directories whose contents are part of the cache key of `run_once`
+///   e.g. `fingerprint: [migrations, package-lock.json]`. `SIGUSR2` does not restart the process unless their
+///   contents have changed since watchmux started or since the previous `SIGUSR2`.
+/// * capture: file stdout of the process is written to as the exact bytes it was read e.g.
+///   `capture: { file: out.tar, mode: raw }`, safe for binary output of commands such as tar. The file is
+///   written again on each run of the process.
+/// * max_output: output of a single run after which further output is dropped with a notice e.g. `50MB`, `512KiB`
+///   or bytes, protecting the terminal and the sinks from runaway loggers.
+/// * max_output_action: `drop` further output, which is default, or `kill` the process once `max_output` is exceeded.
+/// * foreground: give the terminal to the process as is for REPLs and other interactive programs, output of other
+///   processes is written only to non-interactive sinks such as `file` while it runs. Only one process can have it,
+///   and keys and `--broadcast-stdin` are not available with it.
+/// * start_when: defer starting heavyweight processes until the machine has headroom, checked again every 5 seconds
+///   while the process is deferred. `free_memory` is memory available e.g. `2GB` and `load_below` the load average of the
+///   last minute e.g. `4`. Resources which cannot be read on the system do not defer the process.
+/// * restart_strategy: `recreate` (default) stops the running process before starting it again, or `blue-green`
+///   keeps it running on restarts requested with a key or signal until a new instance is ready. The new instance
+///   is ready once it has published the variables it `exports`, right away without any, and the running one is
+///   kept when the new one exits before that. Restarts of `restart_on` and `watchdog` always stop the running
+///   process first, and both instances share the `TMPDIR` of `tmpdir: isolated`.
+/// * autostart: `false` defines the process without starting it with watchmux, for occasionally needed services
+///   such as mail catchers or debug proxies. It is started with `watchmux ctl start <title>`, and until then the
+///   processes waiting for it and later phases wait as well.
+/// * suppress_until_ready: `true` holds back output of the process until it is ready and replaces it with a single
+///   `ready in Xs` line, or writes it after all when the process fails before that. Process is ready once it has
+///   started after its `wait_for` and published the variables of its `exports` and `export_patterns` e.g.
+///   `export_patterns: ['Local: (?P<URL>http\S+)']` for a dev server.
 ///
 /// EXAMPLES:
 ///
@@ -58,13 +338,284 @@ mod config;
 ///     type: shell
 /// EOF
 ///
+/// Run watchmux with config hosted by the team, pinned to its checksum with optional `#sha256=` fragment:
+/// watchmux -c 'https://example.com/team-dev.yaml#sha256=15f8233b2a0a7adb685d702fb0afd55a935d9138767810190b89a4391ddf0fee'
+///
+/// Run watchmux as systemd user service at login:
+/// watchmux -c path/to/config install-service --name my-stack
+///
+/// Run stacks of several projects as named sessions and list them with their running processes:
+/// watchmux -c proj-a/.watchmuxrc.yaml --session proj-a
+/// watchmux sessions list
+///
+/// Annotate processes of the running session or show them with other title when comparing runs of experiments:
+/// watchmux ctl annotate api "trying new flag"
+/// watchmux ctl retitle api api-new-flag
+///
+/// Silence a noisy process without stopping it, writing how many of its lines were left out every 30 seconds:
+/// watchmux ctl mute worker
+/// watchmux ctl unmute worker
+///
+/// Try one more command without bringing the stack down, added to the running session and started right away:
+/// watchmux ctl add --title bench --cmd "cargo bench" --type shell
+///
+/// Mark the moment of a deploy in the output of every sink, like the `-` key does:
+/// watchmux ctl mark deploy
+///
+/// Find which process logged an error in the latest output of the running session, and show the memory it takes:
+/// watchmux ctl search "error|panic" --since 10m
+/// watchmux ctl search timeout --title api
+/// watchmux ctl status --buffers
+///
+/// Stop watchmux already running the config and take over instead of failing to start:
+/// watchmux --replace
+///
+/// Show health of the processes running in the current directory in the shell prompt e.g. with custom module of starship:
+/// watchmux prompt-status
+///
+/// Run only backend processes which are not tagged slow:
+/// watchmux --tags 'backend,!slow'
+///
+/// Export processes as VS Code tasks:
+/// watchmux export vscode -o .vscode/tasks.json
+///
+/// Run watchmux with arguments stored to alias `dev` in the config:
+/// watchmux dev
+///
+/// Install bash completions completing titles, tags and aliases of the config in the current
+/// directory, `zsh`, `fish`, `powershell` and `elvish` are supported as well:
+/// watchmux completions bash > ~/.local/share/bash-completion/completions/watchmux
+///
+/// Check the environment before running processes:
+/// watchmux doctor
+///
+/// Show errors of the api process recorded within the last hour by `json` sink:
+/// watchmux logs session.ndjson --since 1h --title api --grep error
+///
+/// Run the stack as an integration test, running process titled `e2e` once the others have started
+/// and exiting with its exit code:
+/// watchmux test --process e2e
+///
+/// Run watchmux in GitHub Actions grouping output of each process, `buildkite`, `gitlab` and
+/// `teamcity` collapse output in the respective CI systems:
+/// watchmux --output gha
+///
 /// Run watchmux with
 #[derive(Parser, Debug)]
 #[clap(version, verbatim_doc_comment)]
+#[allow(clippy::doc_lazy_continuation, clippy::doc_overindented_list_items)]
 struct WatchMux {
-    /// Path to the config file of watchmux.
+    /// Path to the config file of watchmux, `-` for stdin or URL to fetch it from.
     #[clap(short, long, value_name = "FILE")]
     config: Option<PathBuf>,
+
+    /// Treat config lint warnings as errors and exit before running processes.
+    #[clap(long)]
+    deny_warnings: bool,
+
+    /// Forward lines written to stdin of watchmux to stdin of every process.
+    #[clap(long)]
+    broadcast_stdin: bool,
+
+    /// Run only processes having any of the comma separated tags. Tags prefixed with `!` exclude
+    /// processes having the tag e.g. `--tags backend,!slow`.
+    #[clap(long, value_name = "TAGS", use_value_delimiter = true)]
+    tags: Vec<String>,
+
+    /// Append process lifecycle events as NDJSON to the file.
+    #[clap(long, value_name = "FILE")]
+    events_file: Option<PathBuf>,
+
+    /// Write JSON summary of exit codes, durations, restarts and failures of the processes to
+    /// the file at exit.
+    #[clap(long, value_name = "FILE")]
+    result_file: Option<PathBuf>,
+
+    /// Format of the multiplexed output.
+    #[clap(long, arg_enum, value_name = "FORMAT", default_value = "stream")]
+    output: OutputMode,
+
+    /// Show time since the previous line of the same process e.g. `+1.2s`.
+    #[clap(long)]
+    timing: bool,
+
+    /// Stop processes gracefully after running for the duration e.g. `2h`.
+    #[clap(long, value_name = "DURATION", parse(try_from_str = humantime::parse_duration))]
+    max_runtime: Option<Duration>,
+
+    /// Choose processes to run from fuzzy searchable list before starting them.
+    #[clap(long)]
+    pick: bool,
+
+    /// Start processes having `confirm` without asking for confirmation.
+    #[clap(long)]
+    yes: bool,
+
+    /// Hold failed processes until they are retried with `r<number>` or skipped with
+    /// `s<number>` instead of stopping their dependents. `o<number>` shows output of the process.
+    /// Requires keys typed to the terminal.
+    #[clap(long)]
+    interactive_failures: bool,
+
+    /// Run processes having `run_once` even when nothing changed since their last successful run.
+    #[clap(long, value_name = "TITLES", use_value_delimiter = true)]
+    force_run: Vec<String>,
+
+    /// Theme of the output colors, `dark`, `light`, `solarized`, `high-contrast` or path to YAML
+    /// theme file. Overrides `theme` of the config.
+    #[clap(long, value_name = "THEME")]
+    theme: Option<String>,
+
+    /// Name of the session listed by `watchmux sessions`. Only one session with the name runs at a
+    /// time, defaults to unique id of the run.
+    #[clap(long, value_name = "NAME")]
+    session: Option<String>,
+
+    /// Stop other watchmux running the same config and take over, instead of failing to start.
+    #[clap(long)]
+    replace: bool,
+
+    /// Output without colors and timing, timestamps of JSON records as a counter and processes
+    /// started one by one in order of the config for golden file tests.
+    #[clap(long)]
+    deterministic: bool,
+
+    #[clap(subcommand)]
+    command: Option<WatchMuxCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+enum WatchMuxCommand {
+    /// Install and enable systemd user service running watchmux with the current config.
+    InstallService {
+        /// Name of the service, defaults to name of the current directory.
+        #[clap(long)]
+        name: Option<String>,
+    },
+    /// Disable and remove systemd user service installed with `install-service`.
+    UninstallService {
+        /// Name of the service, defaults to name of the current directory.
+        #[clap(long)]
+        name: Option<String>,
+    },
+    /// Export processes of the config to be run with other tools.
+    Export {
+        /// Format to export processes to.
+        #[clap(arg_enum)]
+        target: ExportTarget,
+        /// File to write the export to, defaults to stdout.
+        #[clap(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Check the environment is able to run processes of the config.
+    Doctor,
+    /// Measure throughput and latency of the output pipeline with synthetic processes.
+    Bench {
+        /// Amount of synthetic processes.
+        #[clap(long, default_value = "10")]
+        processes: usize,
+        /// Total lines per second written by the processes.
+        #[clap(long, default_value = "10000")]
+        lines_per_sec: u64,
+        /// Duration of the benchmark e.g. `30s`.
+        #[clap(long, default_value = "10s", parse(try_from_str = humantime::parse_duration))]
+        duration: Duration,
+    },
+    /// Run processes as an integration test. The test process is started once every other process
+    /// has started, then processes are stopped in reverse order of the config and watchmux exits
+    /// with the exit code of the test process.
+    Test {
+        /// Title of the test process. Without it in the config the test passes once every process
+        /// has started.
+        #[clap(long, value_name = "TITLE", default_value = "test")]
+        process: String,
+    },
+    /// Show output recorded by `json` sink formatted as it was shown live.
+    Logs {
+        /// NDJSON file of `json` sink.
+        file: PathBuf,
+        /// Show only lines recorded within the duration e.g. `1h`.
+        #[clap(long, value_name = "DURATION", parse(try_from_str = humantime::parse_duration))]
+        since: Option<Duration>,
+        /// Show only lines matching the regex.
+        #[clap(long, value_name = "PATTERN", parse(try_from_str = Regex::new))]
+        grep: Option<Regex>,
+        /// Show only lines of the comma separated titles of processes.
+        #[clap(long, value_name = "TITLES", use_value_delimiter = true)]
+        title: Vec<String>,
+    },
+    /// Write completions of the shell to stdout, completing titles, tags and aliases of the config.
+    Completions {
+        #[clap(arg_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print running and failed processes of sessions in the current directory for shell prompts
+    /// e.g. `✓4 ✗1`, nothing when no session is running. `--session` selects the session by name.
+    PromptStatus,
+    /// Manage sessions of watchmux running on the host.
+    Sessions {
+        #[clap(subcommand)]
+        command: SessionsCommand,
+    },
+    /// Control watchmux running in the current directory, `--session` selects it by name.
+    Ctl {
+        #[clap(subcommand)]
+        command: CtlCommand,
+    },
+    #[clap(external_subcommand)]
+    Alias(Vec<String>),
+}
+
+#[derive(Subcommand, Debug)]
+enum SessionsCommand {
+    /// List running sessions with their amount of running processes.
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+enum CtlCommand {
+    /// Attach a note to the process shown in the status and stamped into the output e.g.
+    /// `ctl annotate api "trying new flag"`.
+    Annotate { title: String, note: String },
+    /// Show the process with the new title from now on.
+    Retitle { title: String, new_title: String },
+    /// Leave output of the process out of the terminal without stopping it, writing how many
+    /// lines were left out every 30 seconds instead.
+    Mute { title: String },
+    /// Write output of the muted process to the terminal again.
+    Unmute { title: String },
+    /// Start the process with `autostart: false` e.g. `ctl start mailcatcher`.
+    Start { title: String },
+    /// Add a process to the running session and start it e.g.
+    /// `ctl add --title bench --cmd "cargo bench" --type shell`.
+    Add {
+        #[clap(long)]
+        title: String,
+        #[clap(long)]
+        cmd: String,
+        /// `type` of the process as in the config.
+        #[clap(long = "type", value_name = "TYPE")]
+        run_type: Option<String>,
+    },
+    /// Mark the current moment in the output of every sink with a rule labeled with the label and
+    /// the time e.g. `ctl mark deploy`.
+    Mark { label: Option<String> },
+    /// Show recent output lines matching the regex e.g. `ctl search "error|panic" --since 10m`.
+    Search {
+        pattern: String,
+        /// Show only lines of the process.
+        #[clap(long)]
+        title: Option<String>,
+        /// Show only lines written within the duration e.g. `10m`.
+        #[clap(long, value_name = "DURATION", parse(try_from_str = humantime::parse_duration))]
+        since: Option<Duration>,
+    },
+    /// Show uptime and the last exit of each process.
+    Status {
+        /// Show also memory used by the output lines kept for `ctl search`.
+        #[clap(long)]
+        buffers: bool,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -73,44 +624,1333 @@ enum WatchmuxError {
     Config(#[from] ConfigError),
     #[error("failed to run watch process: {0:?}")]
     WatchError(#[from] WatchError),
+    #[error("config has {0} lint warnings, denied by --deny-warnings")]
+    Lint(usize),
+    #[error("failed to manage service: {0:?}")]
+    Service(#[from] ServiceError),
+    #[error("failed to export config: {0:?}")]
+    Export(#[from] ExportError),
+    #[error("doctor found {0} errors")]
+    Doctor(usize),
+    #[error("unknown command or alias: {0}")]
+    UnknownCommand(String),
+    #[error("invalid alias {0}, cannot split it to arguments")]
+    InvalidAlias(String),
+    #[error("bench failed: {0}")]
+    Bench(std::io::Error),
+    #[error("pick processes failed: {0}")]
+    Pick(#[from] InquireError),
+    #[error("failed to confirm {0}: {1}, start it with --yes")]
+    Confirm(String, InquireError),
+    #[error("failed to show logs: {0}")]
+    Logs(std::io::Error),
+    #[error("failed to list sessions: {0}")]
+    Sessions(std::io::Error),
+    #[error("failed to load theme: {0}")]
+    Theme(#[from] ThemeError),
+    #[error("{0}")]
+    Control(#[from] ControlError),
+    #[error("failed to reload config: {0}")]
+    Reload(std::io::Error),
 }
 
 #[tokio::main]
 async fn main() -> Result<(), WatchmuxError> {
-    let cli = WatchMux::parse();
+    let mut cli = WatchMux::parse();
+
+    let mut loaded_config = None;
+    if let Some(WatchMuxCommand::Alias(args)) = &cli.command {
+        let config = config::load(cli.config.clone()).await?;
+        let expanded = expand_alias(&config, args)?;
+        if let Some(WatchMuxCommand::Alias(args)) = &expanded.command {
+            return Err(WatchmuxError::UnknownCommand(args[0].clone()));
+        }
+        if expanded.config == cli.config {
+            loaded_config = Some(config);
+        }
+        cli = expanded;
+    }
 
-    let config = config::load(cli.config).await?;
+    match cli.command {
+        Some(WatchMuxCommand::InstallService { name }) => {
+            return Ok(service::install(cli.config, name).await?)
+        }
+        Some(WatchMuxCommand::UninstallService { name }) => {
+            return Ok(service::uninstall(name).await?)
+        }
+        Some(WatchMuxCommand::Bench {
+            processes,
+            lines_per_sec,
+            duration,
+        }) => {
+            let report = bench::bench(processes, lines_per_sec, duration, cli.output)
+                .await
+                .map_err(WatchmuxError::Bench)?;
+            println!("{report}");
+            return Ok(());
+        }
+        Some(WatchMuxCommand::Sessions {
+            command: SessionsCommand::List,
+        }) => {
+            for session in session::list().map_err(WatchmuxError::Sessions)? {
+                println!("{}", session.describe());
+            }
+            return Ok(());
+        }
+        Some(WatchMuxCommand::PromptStatus) => {
+            let sessions =
+                selected_sessions(cli.session.as_deref()).map_err(WatchmuxError::Sessions)?;
+            if let Some(status) = session::prompt_status(&sessions, Terminal::detect().unicode) {
+                println!("{status}");
+            }
+            return Ok(());
+        }
+        Some(WatchMuxCommand::Ctl { command }) => {
+            let sessions =
+                selected_sessions(cli.session.as_deref()).map_err(WatchmuxError::Sessions)?;
+            let session = match sessions.as_slice() {
+                [session] => session,
+                [] => return Err(ControlError::NoSession.into()),
+                _ => return Err(ControlError::Sessions(sessions.len()).into()),
+            };
+            let request = match command {
+                CtlCommand::Annotate { title, note } => ControlRequest::Annotate { title, note },
+                CtlCommand::Retitle { title, new_title } => {
+                    ControlRequest::Retitle { title, new_title }
+                }
+                CtlCommand::Mute { title } => ControlRequest::Mute { title },
+                CtlCommand::Unmute { title } => ControlRequest::Unmute { title },
+                CtlCommand::Start { title } => ControlRequest::Start { title },
+                CtlCommand::Add {
+                    title,
+                    cmd,
+                    run_type,
+                } => {
+                    let mut process = serde_json::json!({ "title": title, "cmd": cmd });
+                    if let Some(run_type) = run_type {
+                        process["type"] = run_type.into();
+                    }
+                    ControlRequest::Add { process }
+                }
+                CtlCommand::Mark { label } => ControlRequest::Mark { label },
+                CtlCommand::Search {
+                    pattern,
+                    title,
+                    since,
+                } => ControlRequest::Search {
+                    pattern,
+                    title,
+                    since,
+                },
+                CtlCommand::Status { buffers } => ControlRequest::Status { buffers },
+            };
+            let lines = control::send(&session::control_path(&session.name), &request).await?;
+            let mut formatter = Formatter::new(Terminal::detect(), cli.output);
+            let output = lines
+                .iter()
+                .map(|line| formatter.format(line))
+                .collect::<String>();
+            print!("{output}{}", formatter.finish());
+            return Ok(());
+        }
+        Some(WatchMuxCommand::Completions { shell }) => {
+            // completions of the commands are written without the config when it is not found
+            let config = config::load(cli.config.clone()).await.ok();
+            completions::write(shell, WatchMux::command(), config.as_ref());
+            return Ok(());
+        }
+        Some(WatchMuxCommand::Logs {
+            file,
+            since,
+            grep,
+            title,
+        }) => {
+            let filter = logs::Filter {
+                since,
+                grep,
+                titles: title,
+            };
+            return logs::logs(&file, &filter, cli.output)
+                .await
+                .map_err(WatchmuxError::Logs);
+        }
+        _ => (),
+    };
+
+    let mut config = match loaded_config {
+        Some(config) => config,
+        None => config::load(cli.config.clone()).await?,
+    };
+    config.select_tags(&cli.tags);
+    if cli.pick {
+        pick(&mut config)?;
+    }
+
+    if let Some(WatchMuxCommand::Export { target, output }) = cli.command {
+        return Ok(export::export(&config, cli.config, target, output).await?);
+    }
+
+    if let Some(WatchMuxCommand::Doctor) = cli.command {
+        let checks = doctor::doctor(&config);
+        for check in &checks {
+            println!("{check}");
+        }
+        let errors = checks
+            .iter()
+            .filter(|check| check.level == doctor::Level::Error)
+            .count();
+        return if errors > 0 {
+            Err(WatchmuxError::Doctor(errors))
+        } else {
+            Ok(())
+        };
+    }
+
+    if let Some(warning) = shell::init() {
+        eprintln!("{warning}");
+    }
+    let lints = lint::lint(&config);
+    for lint in &lints {
+        eprintln!("{lint}");
+    }
+    if cli.deny_warnings && !lints.is_empty() {
+        return Err(WatchmuxError::Lint(lints.len()));
+    }
 
-    run(config).await.map_err(WatchmuxError::WatchError)
+    if !cli.yes {
+        confirm(&mut config)?;
+    }
+    config.resolve_env().await?;
+    let theme = match cli.theme.as_ref().or(config.theme.as_ref()) {
+        Some(theme) => theme::load(theme)?,
+        None => Theme::default(),
+    };
+    match run(config, &cli, theme).await? {
+        Outcome::Stopped(Some(code)) => std::process::exit(code),
+        Outcome::Stopped(None) => (),
+        Outcome::Reload => return Err(WatchmuxError::Reload(reload())),
+    }
+
+    Ok(())
+}
+
+/// Parse command line again with the alias, first of the `args`, replaced with the arguments
+/// it is defined with in the config.
+fn expand_alias(config: &Config, args: &[String]) -> Result<WatchMux, WatchmuxError> {
+    let (name, rest) = args
+        .split_first()
+        .expect("external subcommand must have a name");
+    let alias = config
+        .aliases
+        .get(name)
+        .ok_or_else(|| WatchmuxError::UnknownCommand(name.clone()))?;
+    let alias_args =
+        shlex::split(alias).ok_or_else(|| WatchmuxError::InvalidAlias(name.clone()))?;
+
+    let global_args = env::args().count() - args.len();
+    Ok(WatchMux::parse_from(
+        env::args()
+            .take(global_args)
+            .chain(alias_args)
+            .chain(rest.iter().cloned()),
+    ))
+}
+
+/// Retain processes picked from interactive multi-select list.
+fn pick(config: &mut Config) -> Result<(), WatchmuxError> {
+    let titles = config
+        .processes
+        .iter()
+        .map(|process| process.title.clone())
+        .collect::<Vec<_>>();
+    let picked = MultiSelect::new("processes to run:", titles)
+        .with_page_size(15)
+        .prompt()?;
+
+    config
+        .processes
+        .retain(|process| picked.contains(&process.title));
+
+    Ok(())
 }
 
-async fn run(config: Config) -> Result<(), WatchError> {
-    let (tx, mut rx) = mpsc::channel::<String>(1024);
+/// Retain processes having `confirm` only when starting them is confirmed.
+fn confirm(config: &mut Config) -> Result<(), WatchmuxError> {
+    let mut declined = HashSet::new();
+    for process in &config.processes {
+        let Some(message) = &process.confirm else {
+            continue;
+        };
+        let confirmed = Confirm::new(&format!("[ {} ] {message}", process.title))
+            .with_default(false)
+            .prompt()
+            .map_err(|error| WatchmuxError::Confirm(process.title.clone(), error))?;
+        if !confirmed {
+            declined.insert(process.title.clone());
+        }
+    }
+
+    config
+        .processes
+        .retain(|process| !declined.contains(&process.title));
+
+    Ok(())
+}
 
+/// How the run of the processes ended.
+enum Outcome {
+    /// Processes were stopped, with the exit code of `watchmux test`.
+    Stopped(Option<i32>),
+    /// Processes were stopped by `SIGHUP` to run watchmux again with the config reloaded.
+    Reload,
+}
+
+/// Run the processes until they exit or are stopped.
+async fn run(config: Config, cli: &WatchMux, theme: Theme) -> Result<Outcome, WatchError> {
+    // lock is taken before opening sinks or the terminal, which a second watchmux would clobber
+    let _lock = match lock::key(cli.config.as_deref()) {
+        Some(key) => Some(ConfigLock::acquire(&key, cli.replace).await?),
+        None => None,
+    };
+    let (tx, mut rx) = mpsc::channel::<Message>(MESSAGE_BUFFER);
+    let mut sinks = Sinks::default();
+    let sink_configs = match config.sinks.is_empty() {
+        true => &[SinkConfig::Stdout][..],
+        false => &config.sinks,
+    };
+    let icons = config
+        .processes
+        .iter()
+        .filter_map(|process| Some((process.title.clone(), process.icon.clone()?)))
+        .collect::<HashMap<_, _>>();
+    let origins = config
+        .processes
+        .iter()
+        .filter_map(|process| Some((process.title.clone(), process.origin()?.to_string())))
+        .collect::<HashMap<_, _>>();
+    let restart_rules = config
+        .processes
+        .iter()
+        .filter(|process| process.clear_on_restart)
+        .map(|process| process.title.clone())
+        .collect::<HashSet<_>>();
+    if cli.deterministic {
+        event::use_logical_clock();
+    }
+    for sink in sink_configs {
+        let terminal = match cli.deterministic {
+            true => Terminal::plain(),
+            false => Terminal::detect(),
+        };
+        let formatter = Formatter::new(terminal, cli.output)
+            .with_timing(cli.timing && !cli.deterministic)
+            .with_wrap(config.wrap)
+            .with_hyperlinks(config.hyperlinks.as_ref())
+            .with_correlation(config.correlation.as_ref())
+            .with_restart_rules(restart_rules.clone())
+            .with_icons(icons.clone())
+            .with_origins(origins.clone())
+            .with_startup_progress(StartupProgress::new(&config.processes))
+            .with_theme(theme.clone());
+        sinks.register(sink.open(formatter).await.map_err(WatchError::Sink)?);
+    }
+    if let Some(path) = &cli.events_file {
+        sinks.register(Box::new(
+            JsonSink::open(path, false, &FilePermissions::default(), None)
+                .await
+                .map_err(WatchError::Sink)?,
+        ));
+    }
+    let foreground = config
+        .processes
+        .iter()
+        .find(|process| process.foreground)
+        .map(|process| process.title.clone());
+    // stdin of the terminal belongs to the foreground process
+    let stdin = (cli.broadcast_stdin && foreground.is_none()).then(|| {
+        let (stdin_tx, _) = broadcast::channel::<String>(1024);
+        let sender = stdin_tx.clone();
+        // tokio stdin cannot be cancelled and would block shutdown of the runtime
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lines().map_while(Result::ok) {
+                let _ = sender.send(format!("{line}\n"));
+            }
+        });
+        stdin_tx
+    });
+
+    // keys are read only from a terminal not needed for stdin of the processes
+    let stdin_free = !cli.broadcast_stdin && foreground.is_none();
+    let key_mode = (cli.output == OutputMode::Stream && stdin_free && keys::available())
+        .then(|| KeyMode::enable().ok())
+        .flatten();
+    let mut keys = key_mode
+        .as_ref()
+        .map(|_| keys::listen(config.processes.len()));
+    let quarantine = (cli.interactive_failures && keys.is_some()).then(Quarantine::default);
+    let mut scrollback = quarantine.as_ref().map(|_| Scrollback::default());
+    if cli.interactive_failures && quarantine.is_none() {
+        let line = Line::new(
+            WATCHMUX_TITLE,
+            Stream::Notice,
+            "--interactive-failures needs keys typed to the terminal, failed processes are not quarantined",
+        );
+        sinks
+            .write(&Message::Line(line))
+            .await
+            .map_err(WatchError::Sink)?;
+    }
+
+    let mut schedule = schedule::deadline(cli.max_runtime, config.stop_at);
+    let (shutdown_tx, shutdown) = shutdown::listen();
+    let mut dependencies = Dependencies::default();
+    let exports = Exports::new(&config.processes);
+    let pipes = Pipes::new(&config.processes);
+    let mut alerts = Alerts::new(&config.processes);
+    let titles = config
+        .processes
+        .iter()
+        .map(|process| process.title.clone())
+        .collect::<Vec<_>>();
+    let mut notifier = Notifier::from_env(&titles);
+    let readiness = Readiness::new(&titles, &exports);
+    let mut fingerprints = config
+        .processes
+        .iter()
+        .filter(|process| !process.fingerprint.is_empty())
+        .map(|process| {
+            (
+                process.title.clone(),
+                Fingerprint::new(&process.fingerprint),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let mut suppressions = Suppressions::new(&config.processes);
+    let mut phases = Phases::new(&config.phases, &titles);
+    // width of the separators of phases is the default one in deterministic output
+    let separator_width = match cli.deterministic {
+        true => None,
+        false => Terminal::detect().width,
+    };
+    let mut harness = match &cli.command {
+        Some(WatchMuxCommand::Test { process }) => Some(Harness::new(process, &titles)),
+        _ => None,
+    };
+    let startup = cli
+        .deterministic
+        .then(|| StartupOrder::new(&config.processes));
+    let heartbeat = notifier.as_ref().and_then(|notifier| notifier.heartbeat);
+    let mut heartbeats = time::interval(heartbeat.unwrap_or(Duration::from_secs(1)));
+    let mut muted_heartbeats = time::interval(sink::MUTED_HEARTBEAT);
+    let mut restarts = Vec::new();
+    let mut manual_starts = HashMap::new();
+    let session_id = session_id();
+    let session_name = cli.session.as_deref().unwrap_or(&session_id);
+    let mut session = Session::register(session_name, config.processes.len())?;
+    let mut controls = control::listen(
+        &session::control_path(session_name),
+        config.http_control.as_ref(),
+    )
+    .await
+    .map_err(WatchError::Control)?;
+    let session_dir = env::temp_dir().join(format!("watchmux-{session_id}"));
+    let ports = Ports::allocate(&config.processes).map_err(WatchError::Ports)?;
+    for process in &config.processes {
+        for (name, port) in ports.own(&process.title) {
+            let line = Line::new(&process.title, Stream::Notice, format!("{name}={port}"));
+            sinks
+                .write(&Message::Line(line))
+                .await
+                .map_err(WatchError::Sink)?;
+        }
+    }
+    // keys are computed before watchmux adds env variables changing on each run
+    let mut run_once = config
+        .processes
+        .iter()
+        .filter(|process| process.run_once && !cli.force_run.contains(&process.title))
+        .map(|process| {
+            RunOnce::new(process)
+                .map(|run_once| (process.title.clone(), run_once))
+                .map_err(|error| WatchError::RunOnce(process.title.clone(), error))
+        })
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    let processes = config
+        .processes
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut process)| {
+            let metadata = [
+                ("WATCHMUX_TITLE", process.title.clone()),
+                ("WATCHMUX_INSTANCE", (index + 1).to_string()),
+                ("WATCHMUX_SESSION_ID", session_id.clone()),
+            ];
+            for (name, value) in metadata {
+                process
+                    .env
+                    .entry(name.to_string())
+                    .or_insert(EnvValue::Value(value));
+            }
+            for (name, port) in ports.env(&process) {
+                process
+                    .env
+                    .entry(name)
+                    .or_insert(EnvValue::Value(port.to_string()));
+            }
+            if process.tmpdir == TmpDirMode::Isolated {
+                let tmpdir =
+                    session_dir.join(format!("{}-{}", index + 1, file_name(&process.title)));
+                let tmpdir = tmpdir.to_string_lossy().to_string();
+                // TMP and TEMP are the temporary directory of programs on Windows
+                for name in ["TMPDIR", "TMP", "TEMP"] {
+                    process
+                        .env
+                        .insert(name.to_string(), EnvValue::Value(tmpdir.clone()));
+                }
+            }
+            let (process_shutdown_tx, process_shutdown) = shutdown::follow(shutdown.clone());
+            if let Some(harness) = &mut harness {
+                harness.add(process_shutdown_tx.clone());
+            }
+            dependencies.add(&process, process_shutdown_tx);
+            let (restart_tx, restart) = mpsc::channel(1);
+            restarts.push((process.title.clone(), restart_tx));
+            let run_once = run_once.remove(&process.title);
+            let manual_start = (!process.autostart).then(|| {
+                let (start_tx, start) = oneshot::channel();
+                manual_starts.insert(process.title.clone(), start_tx);
+                start
+            });
+            (
+                index,
+                process,
+                process_shutdown,
+                restart,
+                run_once,
+                manual_start,
+            )
+        })
+        .collect::<Vec<_>>();
+    let dependencies = Arc::new(dependencies);
+    // stdin read for keys or by the foreground process is not given to the other processes
+    let inherit_stdin = keys.is_none() && foreground.is_none();
+
+    for label in phases.start() {
+        write_separator(&mut sinks, &label, separator_width).await?;
+    }
     let processes = future::join_all(
-        config
-            .processes
+        processes
             .into_iter()
-            .map(|process| {
+            .map(|(index, process, shutdown, mut restart, run_once, manual_start)| {
+                let gate = phases.gate(&process.title);
+                let turn = startup
+                    .as_ref()
+                    .map(|startup| startup.turn(index, &process, gate.is_some()));
                 let sender = tx.clone();
-                tokio::spawn(async move { process.run(sender).await })
+                let quarantine = quarantine.clone();
+                let stdin = pipes
+                    .stdin(&process.title)
+                    .or_else(|| stdin.as_ref().map(broadcast::Sender::subscribe));
+                let dependencies = dependencies.clone();
+                let exports = exports.clone();
+                let readiness = readiness.clone();
+                let ready = harness
+                    .as_ref()
+                    .filter(|harness| harness.is_test(&process.title))
+                    .map(Harness::ready);
+                tokio::spawn(async move {
+                    if let Some(turn) = turn {
+                        if !turn.wait(shutdown.clone()).await {
+                            return;
+                        }
+                    }
+                    if let Some(gate) = gate {
+                        match gate.wait(shutdown.clone()).await {
+                            Ok(true) => (),
+                            Ok(false) => return,
+                            Err(dependency) => {
+                                let _ = sender
+                                    .send(Message::Line(Line::new(
+                                        &process.title,
+                                        Stream::Notice,
+                                        format!(
+                                            "not started, {dependency} of an earlier phase failed"
+                                        ),
+                                    )))
+                                    .await;
+                                let _ = sender
+                                    .send(Message::Event(Event::Blocked {
+                                        title: process.title.clone(),
+                                        dependency,
+                                    }))
+                                    .await;
+                                return;
+                            }
+                        }
+                    }
+                    if let Some(ready) = ready {
+                        if !harness::wait_ready(ready, shutdown.clone()).await {
+                            return;
+                        }
+                    }
+                    if run_once.as_ref().is_some_and(RunOnce::is_cached) {
+                        let _ = sender
+                            .send(Message::Line(Line::new(
+                                &process.title,
+                                Stream::Notice,
+                                "skipped, nothing changed since its last successful run",
+                            )))
+                            .await;
+                        let _ = sender
+                            .send(Message::Event(Event::Skipped {
+                                title: process.title.clone(),
+                            }))
+                            .await;
+                        let _ = sender
+                            .send(Message::Event(Event::Exited {
+                                title: process.title.clone(),
+                                code: Some(0),
+                                success: true,
+                                ended_by: None,
+                            }))
+                            .await;
+                        return;
+                    }
+                    if let Some(manual_start) = manual_start {
+                        let _ = sender
+                            .send(Message::Line(Line::new(
+                                &process.title,
+                                Stream::Notice,
+                                format!("not started, start it with ctl start {}", process.title),
+                            )))
+                            .await;
+                        let _ = sender
+                            .send(Message::Event(Event::Deferred {
+                                title: process.title.clone(),
+                                reason: "started with ctl start".to_string(),
+                            }))
+                            .await;
+                        let mut shutdown = shutdown.clone();
+                        tokio::select! {
+                            started = manual_start => if started.is_err() {
+                                return;
+                            },
+                            _ = shutdown.wait_for(|state| *state != Shutdown::Running) => return,
+                        }
+                    }
+                    if let Some(start_when) = &process.start_when {
+                        if let Some(reason) = start_when.unmet() {
+                            let _ = sender
+                                .send(Message::Line(Line::new(
+                                    &process.title,
+                                    Stream::Notice,
+                                    format!("deferred until {reason}"),
+                                )))
+                                .await;
+                            let _ = sender
+                                .send(Message::Event(Event::Deferred {
+                                    title: process.title.clone(),
+                                    reason,
+                                }))
+                                .await;
+                            if !start_when.wait(shutdown.clone()).await {
+                                return;
+                            }
+                        }
+                    }
+                    if let WaitFor::Process {
+                        process: dependency,
+                        state,
+                    } = &process.wait_for
+                    {
+                        if !readiness.reached(dependency, *state) {
+                            let state = match state {
+                                ProcessState::Ready => "be ready",
+                                ProcessState::Finished => "finish",
+                            };
+                            let _ = sender
+                                .send(Message::Line(Line::new(
+                                    &process.title,
+                                    Stream::Notice,
+                                    format!("waiting for {dependency} to {state}"),
+                                )))
+                                .await;
+                        }
+                        match readiness.wait(dependency, *state, shutdown.clone()).await {
+                            Ok(true) => (),
+                            Ok(false) => return,
+                            Err(dependency) => {
+                                let _ = sender
+                                    .send(Message::Line(Line::new(
+                                        &process.title,
+                                        Stream::Notice,
+                                        format!("blocked by dependency {dependency}"),
+                                    )))
+                                    .await;
+                                let _ = sender
+                                    .send(Message::Event(Event::Blocked {
+                                        title: process.title.clone(),
+                                        dependency,
+                                    }))
+                                    .await;
+                                return;
+                            }
+                        }
+                    }
+                    // receiver of stdin is consumed by the run, retries subscribe to it again
+                    let resubscribe = stdin.as_ref().map(broadcast::Receiver::resubscribe);
+                    let mut stdin = Some(stdin);
+                    let failed = loop {
+                        let failed = match process
+                            .run(
+                                sender.clone(),
+                                stdin.take().unwrap_or_else(|| {
+                                    resubscribe.as_ref().map(broadcast::Receiver::resubscribe)
+                                }),
+                                inherit_stdin,
+                                shutdown.clone(),
+                                &exports,
+                                &mut restart,
+                            )
+                            .await
+                        {
+                            Ok(status) => {
+                                let success = status.is_some_and(|status| status.success());
+                                if let Some(run_once) = run_once.as_ref().filter(|_| success) {
+                                    if let Err(error) = run_once.store() {
+                                        let _ = sender
+                                            .send(Message::Line(Line::new(
+                                                &process.title,
+                                                Stream::Notice,
+                                                format!("failed to store run_once key: {error}"),
+                                            )))
+                                            .await;
+                                    }
+                                }
+                                status.map(|status| !status.success()).unwrap_or(false)
+                            }
+                            Err(error) => {
+                                let error = error.to_string();
+                                let _ = sender
+                                    .send(Message::Line(Line::new(
+                                        &process.title,
+                                        Stream::Notice,
+                                        format!("failed: {error}"),
+                                    )))
+                                    .await;
+                                let _ = sender
+                                    .send(Message::Event(Event::Failed {
+                                        title: process.title.clone(),
+                                        error,
+                                    }))
+                                    .await;
+                                true
+                            }
+                        };
+                        let Some(quarantine) = quarantine.as_ref().filter(|_| failed) else {
+                            break failed;
+                        };
+                        let number = index + 1;
+                        let _ = sender
+                            .send(Message::Line(Line::new(
+                                &process.title,
+                                Stream::Notice,
+                                format!(
+                                    "quarantined, press r{number} to retry, s{number} to skip \
+                                    or o{number} to show its output"
+                                ),
+                            )))
+                            .await;
+                        match quarantine.hold(&process.title, shutdown.clone()).await {
+                            Decision::Retry => continue,
+                            Decision::Skip => break true,
+                        }
+                    };
+
+                    if failed {
+                        for (title, dependency) in dependencies.block_dependents(&process.title) {
+                            let _ = sender
+                                .send(Message::Line(Line::new(
+                                    &title,
+                                    Stream::Notice,
+                                    format!("blocked by dependency {dependency}"),
+                                )))
+                                .await;
+                            let _ = sender
+                                .send(Message::Event(Event::Blocked { title, dependency }))
+                                .await;
+                        }
+                    }
+                })
             })
             .collect::<Vec<_>>(),
     );
     tokio::pin!(processes);
 
-    let mut stdout = tokio::io::stdout();
+    let mut summary = Summary::new();
+    let scheduled_stop = time::sleep_until(
+        schedule
+            .as_ref()
+            .map(|(deadline, _)| *deadline)
+            .unwrap_or_else(Instant::now),
+    );
+    tokio::pin!(scheduled_stop);
+    let mut control_signals = signals::listen();
+    let reads_stdin = cli
+        .config
+        .as_deref()
+        .is_some_and(|path| path.as_os_str() == "-");
+    let in_terminal = std::io::stdin().is_terminal() || std::io::stdout().is_terminal();
+    let mut reload = false;
+    let mut stop_requests = stop_file::watch(config.stop_file.clone());
+    let mut stopped_by_schedule = false;
+    let mut completed = false;
+    let mut search_index = SearchIndex::new(
+        config
+            .buffer_memory
+            .unwrap_or(search::DEFAULT_BUFFER_MEMORY),
+    );
+    let mut added = Added::new(
+        tx.clone(),
+        shutdown.clone(),
+        exports.clone(),
+        session_id.clone(),
+    );
+    let mut separators = Vec::new();
     loop {
         tokio::select! {
-            _ = &mut processes => {
-                rx.close();
-                break;
+            _ = &mut processes, if !completed => {
+                // closed channel still yields buffered messages before returning None
+                completed = true;
+                if added.is_empty() {
+                    rx.close();
+                }
+            },
+            true = added.next(), if !added.is_empty() => {
+                if completed && added.is_empty() {
+                    rx.close();
+                }
+            },
+            _ = &mut scheduled_stop, if schedule.is_some() => {
+                let reason = schedule.take().map(|(_, reason)| reason).unwrap_or_default();
+                if shutdown::terminate(&shutdown_tx) {
+                    stopped_by_schedule = true;
+                    let line = Line::new(
+                        WATCHMUX_TITLE,
+                        Stream::Notice,
+                        format!("{reason}, stopping processes"),
+                    );
+                    sinks.write(&Message::Line(line)).await.map_err(WatchError::Sink)?
+                }
+            },
+            _ = heartbeats.tick(), if heartbeat.is_some() => {
+                if let Some(notifier) = &notifier {
+                    notifier.heartbeat();
+                }
+            },
+            _ = muted_heartbeats.tick() => sinks.heartbeat().await.map_err(WatchError::Sink)?,
+            Some(key) = keys::next(keys.as_mut()) => {
+                handle_key(
+                    key,
+                    &restarts,
+                    quarantine.as_ref(),
+                    scrollback.as_ref(),
+                    &mut sinks,
+                    &shutdown_tx,
+                )
+                .await?
+            },
+            Some(signal) = control_signals.recv() => {
+                let lines = match signal {
+                    ControlSignal::Dump => summary.lines(),
+                    ControlSignal::Reload if reads_stdin => {
+                        let notice = "config is read from stdin, it cannot be reloaded";
+                        vec![Line::new(WATCHMUX_TITLE, Stream::Notice, notice)]
+                    }
+                    // terminal sends SIGHUP when it is closed, nothing would show the output
+                    ControlSignal::Reload if in_terminal && shutdown::terminate(&shutdown_tx) => {
+                        let notice = "terminal hung up, stopping processes";
+                        vec![Line::new(WATCHMUX_TITLE, Stream::Notice, notice)]
+                    }
+                    ControlSignal::Reload if !in_terminal && shutdown::terminate(&shutdown_tx) => {
+                        reload = true;
+                        let notice = "reloading config, stopping processes";
+                        vec![Line::new(WATCHMUX_TITLE, Stream::Notice, notice)]
+                    }
+                    ControlSignal::Reload => Vec::new(),
+                    ControlSignal::RestartAll => {
+                        let mut lines =
+                            vec![Line::new(WATCHMUX_TITLE, Stream::Notice, "restarting processes")];
+                        for (title, restart) in &restarts {
+                            if fingerprints.get_mut(title).is_some_and(|print| !print.changed()) {
+                                let notice = "not restarted, its fingerprint has not changed";
+                                lines.push(Line::new(title, Stream::Notice, notice));
+                                continue;
+                            }
+                            let _ = restart.try_send(());
+                        }
+                        lines
+                    }
+                };
+                for line in lines {
+                    sinks.write(&Message::Line(line)).await.map_err(WatchError::Sink)?;
+                }
             },
-            Some(line) = rx.recv() => {
-                stdout.write_all(line.as_bytes()).await?
+            Some(request) = stop_requests.recv() => {
+                let lines = match request {
+                    StopRequest::All if shutdown::terminate(&shutdown_tx) => {
+                        let notice = "stop_file appeared, stopping processes";
+                        vec![Line::new(WATCHMUX_TITLE, Stream::Notice, notice)]
+                    }
+                    StopRequest::All => Vec::new(),
+                    StopRequest::Processes(titles) => titles
+                        .iter()
+                        .map(|title| match dependencies.stop(title) {
+                            true => {
+                                Line::new(title, Stream::Notice, "stopping, requested with stop_file")
+                            }
+                            false => Line::new(
+                                WATCHMUX_TITLE,
+                                Stream::Notice,
+                                format!("stop_file names {title}, which is not running"),
+                            ),
+                        })
+                        .collect(),
+                };
+                for line in lines {
+                    sinks.write(&Message::Line(line)).await.map_err(WatchError::Sink)?;
+                }
+            },
+            Some((request, reply)) = controls.recv() => {
+                let adds = matches!(request, ControlRequest::Add { .. });
+                let outcome = apply_control(
+                    request,
+                    &mut sinks,
+                    &mut summary,
+                    &mut manual_starts,
+                    &mut added,
+                    &mut restarts,
+                    &search_index,
+                );
+                let outcome = match outcome {
+                    Ok(Applied::Notice(line)) => {
+                        sinks.write(&Message::Line(line)).await.map_err(WatchError::Sink)?;
+                        if adds {
+                            let _ = session.add_process();
+                        }
+                        Ok(Vec::new())
+                    }
+                    Ok(Applied::Reply(lines)) => Ok(lines),
+                    Err(reason) => Err(reason),
+                };
+                let _ = reply.send(outcome);
+            },
+            message = rx.recv() => match message {
+                Some(message) => {
+                    let mut foreground_started = false;
+                    let mut held = false;
+                    let mut startup_output = None;
+                    match &message {
+                        Message::Event(event) => {
+                            // processes exit unsuccessfully when stopped, failing their phase
+                            if *shutdown_tx.borrow() == Shutdown::Running {
+                                separators = phases.record(event);
+                                if let Some(notifier) = &mut notifier {
+                                    notifier.record(event);
+                                }
+                            }
+                            summary.record(event);
+                            readiness.record(event);
+                            startup_output = suppressions.record(event);
+                            if let Some(startup) = &startup {
+                                startup.record(event);
+                            }
+                            // sessions list is informative, failing to update it is ignored
+                            let _ = session.record(event);
+                            if let Some(harness) = &mut harness {
+                                if harness.record(event).is_some() {
+                                    harness.teardown();
+                                }
+                            }
+                            // terminal is given to the process once its start is written
+                            if foreground.as_deref() == Some(event.title()) {
+                                sinks.set_foreground(None);
+                                foreground_started = matches!(event, Event::Started { .. });
+                            }
+                        }
+                        _ => {
+                            for line in message.lines() {
+                                if let Some(scrollback) = &mut scrollback {
+                                    scrollback.record(line);
+                                }
+                                search_index.record(line);
+                                exports.capture(line);
+                                pipes.write(line);
+                                alerts.check(line);
+                            }
+                            held = suppressions.hold(&message);
+                        }
+                    }
+                    if let Some(output) = startup_output {
+                        sinks.write(&output).await.map_err(WatchError::Sink)?;
+                    }
+                    if !held {
+                        sinks.write(&message).await.map_err(WatchError::Sink)?;
+                    }
+                    let title = match &message {
+                        Message::Event(event) => Some(event.title()),
+                        _ => message.lines().first().map(|line| line.title.as_str()),
+                    };
+                    let ready = title.and_then(|title| suppressions.ready(title, &readiness));
+                    if let Some(line) = ready {
+                        sinks
+                            .write(&Message::Line(line))
+                            .await
+                            .map_err(WatchError::Sink)?;
+                    }
+                    if let Some(title) = foreground.as_ref().filter(|_| foreground_started) {
+                        let line = Line::new(
+                            title,
+                            Stream::Notice,
+                            "has the terminal until it exits, output of other processes is written only to non-interactive sinks",
+                        );
+                        sinks
+                            .write(&Message::Line(line))
+                            .await
+                            .map_err(WatchError::Sink)?;
+                        sinks.set_foreground(Some(title.clone()));
+                    }
+                    for label in separators.drain(..) {
+                        write_separator(&mut sinks, &label, separator_width).await?;
+                    }
+                }
+                None => break,
             }
         };
     }
+    if let Some(notifier) = &notifier {
+        notifier.stopping();
+    }
+    for line in alerts.lines() {
+        sinks
+            .write(&Message::Line(line))
+            .await
+            .map_err(WatchError::Sink)?;
+    }
+    if stopped_by_schedule {
+        for line in summary.lines() {
+            sinks
+                .write(&Message::Line(line))
+                .await
+                .map_err(WatchError::Sink)?;
+        }
+    } else if !cli.deterministic {
+        for line in summary.durations() {
+            sinks
+                .write(&Message::Line(line))
+                .await
+                .map_err(WatchError::Sink)?;
+        }
+    }
+    sinks.finish().await.map_err(WatchError::Sink)?;
+    let _ = tokio::fs::remove_dir_all(&session_dir).await;
+    if let Some(path) = &cli.result_file {
+        let result = serde_json::to_vec_pretty(&summary.result()).unwrap_or_default();
+        tokio::fs::write(path, result)
+            .await
+            .map_err(WatchError::ResultFile)?;
+    }
+
+    if reload {
+        return Ok(Outcome::Reload);
+    }
+    // test stopped before it was decided fails
+    Ok(Outcome::Stopped(
+        harness.map(|harness| harness.code.unwrap_or(1)),
+    ))
+}
+
+/// Run watchmux again in place of this process with the same arguments, reading the config anew.
+/// Returns only when it cannot be run.
+#[cfg(unix)]
+fn reload() -> std::io::Error {
+    use std::os::unix::process::CommandExt;
+
+    match env::current_exe() {
+        Ok(exe) => std::process::Command::new(exe)
+            .args(env::args_os().skip(1))
+            .exec(),
+        Err(error) => error,
+    }
+}
+
+#[cfg(not(unix))]
+fn reload() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "reloading is only supported on unix",
+    )
+}
+
+/// Outcome of the `watchmux ctl` request.
+enum Applied {
+    /// Notice stamped into the output.
+    Notice(Line),
+    /// Output lines replied to the request.
+    Reply(Vec<Line>),
+}
+
+/// Apply the `watchmux ctl` request to the processes, which are the ones restarted by
+/// `restarts`.
+fn apply_control(
+    request: ControlRequest,
+    sinks: &mut Sinks,
+    summary: &mut Summary,
+    manual_starts: &mut HashMap<String, oneshot::Sender<()>>,
+    added: &mut Added,
+    restarts: &mut Vec<(String, mpsc::Sender<()>)>,
+    search_index: &SearchIndex,
+) -> Result<Applied, String> {
+    // processes are found by the title of the config or the one they are shown with
+    let find = |title: &str| {
+        restarts
+            .iter()
+            .map(|(process, _)| process)
+            .find(|process| *process == title || sinks.title(process) == title)
+            .cloned()
+            .ok_or_else(|| format!("no process titled {title}"))
+    };
+
+    let notice = match request {
+        ControlRequest::Annotate { title, note } => {
+            let process = find(&title)?;
+            summary.annotate(&process, note.clone());
+            Line::new(&process, Stream::Notice, format!("note: {note}"))
+        }
+        ControlRequest::Retitle { title, new_title } => {
+            let process = find(&title)?;
+            if restarts
+                .iter()
+                .any(|(other, _)| sinks.title(other) == new_title)
+            {
+                return Err(format!("title {new_title} is taken"));
+            }
+            let previous = sinks.title(&process).to_string();
+            sinks.retitle(&process, new_title);
+            Line::new(
+                &process,
+                Stream::Notice,
+                format!("retitled from {previous}"),
+            )
+        }
+        ControlRequest::Mute { title } => match sinks.set_muted(&find(&title)?, true) {
+            true => Line::new(
+                WATCHMUX_TITLE,
+                Stream::Notice,
+                format!("{title} muted, unmute with ctl unmute {title}"),
+            ),
+            false => return Err(format!("{title} is muted already")),
+        },
+        ControlRequest::Unmute { title } => match sinks.set_muted(&find(&title)?, false) {
+            true => Line::new(WATCHMUX_TITLE, Stream::Notice, format!("{title} unmuted")),
+            false => return Err(format!("{title} is not muted")),
+        },
+        ControlRequest::Start { title } => {
+            let process = find(&title)?;
+            let start = manual_starts
+                .remove(&process)
+                .ok_or_else(|| format!("{title} is started already"))?;
+            let _ = start.send(());
+            Line::new(
+                &process,
+                Stream::Notice,
+                "starting, requested with ctl start",
+            )
+        }
+        ControlRequest::Add { process } => {
+            let process = Added::parse(process)?;
+            let title = process.title.clone();
+            if find(&title).is_ok() {
+                return Err(format!("title {title} is taken"));
+            }
+            let restart = added.start(process, restarts.len() + 1)?;
+            restarts.push((title.clone(), restart));
+            Line::new(&title, Stream::Notice, "added, requested with ctl add")
+        }
+        ControlRequest::Mark { label } => {
+            Line::new(WATCHMUX_TITLE, Stream::Notice, mark(label.as_deref()))
+        }
+        ControlRequest::Search {
+            pattern,
+            title,
+            since,
+        } => {
+            let pattern = Regex::new(&pattern).map_err(|_| format!("invalid pattern {pattern}"))?;
+            let process = title.as_deref().map(find).transpose()?;
+            let lines = search_index
+                .search(&pattern, process.as_deref(), since)
+                .into_iter()
+                .map(|mut line| {
+                    line.title = sinks.title(&line.title).to_string();
+                    line
+                })
+                .collect();
+            return Ok(Applied::Reply(lines));
+        }
+        ControlRequest::Status { buffers } => {
+            let mut lines = summary.lines();
+            if buffers {
+                lines.extend(search_index.usage());
+            }
+            return Ok(Applied::Reply(lines));
+        }
+    };
+
+    Ok(Applied::Notice(notice))
+}
+
+/// Sessions with the name, or the sessions running in the current directory without it.
+fn selected_sessions(name: Option<&str>) -> std::io::Result<Vec<SessionInfo>> {
+    let directory = env::current_dir()?;
+
+    Ok(session::list()?
+        .into_iter()
+        .filter(|session| match name {
+            Some(name) => session.name == name,
+            None => session.directory == directory,
+        })
+        .collect())
+}
+
+/// Identifier of this run of watchmux unique on the host.
+fn session_id() -> String {
+    let started = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or_default();
+
+    format!("{started:x}-{:x}", std::process::id())
+}
+
+/// Title made safe to use as a file name.
+fn file_name(title: &str) -> String {
+    title
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' || c == '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Write a rule labeled with the label e.g. when a phase starts.
+async fn write_separator(
+    sinks: &mut Sinks,
+    label: &str,
+    width: Option<usize>,
+) -> Result<(), WatchError> {
+    let title_width = WATCHMUX_TITLE.chars().count();
+    let rule = output::rule(Some(label), title_width, width);
+    let line = Line::new(WATCHMUX_TITLE, Stream::Notice, rule);
+    sinks
+        .write(&Message::Line(line))
+        .await
+        .map_err(WatchError::Sink)
+}
+
+/// Act on the key typed to watchmux, processes are numbered from 1 in the order of the config.
+/// Rule marking the current moment in the output, labeled with the label, `mark` by default, and
+/// the time.
+fn mark(label: Option<&str>) -> String {
+    let label = format!("{} {}", label.unwrap_or("mark"), schedule::local_time());
+    let title_width = WATCHMUX_TITLE.chars().count();
+    output::rule(Some(&label), title_width, Terminal::detect().width)
+}
+
+async fn handle_key(
+    key: Key,
+    restarts: &[(String, mpsc::Sender<()>)],
+    quarantine: Option<&Quarantine>,
+    scrollback: Option<&Scrollback>,
+    sinks: &mut Sinks,
+    shutdown: &watch::Sender<Shutdown>,
+) -> Result<(), WatchError> {
+    let process = |number: usize| number.checked_sub(1).and_then(|index| restarts.get(index));
+    let decide = |title: &str, decision| {
+        quarantine.is_some_and(|quarantine| quarantine.decide(title, decision))
+    };
+    let notices = match key {
+        Key::Restart(number) => match process(number) {
+            Some((title, _)) if decide(title, Decision::Retry) => {
+                vec![format!("retrying {title}")]
+            }
+            Some((title, restart)) => match restart.try_send(()) {
+                Err(TrySendError::Closed(_)) => vec![format!("{title} is not running")],
+                _ => Vec::new(),
+            },
+            None => vec![format!("no process {number}, press ? for help")],
+        },
+        Key::Mute(number) => match process(number) {
+            Some((title, _)) if sinks.toggle_mute(title) => {
+                vec![format!("{title} muted, press m{number} to unmute")]
+            }
+            Some((title, _)) => vec![format!("{title} unmuted")],
+            None => vec![format!("no process {number}, press ? for help")],
+        },
+        Key::Skip(number) => match process(number) {
+            Some((title, _)) if decide(title, Decision::Skip) => {
+                vec![format!("skipped {title}")]
+            }
+            Some((title, _)) => vec![format!("{title} is not quarantined")],
+            None => vec![format!("no process {number}, press ? for help")],
+        },
+        Key::Output(number) => match (process(number), scrollback) {
+            (Some((title, _)), Some(scrollback)) => {
+                let title_width = WATCHMUX_TITLE.chars().count();
+                let label = format!("output of {title}");
+                let rule = output::rule(Some(&label), title_width, Terminal::detect().width);
+                let rule = Line::new(WATCHMUX_TITLE, Stream::Notice, rule);
+                sinks
+                    .write(&Message::Line(rule))
+                    .await
+                    .map_err(WatchError::Sink)?;
+                sinks
+                    .write(&Message::Block(scrollback.lines(title)))
+                    .await
+                    .map_err(WatchError::Sink)?;
+                Vec::new()
+            }
+            (Some(_), None) => vec!["output is kept with --interactive-failures".to_string()],
+            (None, _) => vec![format!("no process {number}, press ? for help")],
+        },
+        Key::Mark => vec![mark(None)],
+        Key::Quit if shutdown::terminate(shutdown) => vec!["stopping processes".to_string()],
+        Key::Quit => Vec::new(),
+        Key::Help => vec![
+            match quarantine {
+                Some(_) => {
+                    "keys: r<number> restart or retry, s<number> skip, o<number> output, \
+                    m<number> mute, - mark, q quit, ? help"
+                }
+                None => "keys: r<number> restart, m<number> mute, - mark, q quit, ? help",
+            }
+            .to_string(),
+            restarts
+                .iter()
+                .enumerate()
+                .map(|(index, (title, _))| format!("{} {title}", index + 1))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ],
+    };
+
+    for notice in notices {
+        let line = Line::new(WATCHMUX_TITLE, Stream::Notice, notice);
+        sinks
+            .write(&Message::Line(line))
+            .await
+            .map_err(WatchError::Sink)?;
+    }
 
     Ok(())
 }
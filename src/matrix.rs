@@ -0,0 +1,222 @@
+use serde_yaml::Value;
+use thiserror::Error;
+
+/// Replace processes of the config having `matrix: { name: [values] }` with a process for each
+/// combination of the values. `{name}` in strings of the process is replaced with the value of
+/// the combination, and the values without a placeholder in the title are appended to it to keep
+/// the titles unique.
+pub fn expand(config: &mut Value) -> Result<(), MatrixError> {
+    let Some(processes) = config.get_mut("processes").and_then(Value::as_sequence_mut) else {
+        return Ok(());
+    };
+
+    let mut expanded = Vec::with_capacity(processes.len());
+    for mut process in processes.drain(..) {
+        let Some(matrix) = process
+            .as_mapping_mut()
+            .and_then(|process| process.remove("matrix"))
+        else {
+            expanded.push(process);
+            continue;
+        };
+
+        for combination in combinations(&matrix)? {
+            let mut process = process.clone();
+            if let Some(title) = process.get("title").and_then(Value::as_str) {
+                let values = combination
+                    .iter()
+                    .filter(|(name, _)| !has_placeholder(title, name))
+                    .map(|(_, value)| value.as_str());
+                let title = [title].into_iter().chain(values).collect::<Vec<_>>();
+                process["title"] = Value::String(title.join("-"));
+            }
+            interpolate(&mut process, &combination);
+            expanded.push(process);
+        }
+    }
+    *processes = expanded;
+
+    Ok(())
+}
+
+/// Every combination of the values as `(name, value)` pairs, last name varying fastest.
+fn combinations(matrix: &Value) -> Result<Vec<Vec<(String, String)>>, MatrixError> {
+    let matrix = matrix.as_mapping().ok_or(MatrixError::NotMapping)?;
+
+    let mut combinations = vec![Vec::new()];
+    for (name, values) in matrix {
+        let name = scalar(name).ok_or(MatrixError::InvalidName)?;
+        let values = values
+            .as_sequence()
+            .and_then(|values| values.iter().map(scalar).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| MatrixError::InvalidValues(name.clone()))?;
+        // process would silently expand to nothing
+        if values.is_empty() {
+            return Err(MatrixError::NoValues(name));
+        }
+
+        let mut next = Vec::with_capacity(combinations.len() * values.len());
+        for combination in &combinations {
+            for value in &values {
+                let mut combination = combination.clone();
+                combination.push((name.clone(), value.clone()));
+                next.push(combination);
+            }
+        }
+        combinations = next;
+    }
+
+    Ok(combinations)
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn has_placeholder(text: &str, name: &str) -> bool {
+    text.contains(&format!("{{{name}}}"))
+}
+
+/// Replace placeholders in every string of the value.
+fn interpolate(value: &mut Value, combination: &[(String, String)]) {
+    match value {
+        Value::String(text) => {
+            for (name, value) in combination {
+                *text = text.replace(&format!("{{{name}}}"), value);
+            }
+        }
+        Value::Sequence(values) => {
+            for value in values {
+                interpolate(value, combination);
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (_, value) in mapping.iter_mut() {
+                interpolate(value, combination);
+            }
+        }
+        Value::Tagged(tagged) => interpolate(&mut tagged.value, combination),
+        _ => (),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MatrixError {
+    #[error("matrix must be a map of names to lists of values")]
+    NotMapping,
+
+    #[error("matrix names must be strings")]
+    InvalidName,
+
+    #[error("matrix values of {0} must be a list of strings, numbers or booleans")]
+    InvalidValues(String),
+
+    #[error("matrix values of {0} are empty, the process would not be run at all")]
+    NoValues(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expanded(processes: &str) -> Result<Vec<Value>, MatrixError> {
+        let mut config: Value = serde_yaml::from_str(&format!("processes:\n{processes}")).unwrap();
+        expand(&mut config)?;
+        Ok(config["processes"].as_sequence().unwrap().clone())
+    }
+
+    fn titles(processes: &[Value]) -> Vec<&str> {
+        processes
+            .iter()
+            .map(|process| process["title"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn every_combination_is_expanded_with_values_in_title() {
+        let processes = expanded(
+            "
+  - title: build
+    cmd: cargo build
+    matrix: { service: [api, worker], mode: [debug, release] }
+  - title: db
+    cmd: postgres
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            titles(&processes),
+            [
+                "build-api-debug",
+                "build-api-release",
+                "build-worker-debug",
+                "build-worker-release",
+                "db"
+            ]
+        );
+        assert!(processes[0].get("matrix").is_none());
+    }
+
+    #[test]
+    fn placeholders_are_replaced_in_title_cmd_and_env() {
+        let processes = expanded(
+            "
+  - title: '{service} {port}'
+    cmd: run {service} --port {port}
+    env: { SERVICE: '{service}', DEBUG: true }
+    matrix: { service: [api], port: [8080, 8081] }
+",
+        )
+        .unwrap();
+
+        assert_eq!(titles(&processes), ["api 8080", "api 8081"]);
+        assert_eq!(processes[1]["cmd"], "run api --port 8081");
+        assert_eq!(processes[1]["env"]["SERVICE"], "api");
+        assert_eq!(processes[1]["env"]["DEBUG"], true);
+    }
+
+    #[test]
+    fn values_without_placeholder_in_title_keep_titles_unique() {
+        let processes = expanded(
+            "
+  - title: '{service}'
+    cmd: run {service} --{mode}
+    matrix: { service: [api, worker], mode: [debug, release] }
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            titles(&processes),
+            ["api-debug", "api-release", "worker-debug", "worker-release"]
+        );
+    }
+
+    #[test]
+    fn invalid_matrix_is_an_error() {
+        let error = |matrix: &str| {
+            expanded(&format!("  - {{ title: a, cmd: a, matrix: {matrix} }}"))
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            error("[a, b]"),
+            "matrix must be a map of names to lists of values"
+        );
+        assert_eq!(
+            error("{ mode: [[a]] }"),
+            "matrix values of mode must be a list of strings, numbers or booleans"
+        );
+        assert_eq!(
+            error("{ mode: [] }"),
+            "matrix values of mode are empty, the process would not be run at all"
+        );
+    }
+}
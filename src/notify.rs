@@ -0,0 +1,236 @@
+use std::{collections::HashSet, env, time::Duration};
+
+use crate::event::Event;
+
+/// Notifies systemd of readiness and liveness of watchmux run as `Type=notify` service.
+pub struct Notifier {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    /// Interval of `WATCHDOG=1` heartbeats, half of the watchdog timeout of the service.
+    pub heartbeat: Option<Duration>,
+    /// Processes which have not started yet.
+    starting: HashSet<String>,
+    /// Processes which failed or exited unsuccessfully without being restarted.
+    unhealthy: HashSet<String>,
+    ready: bool,
+}
+
+impl Notifier {
+    /// Connect to `NOTIFY_SOCKET` of systemd when run as a service waiting for the `titles`.
+    #[cfg(unix)]
+    pub fn from_env(titles: &[String]) -> Option<Self> {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = env::var_os("NOTIFY_SOCKET")?;
+        let socket = UnixDatagram::unbound().ok()?;
+        match path.to_str().and_then(|path| path.strip_prefix('@')) {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+                let address = SocketAddr::from_abstract_name(name).ok()?;
+                socket.connect_addr(&address).ok()?;
+            }
+            _ => socket.connect(path).ok()?,
+        }
+
+        let watchdog_pid = env::var("WATCHDOG_PID")
+            .ok()
+            .and_then(|pid| pid.parse::<u32>().ok());
+        let heartbeat = env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse().ok())
+            .filter(|_| watchdog_pid.is_none_or(|pid| pid == std::process::id()))
+            .map(|usec| Duration::from_micros(usec) / 2);
+
+        Some(Self {
+            socket,
+            heartbeat,
+            starting: titles.iter().cloned().collect(),
+            unhealthy: HashSet::new(),
+            ready: false,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_env(_: &[String]) -> Option<Self> {
+        None
+    }
+
+    /// Track the event, notifying `READY=1` once every process has started, or exited
+    /// successfully, and none is unhealthy. Processes failing before that are notified as the
+    /// `STATUS=` of the service.
+    pub fn record(&mut self, event: &Event) {
+        match event {
+            Event::Started { title, .. }
+            | Event::Restarted { title, .. }
+            | Event::Skipped { title }
+            | Event::Exited {
+                title,
+                success: true,
+                ended_by: None,
+                ..
+            } => {
+                self.starting.remove(title);
+                self.unhealthy.remove(title);
+            }
+            // process stopped or restarted by watchmux has not failed, and deferred processes are
+            // started only once the machine has headroom or they are started with `ctl start`
+            Event::Exited {
+                title,
+                ended_by: Some(_),
+                ..
+            }
+            | Event::Deferred { title, .. } => {
+                self.starting.remove(title);
+            }
+            Event::Exited { title, .. }
+            | Event::Failed { title, .. }
+            | Event::Blocked { title, .. } => {
+                self.unhealthy.insert(title.clone());
+                if !self.ready {
+                    self.notify(&format!("STATUS={title} failed, not ready"));
+                }
+            }
+        }
+
+        if !self.ready && self.starting.is_empty() && self.unhealthy.is_empty() {
+            self.ready = true;
+            self.notify("READY=1\nSTATUS=every process has started");
+        }
+    }
+
+    /// Notify `WATCHDOG=1` unless a process is unhealthy, letting systemd restart the service.
+    pub fn heartbeat(&self) {
+        if self.unhealthy.is_empty() {
+            self.notify("WATCHDOG=1");
+        }
+    }
+
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    fn notify(&self, state: &str) {
+        // notifications are best effort, systemd reports the service failing to notify itself
+        #[cfg(unix)]
+        let _ = self.socket.send(state.as_bytes());
+        #[cfg(not(unix))]
+        let _ = state;
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::net::UnixDatagram;
+
+    use super::*;
+    use crate::event::EndedBy;
+
+    /// Notifier of the processes connected to the returned socket in place of systemd.
+    fn notifier(titles: &[&str]) -> (Notifier, UnixDatagram) {
+        let (socket, systemd) = UnixDatagram::pair().unwrap();
+        systemd.set_nonblocking(true).unwrap();
+        let notifier = Notifier {
+            socket,
+            heartbeat: None,
+            starting: titles.iter().map(|title| title.to_string()).collect(),
+            unhealthy: HashSet::new(),
+            ready: false,
+        };
+        (notifier, systemd)
+    }
+
+    fn notified(systemd: &UnixDatagram) -> Vec<String> {
+        let mut buffer = [0; 256];
+        std::iter::from_fn(|| {
+            let len = systemd.recv(&mut buffer).ok()?;
+            Some(String::from_utf8_lossy(&buffer[..len]).into_owned())
+        })
+        .collect()
+    }
+
+    fn started(title: &str) -> Event {
+        Event::Started {
+            title: title.to_string(),
+            pid: None,
+        }
+    }
+
+    #[test]
+    fn ready_once_every_process_has_started() {
+        let (mut notifier, systemd) = notifier(&["db", "api"]);
+
+        notifier.record(&started("db"));
+        assert!(notified(&systemd).is_empty());
+        notifier.record(&started("api"));
+        notifier.record(&started("api"));
+        assert_eq!(
+            notified(&systemd),
+            ["READY=1\nSTATUS=every process has started"]
+        );
+    }
+
+    #[test]
+    fn successful_exit_counts_as_started() {
+        let (mut notifier, systemd) = notifier(&["migrate"]);
+
+        notifier.record(&Event::Exited {
+            title: "migrate".to_string(),
+            code: Some(0),
+            success: true,
+            ended_by: None,
+        });
+        assert_eq!(
+            notified(&systemd),
+            ["READY=1\nSTATUS=every process has started"]
+        );
+    }
+
+    #[test]
+    fn failure_before_ready_is_notified_as_status() {
+        let (mut notifier, systemd) = notifier(&["db", "api"]);
+
+        notifier.record(&Event::Failed {
+            title: "db".to_string(),
+            error: "not found".to_string(),
+        });
+        notifier.record(&started("api"));
+        assert_eq!(notified(&systemd), ["STATUS=db failed, not ready"]);
+
+        notifier.heartbeat();
+        assert!(notified(&systemd).is_empty());
+    }
+
+    #[test]
+    fn process_stopped_on_purpose_is_not_unhealthy() {
+        let (mut notifier, systemd) = notifier(&["db", "api"]);
+
+        notifier.record(&started("db"));
+        notifier.record(&Event::Exited {
+            title: "api".to_string(),
+            code: None,
+            success: false,
+            ended_by: Some(EndedBy::Stop),
+        });
+        assert_eq!(
+            notified(&systemd),
+            ["READY=1\nSTATUS=every process has started"]
+        );
+
+        notifier.heartbeat();
+        assert_eq!(notified(&systemd), ["WATCHDOG=1"]);
+    }
+
+    #[test]
+    fn skipped_run_once_process_counts_as_started() {
+        let (mut notifier, systemd) = notifier(&["migrate"]);
+
+        notifier.record(&Event::Skipped {
+            title: "migrate".to_string(),
+        });
+        assert_eq!(
+            notified(&systemd),
+            ["READY=1\nSTATUS=every process has started"]
+        );
+    }
+}
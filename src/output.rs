@@ -0,0 +1,548 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    io::{self, IsTerminal},
+    time::{Duration, Instant},
+};
+
+use ansi_term::{Color, Style};
+use clap::ArgEnum;
+use terminal_size::{terminal_size, Width};
+
+use crate::{
+    ci::{Buildkite, CiFormatter, GitHubActions, GitLab, TeamCity},
+    correlation::{Correlation, Correlator},
+    event::{Event, Line, Stream},
+    hyperlink::{Hyperlinker, Hyperlinks},
+    progress::StartupProgress,
+    theme::{Theme, ThemeColor},
+};
+
+/// Minimum amount of characters title is truncated to on narrow terminals.
+const MIN_TITLE_WIDTH: usize = 8;
+/// Width of the restart separator rule when the terminal width is not known.
+const DEFAULT_RULE_WIDTH: usize = 80;
+
+/// Colors terminal is able to show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSupport {
+    None,
+    /// Basic 8 ANSI colors.
+    Basic,
+    /// 256 color palette.
+    Fixed,
+}
+
+/// Capabilities of the terminal watchmux output is written to.
+#[derive(Debug)]
+pub struct Terminal {
+    pub colors: ColorSupport,
+    pub width: Option<usize>,
+    /// Terminal is able to show emoji and glyphs of nerd fonts.
+    pub unicode: bool,
+    /// Output is written to a terminal instead of a file or a pipe.
+    pub interactive: bool,
+}
+
+impl Terminal {
+    /// Detect capabilities of stdout honoring `NO_COLOR`, `CLICOLOR` and `CLICOLOR_FORCE`.
+    pub fn detect() -> Self {
+        let is_terminal = io::stdout().is_terminal();
+        let env_set = |name: &str| env::var(name).map(|value| value != "0").ok();
+        let term = env::var("TERM").unwrap_or_default();
+
+        let color_enabled = if env::var_os("NO_COLOR").is_some() {
+            false
+        } else if env_set("CLICOLOR_FORCE") == Some(true) {
+            true
+        } else {
+            env_set("CLICOLOR") != Some(false) && is_terminal && term != "dumb"
+        };
+
+        let colors = if !color_enabled {
+            ColorSupport::None
+        } else if term.contains("256color")
+            || env::var("COLORTERM")
+                .map(|colorterm| colorterm == "truecolor" || colorterm == "24bit")
+                .unwrap_or(false)
+        {
+            ColorSupport::Fixed
+        } else {
+            ColorSupport::Basic
+        };
+
+        let width = terminal_size()
+            .map(|(Width(width), _)| width as usize)
+            .or_else(|| env::var("COLUMNS").ok()?.parse().ok());
+
+        // console of the linux kernel has no glyphs beyond its font of 512 characters
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+            .unwrap_or_default()
+            .to_lowercase();
+        let unicode = term != "dumb"
+            && term != "linux"
+            && (cfg!(windows) || locale.contains("utf-8") || locale.contains("utf8"));
+
+        Self {
+            colors,
+            width,
+            unicode,
+            interactive: is_terminal,
+        }
+    }
+
+    /// Terminal without colors, width or glyphs, such as a file.
+    pub fn plain() -> Self {
+        Self {
+            colors: ColorSupport::None,
+            width: None,
+            unicode: false,
+            interactive: false,
+        }
+    }
+}
+
+/// Format of the multiplexed output.
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Lines prefixed with title of the process.
+    #[default]
+    Stream,
+    /// GitHub Actions workflow commands grouping contiguous output of a process.
+    Gha,
+    /// Buildkite collapsed sections for contiguous output of a process.
+    Buildkite,
+    /// GitLab CI collapsible sections for contiguous output of a process.
+    Gitlab,
+    /// TeamCity service message blocks for contiguous output of a process.
+    Teamcity,
+}
+
+impl OutputMode {
+    fn ci_formatter(self) -> Option<Box<dyn CiFormatter>> {
+        match self {
+            OutputMode::Stream => None,
+            OutputMode::Gha => Some(Box::new(GitHubActions)),
+            OutputMode::Buildkite => Some(Box::new(Buildkite)),
+            OutputMode::Gitlab => Some(Box::new(GitLab)),
+            OutputMode::Teamcity => Some(Box::new(TeamCity)),
+        }
+    }
+}
+
+/// Formats output lines with title prefix for the terminal.
+pub struct Formatter {
+    terminal: Terminal,
+    ci: Option<Box<dyn CiFormatter>>,
+    /// Title of the process output section currently open.
+    section: Option<String>,
+    /// Time of the previous line or start of the process by title when timing is enabled.
+    timing: Option<HashMap<String, Instant>>,
+    /// Wrap lines longer than the terminal width with continuation lines indented under the text.
+    wrap: bool,
+    hyperlinker: Option<Hyperlinker>,
+    correlator: Option<Correlator>,
+    /// Titles of processes separated with a rule from the output of their previous run.
+    restart_rules: HashSet<String>,
+    /// Icons shown before the title by title of the process.
+    icons: HashMap<String, String>,
+    /// Origins of the remote processes shown before the title by title of the process.
+    origins: HashMap<String, String>,
+    progress: Option<StartupProgress>,
+    theme: Theme,
+}
+
+impl Formatter {
+    pub fn new(terminal: Terminal, mode: OutputMode) -> Self {
+        Self {
+            terminal,
+            ci: mode.ci_formatter(),
+            section: None,
+            timing: None,
+            wrap: false,
+            hyperlinker: None,
+            correlator: None,
+            restart_rules: HashSet::new(),
+            icons: HashMap::new(),
+            origins: HashMap::new(),
+            progress: None,
+            theme: Theme::default(),
+        }
+    }
+
+    /// Prefix lines with time elapsed since the previous line of the same process.
+    pub fn with_timing(mut self, timing: bool) -> Self {
+        self.timing = timing.then(HashMap::new);
+        self
+    }
+
+    /// Wrap long lines to the terminal width indenting continuation lines after the title prefix.
+    pub fn with_wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Make file references clickable in terminals supporting colors.
+    pub fn with_hyperlinks(mut self, hyperlinks: Option<&Hyperlinks>) -> Self {
+        self.hyperlinker = hyperlinks
+            .filter(|_| self.terminal.colors != ColorSupport::None)
+            .map(Hyperlinker::new);
+        self
+    }
+
+    /// Color correlation ids of the lines in terminals supporting colors.
+    pub fn with_correlation(mut self, correlation: Option<&Correlation>) -> Self {
+        self.correlator = correlation
+            .filter(|_| self.terminal.colors != ColorSupport::None)
+            .map(|correlation| Correlator::new(correlation, self.terminal.colors));
+        self
+    }
+
+    /// Separate output of restarted processes from the output of their previous run with a rule.
+    pub fn with_restart_rules(mut self, titles: HashSet<String>) -> Self {
+        self.restart_rules = titles;
+        self
+    }
+
+    /// Show icons before the titles in terminals able to show them.
+    pub fn with_icons(mut self, icons: HashMap<String, String>) -> Self {
+        if self.terminal.unicode {
+            self.icons = icons;
+        }
+        self
+    }
+
+    /// Show origins of the remote processes before the titles e.g. `[ prod:api ]`.
+    pub fn with_origins(mut self, origins: HashMap<String, String>) -> Self {
+        self.origins = origins;
+        self
+    }
+
+    /// Color the output with the theme instead of the default dark theme.
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Show status of starting processes on a line updated in place in interactive terminals.
+    pub fn with_startup_progress(mut self, progress: StartupProgress) -> Self {
+        if self.terminal.interactive && self.ci.is_none() {
+            self.progress = Some(progress.with_unicode(self.terminal.unicode));
+        }
+        self
+    }
+
+    /// Status line of starting processes after the event cut to the terminal width, `None` once
+    /// every process has started.
+    pub fn status(&mut self, event: Option<&Event>) -> Option<String> {
+        let progress = self.progress.as_mut()?;
+        if let Some(event) = event {
+            progress.record(event);
+        }
+
+        let Some(line) = progress.line() else {
+            self.progress = None;
+            return None;
+        };
+        Some(match self.terminal.width {
+            // cursor stays on the line only when it is not filled
+            Some(width) => line.chars().take(width.saturating_sub(1)).collect(),
+            None => line,
+        })
+    }
+
+    /// Format line of a process for the output.
+    pub fn format(&mut self, line: &Line) -> String {
+        let mut output = String::new();
+        if self.ci.is_some() && self.section.as_deref() != Some(&line.title) {
+            output.push_str(&self.finish());
+            if let Some(ci) = &self.ci {
+                output.push_str(&ci.open_section(&line.title));
+            }
+            self.section = Some(line.title.clone());
+        }
+        let delta = self.timing.as_mut().map(|timing| {
+            let now = Instant::now();
+            let delta = timing
+                .insert(line.title.clone(), now)
+                .map(|previous| now - previous);
+            format_delta(delta)
+        });
+        output.push_str(&self.prefixed(line, delta.as_deref()));
+
+        output
+    }
+
+    /// Format lifecycle event for the output if it is shown in current output mode.
+    pub fn format_event(&mut self, event: &Event) -> Option<String> {
+        if let (Some(timing), Event::Started { title, .. }) = (self.timing.as_mut(), event) {
+            timing.insert(title.clone(), Instant::now());
+        }
+
+        if let Event::Restarted { title, .. } = event {
+            if self.ci.is_none() && self.restart_rules.contains(title) {
+                return Some(self.rule(title));
+            }
+        }
+
+        let (title, message) = match event {
+            Event::Exited {
+                title,
+                code,
+                success: false,
+                ..
+            } => (
+                title,
+                match code {
+                    Some(code) => format!("process exited with code {code}"),
+                    None => "process was terminated by signal".to_string(),
+                },
+            ),
+            Event::Failed { title, error } => (title, error.clone()),
+            Event::Blocked { title, dependency } => {
+                (title, format!("blocked by dependency {dependency}"))
+            }
+            _ => return None,
+        };
+
+        let finish = self.finish();
+        self.ci
+            .as_ref()
+            .map(|ci| format!("{finish}{}", ci.error(title, &message)))
+    }
+
+    /// Close output section left open.
+    pub fn finish(&mut self) -> String {
+        match (&self.ci, self.section.take()) {
+            (Some(ci), Some(title)) => ci.close_section(&title),
+            _ => String::new(),
+        }
+    }
+
+    /// Format line with colored title prefix and line ending.
+    fn prefixed(&self, line: &Line, delta: Option<&str>) -> String {
+        let label = self.label(&line.title);
+        let origin = self.origins.get(&line.title);
+        let prefix = match origin {
+            Some(origin) => format!("[ {origin}:{label} ] "),
+            None => format!("[ {label} ] "),
+        };
+        let mut indent = display_width(&prefix) + 1;
+        let prefix = match self.color(line.stream) {
+            Some(color) => {
+                let style = Style::new().on(color);
+                let style = match self.text_color(self.theme.prefix_text) {
+                    Some(text) => style.fg(text),
+                    None => style,
+                };
+                match (origin, self.text_color(self.theme.origin)) {
+                    (Some(origin), Some(origin_color)) => format!(
+                        "{}{}{}",
+                        style.paint("[ "),
+                        style.fg(origin_color).paint(origin),
+                        style.paint(format!(":{label} ] "))
+                    ),
+                    _ => style.paint(prefix).to_string(),
+                }
+            }
+            None => prefix,
+        };
+        let prefix = match delta {
+            Some(delta) => {
+                indent += 8;
+                format!("{prefix} {delta:>7} ")
+            }
+            None => format!("{prefix} "),
+        };
+
+        let text = match &self.correlator {
+            Some(correlator) => correlator.highlight(&line.text),
+            None => line.text.as_str().into(),
+        };
+        let text = match &self.hyperlinker {
+            Some(hyperlinker) => hyperlinker.link(&text).into_owned().into(),
+            None => text,
+        };
+        let text_width = self
+            .terminal
+            .width
+            .filter(|_| self.wrap)
+            .map(|width| width.saturating_sub(indent).max(MIN_TITLE_WIDTH));
+        let text_color = match line.stream {
+            Stream::Stderr => self.text_color(self.theme.stderr_text),
+            Stream::Stdout | Stream::Notice => None,
+        };
+        let paint = |part: &str| match text_color {
+            Some(color) => color.paint(part).to_string(),
+            None => part.to_string(),
+        };
+        match text_width {
+            Some(text_width) => wrap(&text, text_width)
+                .into_iter()
+                .enumerate()
+                .map(|(index, part)| match index {
+                    0 => format!("{prefix}{}\n", paint(part)),
+                    _ => format!("{:indent$}{}\n", "", paint(part)),
+                })
+                .collect(),
+            None => format!("{prefix}{}\n", paint(&text)),
+        }
+    }
+
+    /// Full width rule prefixed with the title.
+    fn rule(&self, title: &str) -> String {
+        let label = match self.origins.get(title) {
+            Some(origin) => format!("{origin}:{}", self.label(title)),
+            None => self.label(title),
+        };
+        let text = rule(None, display_width(&label), self.terminal.width);
+        let line = Line::new(title, Stream::Notice, text);
+
+        self.prefixed(&line, None)
+    }
+
+    /// Title with the icon of the process.
+    fn label(&self, title: &str) -> String {
+        match self.icons.get(title) {
+            Some(icon) => format!("{icon} {}", self.title(title)),
+            None => self.title(title).into_owned(),
+        }
+    }
+
+    /// Truncate title to fit a quarter of the terminal width.
+    fn title<'a>(&self, title: &'a str) -> std::borrow::Cow<'a, str> {
+        let max_width = self
+            .terminal
+            .width
+            .map(|width| (width / 4).max(MIN_TITLE_WIDTH));
+
+        match max_width {
+            Some(max_width) if title.chars().count() > max_width => {
+                let truncated = title.chars().take(max_width - 1).collect::<String>();
+                format!("{truncated}…").into()
+            }
+            _ => title.into(),
+        }
+    }
+
+    fn color(&self, stream: Stream) -> Option<Color> {
+        match stream {
+            Stream::Stdout => self.theme.stdout,
+            Stream::Stderr => self.theme.stderr,
+            Stream::Notice => self.theme.notice,
+        }
+        .color(self.terminal.colors)
+    }
+
+    fn text_color(&self, color: Option<ThemeColor>) -> Option<Color> {
+        color?.color(self.terminal.colors)
+    }
+}
+
+/// Rule with optional label filling the terminal width after the prefix of title with
+/// `title_width` characters.
+pub fn rule(label: Option<&str>, title_width: usize, terminal_width: Option<usize>) -> String {
+    let width = terminal_width
+        .unwrap_or(DEFAULT_RULE_WIDTH)
+        .saturating_sub(title_width + 6)
+        .max(MIN_TITLE_WIDTH);
+
+    match label {
+        Some(label) => {
+            let label = format!("── {label} ");
+            let fill = width.saturating_sub(label.chars().count());
+            format!("{label}{}", "─".repeat(fill))
+        }
+        None => "─".repeat(width),
+    }
+}
+
+/// Columns the text takes in the terminal. Emoji take two columns and the joiners and variation
+/// selectors combining them none.
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c {
+            '\u{200d}' | '\u{fe00}'..='\u{fe0f}' => 0,
+            '\u{1f000}'.. => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+/// Split text to parts of at most `width` visible characters. ANSI escape sequences and OSC
+/// hyperlinks do not take space and are never split.
+fn wrap(text: &str, width: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut visible = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        if c == '\x1b' {
+            if chars.next_if(|(_, c)| *c == '[').is_some() {
+                while chars.next_if(|(_, c)| !('@'..='~').contains(c)).is_some() {}
+                chars.next();
+            } else if chars.next_if(|(_, c)| *c == ']').is_some() {
+                // terminated by BEL or ST, of which the trailing `\` is consumed here
+                while chars.next_if(|(_, c)| *c != '\x07' && *c != '\x1b').is_some() {}
+                if chars.next().map(|(_, c)| c) == Some('\x1b') {
+                    chars.next();
+                }
+            }
+            continue;
+        }
+        if visible == width {
+            parts.push(&text[start..index]);
+            start = index;
+            visible = 0;
+        }
+        visible += 1;
+    }
+    parts.push(&text[start..]);
+
+    parts
+}
+
+/// Format elapsed time as `+350ms` under a second and `+1.2s` otherwise.
+fn format_delta(delta: Option<Duration>) -> String {
+    match delta {
+        Some(delta) if delta < Duration::from_secs(1) => format!("+{}ms", delta.as_millis()),
+        Some(delta) => format!("+{:.1}s", delta.as_secs_f64()),
+        None => "+0ms".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_splits_to_width() {
+        assert_eq!(wrap("abcdefgh", 3), ["abc", "def", "gh"]);
+        assert_eq!(wrap("abc", 3), ["abc"]);
+        assert_eq!(wrap("", 3), [""]);
+    }
+
+    #[test]
+    fn wrap_does_not_count_or_split_escape_sequences() {
+        assert_eq!(
+            wrap("\x1b[31mabcd\x1b[0mef", 3),
+            ["\x1b[31mabc", "d\x1b[0mef"]
+        );
+        let link = "\x1b]8;;https://example.com\x1b\\ab\x1b]8;;\x07cd";
+        assert_eq!(
+            wrap(link, 2),
+            ["\x1b]8;;https://example.com\x1b\\ab\x1b]8;;\x07", "cd"]
+        );
+    }
+
+    #[test]
+    fn emoji_take_two_columns_and_their_joiners_none() {
+        assert_eq!(display_width("api"), 3);
+        assert_eq!(display_width("🚀"), 2);
+        assert_eq!(display_width("⚙\u{fe0f}"), 1);
+        assert_eq!(display_width("👩\u{200d}💻 dev"), 8);
+    }
+}
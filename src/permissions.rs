@@ -0,0 +1,228 @@
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::{
+    fs::{File, OpenOptions},
+    io,
+};
+
+/// Permissions of a file written by a sink e.g. one logging output with secrets on a shared
+/// machine.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FilePermissions {
+    /// Permission bits of the file e.g. `0600`.
+    pub mode: Option<FileMode>,
+    /// User and optionally group owning the file, as names or ids e.g. `deploy` or `deploy:adm`.
+    pub owner: Option<String>,
+}
+
+impl FilePermissions {
+    /// Open the file for appending. A new file is created with the mode so it is never accessible
+    /// to others, and the mode and owner are set exactly regardless of the umask and of the file
+    /// existing already.
+    pub async fn append(&self, path: &Path) -> io::Result<File> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            options.mode(mode.0);
+        }
+        let file = options.open(path).await?;
+
+        if let Some(mode) = self.mode {
+            set_mode(&file, mode).await?;
+        }
+        if let Some(owner) = &self.owner {
+            set_owner(&file, owner)?;
+        }
+
+        Ok(file)
+    }
+}
+
+#[cfg(unix)]
+async fn set_mode(file: &File, mode: FileMode) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    file.set_permissions(std::fs::Permissions::from_mode(mode.0))
+        .await
+}
+
+#[cfg(not(unix))]
+async fn set_mode(_: &File, _: FileMode) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file mode is only supported on unix",
+    ))
+}
+
+#[cfg(unix)]
+fn set_owner(file: &File, owner: &str) -> io::Result<()> {
+    let (user, group) = match owner.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (owner, None),
+    };
+    let uid = Some(user)
+        .filter(|user| !user.is_empty())
+        .map(uid)
+        .transpose()?;
+    let gid = group.map(gid).transpose()?;
+
+    std::os::unix::fs::fchown(file, uid, gid)
+}
+
+#[cfg(not(unix))]
+fn set_owner(_: &File, _: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file owner is only supported on unix",
+    ))
+}
+
+/// Id of the user by its name or the id itself.
+#[cfg(unix)]
+fn uid(user: &str) -> io::Result<u32> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    let name = std::ffi::CString::new(user)?;
+    // SAFETY: getpwnam reads the null terminated name, the entry is read before any other call
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    match passwd.is_null() {
+        true => Err(unknown("user", user)),
+        // SAFETY: the entry is not null
+        false => Ok(unsafe { (*passwd).pw_uid }),
+    }
+}
+
+/// Id of the group by its name or the id itself.
+#[cfg(unix)]
+fn gid(group: &str) -> io::Result<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let name = std::ffi::CString::new(group)?;
+    // SAFETY: getgrnam reads the null terminated name, the entry is read before any other call
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    match entry.is_null() {
+        true => Err(unknown("group", group)),
+        // SAFETY: the entry is not null
+        false => Ok(unsafe { (*entry).gr_gid }),
+    }
+}
+
+#[cfg(unix)]
+fn unknown(kind: &str, name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("no such {kind} {name}"))
+}
+
+/// Permission bits given in octal e.g. `0600` or `0o640`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMode(pub u32);
+
+/// Largest mode of a file written by a sink, special bits are not allowed.
+const MAX_MODE: u32 = 0o777;
+
+impl FromStr for FileMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        let mode = mode.trim();
+        let digits = mode.strip_prefix("0o").unwrap_or(mode);
+        // from_str_radix takes a sign as well
+        Some(digits)
+            .filter(|digits| digits.bytes().all(|digit| matches!(digit, b'0'..=b'7')))
+            .and_then(|digits| u32::from_str_radix(digits, 8).ok())
+            .filter(|mode| *mode <= MAX_MODE)
+            .map(FileMode)
+            .ok_or_else(|| format!("invalid mode {mode}, expected octal e.g. 0600 or 0640"))
+    }
+}
+
+impl Display for FileMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+impl Serialize for FileMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for FileMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Mode {
+            Number(u64),
+            Text(String),
+        }
+
+        // YAML reads `0600` as text but `600` and `0o600` as numbers, which cannot be told apart
+        // from each other once read
+        match Mode::deserialize(deserializer)? {
+            Mode::Number(mode) => Err(serde::de::Error::custom(format!(
+                "mode {mode} is read as a number, give it in octal as text e.g. \"0600\""
+            ))),
+            Mode::Text(mode) => mode.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(mode: &str) -> Result<u32, String> {
+        mode.parse::<FileMode>().map(|mode| mode.0)
+    }
+
+    #[test]
+    fn modes_are_parsed_as_octal() {
+        assert_eq!(parse("0600"), Ok(0o600));
+        assert_eq!(parse("640"), Ok(0o640));
+        assert_eq!(parse("0o640"), Ok(0o640));
+        assert_eq!(parse(" 0777 "), Ok(0o777));
+        assert_eq!(parse("0"), Ok(0));
+        assert_eq!(FileMode(0o600).to_string(), "0600");
+    }
+
+    #[test]
+    fn invalid_digits_and_out_of_range_modes_are_rejected() {
+        for mode in [
+            "",
+            "0o",
+            "0800",
+            "0x1ff",
+            "rw-r--r--",
+            "+600",
+            "-600",
+            "1777",
+            "4755",
+        ] {
+            assert_eq!(
+                parse(mode),
+                Err(format!(
+                    "invalid mode {mode}, expected octal e.g. 0600 or 0640"
+                )),
+                "{mode}"
+            );
+        }
+    }
+
+    #[test]
+    fn modes_read_by_yaml_as_numbers_are_rejected() {
+        let mode = |yaml: &str| serde_yaml::from_str::<FileMode>(yaml).map_err(|e| e.to_string());
+
+        assert_eq!(mode("0600"), Ok(FileMode(0o600)));
+        assert_eq!(mode("'0o640'"), Ok(FileMode(0o640)));
+        assert!(mode("400")
+            .unwrap_err()
+            .starts_with("mode 400 is read as a number"));
+        assert!(mode("0o600")
+            .unwrap_err()
+            .starts_with("mode 384 is read as a number"));
+    }
+}
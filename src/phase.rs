@@ -0,0 +1,246 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{
+    event::{EndedBy, Event},
+    shutdown::Shutdown,
+};
+
+/// Processes started together once the previous phase is complete e.g. setup before services.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PhaseConfig {
+    pub name: String,
+    /// Titles of the processes of the phase.
+    pub processes: Vec<String>,
+    #[serde(default)]
+    pub until: PhaseUntil,
+}
+
+/// When a process is complete for its phase.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseUntil {
+    /// Process has exited successfully.
+    #[default]
+    Finished,
+    /// Process has started, after its `wait_for` has passed.
+    Ready,
+}
+
+/// Phases of the config advanced by the events of their processes. Processes of a phase are
+/// started once every process of the previous phase is complete, and are not started at all
+/// when a process of an earlier phase fails.
+pub struct Phases {
+    phases: Vec<Phase>,
+    current: usize,
+}
+
+struct Phase {
+    name: String,
+    until: PhaseUntil,
+    titles: Vec<String>,
+    pending: HashSet<String>,
+    /// `None` until the phase starts, then whether it started or the process of an earlier phase
+    /// which failed.
+    gate: watch::Sender<Option<Result<(), String>>>,
+}
+
+/// Gate of a process opened when its phase starts.
+pub struct Gate(watch::Receiver<Option<Result<(), String>>>);
+
+impl Phases {
+    /// Phases of the processes with the titles, processes not running are left out of them.
+    pub fn new(phases: &[PhaseConfig], titles: &[String]) -> Self {
+        Self {
+            phases: phases
+                .iter()
+                .map(|phase| {
+                    let titles = phase
+                        .processes
+                        .iter()
+                        .filter(|title| titles.contains(title))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    Phase {
+                        name: phase.name.clone(),
+                        until: phase.until,
+                        pending: titles.iter().cloned().collect(),
+                        titles,
+                        gate: watch::Sender::new(None),
+                    }
+                })
+                .collect(),
+            current: 0,
+        }
+    }
+
+    /// Gate of the process, `None` when it is not in any phase.
+    pub fn gate(&self, title: &str) -> Option<Gate> {
+        self.phases
+            .iter()
+            .find(|phase| phase.titles.iter().any(|phase_title| phase_title == title))
+            .map(|phase| Gate(phase.gate.subscribe()))
+    }
+
+    /// Start the first phase, returning labels of the separators to write.
+    pub fn start(&mut self) -> Vec<String> {
+        match self.phases.first() {
+            Some(phase) => {
+                phase.gate.send_replace(Some(Ok(())));
+                let mut labels = vec![format!("phase {} started", phase.name)];
+                labels.extend(self.advance());
+                labels
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Track the processes of the current phase from the event, returning labels of the
+    /// separators to write when phases complete or start.
+    pub fn record(&mut self, event: &Event) -> Vec<String> {
+        let Some(phase) = self.phases.get_mut(self.current) else {
+            return Vec::new();
+        };
+        if !phase.pending.contains(event.title()) {
+            return Vec::new();
+        }
+
+        match event {
+            Event::Started { title, .. } if phase.until == PhaseUntil::Ready => {
+                phase.pending.remove(title);
+            }
+            // process stopped on purpose is complete, restarted one runs again
+            Event::Exited {
+                title,
+                success: true,
+                ..
+            }
+            | Event::Exited {
+                title,
+                ended_by: Some(EndedBy::Stop),
+                ..
+            } => {
+                phase.pending.remove(title);
+            }
+            Event::Exited {
+                ended_by: Some(EndedBy::Restart),
+                ..
+            } => (),
+            Event::Exited { title, .. }
+            | Event::Failed { title, .. }
+            | Event::Blocked { title, .. } => {
+                let label = format!("phase {} failed", phase.name);
+                for phase in &self.phases[self.current + 1..] {
+                    phase.gate.send_replace(Some(Err(title.clone())));
+                }
+                self.current = self.phases.len();
+                return vec![label];
+            }
+            // skipped process is complete with its successful exit following
+            Event::Started { .. }
+            | Event::Restarted { .. }
+            | Event::Deferred { .. }
+            | Event::Skipped { .. } => (),
+        }
+
+        self.advance()
+    }
+
+    /// Complete phases without pending processes and start the next ones.
+    fn advance(&mut self) -> Vec<String> {
+        let mut labels = Vec::new();
+        while let Some(phase) = self
+            .phases
+            .get(self.current)
+            .filter(|phase| phase.pending.is_empty())
+        {
+            labels.push(format!("phase {} completed", phase.name));
+            self.current += 1;
+            if let Some(next) = self.phases.get(self.current) {
+                next.gate.send_replace(Some(Ok(())));
+                labels.push(format!("phase {} started", next.name));
+            }
+        }
+
+        labels
+    }
+}
+
+impl Gate {
+    /// Wait for the phase of the process to start. Returns `Ok(false)` when the process is stopped
+    /// before that, and the process which failed an earlier phase as the error.
+    pub async fn wait(mut self, mut shutdown: watch::Receiver<Shutdown>) -> Result<bool, String> {
+        tokio::select! {
+            started = self.0.wait_for(Option::is_some) => match started.as_deref() {
+                Ok(Some(Err(failed))) => Err(failed.clone()),
+                Ok(_) => Ok(true),
+                Err(_) => Ok(false),
+            },
+            _ = shutdown.wait_for(|state| *state != Shutdown::Running) => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phases() -> Phases {
+        let config: Vec<PhaseConfig> = serde_yaml::from_str(
+            "
+- { name: setup, processes: [migrate] }
+- { name: services, processes: [api] }
+",
+        )
+        .unwrap();
+        Phases::new(&config, &["migrate".to_string(), "api".to_string()])
+    }
+
+    fn exited(success: bool, ended_by: Option<EndedBy>) -> Event {
+        Event::Exited {
+            title: "migrate".to_string(),
+            code: success.then_some(0),
+            success,
+            ended_by,
+        }
+    }
+
+    #[test]
+    fn failure_closes_gates_of_later_phases() {
+        let mut phases = phases();
+        let gate = phases.gate("api").unwrap();
+        phases.start();
+
+        assert_eq!(phases.record(&exited(false, None)), ["phase setup failed"]);
+        assert_eq!(gate.0.borrow().clone(), Some(Err("migrate".to_string())));
+    }
+
+    #[test]
+    fn process_stopped_on_purpose_completes_its_phase() {
+        let mut phases = phases();
+        let gate = phases.gate("api").unwrap();
+        phases.start();
+
+        assert_eq!(
+            phases.record(&exited(false, Some(EndedBy::Stop))),
+            ["phase setup completed", "phase services started"]
+        );
+        assert_eq!(gate.0.borrow().clone(), Some(Ok(())));
+    }
+
+    #[test]
+    fn restarted_process_does_not_complete_or_fail_its_phase() {
+        let mut phases = phases();
+        phases.start();
+
+        assert!(phases
+            .record(&exited(false, Some(EndedBy::Restart)))
+            .is_empty());
+        assert_eq!(
+            phases.record(&exited(true, None)),
+            ["phase setup completed", "phase services started"]
+        );
+    }
+}
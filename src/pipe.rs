@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use tokio::sync::broadcast;
+
+use crate::{
+    config::WatchProcess,
+    event::{Line, Stream},
+};
+
+/// Lines buffered for a consumer which is not reading its stdin fast enough.
+const PIPE_BUFFER: usize = 1024;
+
+/// Stdout of processes connected to stdin of other processes with `pipe_to`.
+#[derive(Debug, Default)]
+pub struct Pipes {
+    /// Stdin of consumers by title.
+    consumers: HashMap<String, broadcast::Sender<String>>,
+    /// Title of the consumer by title of the producer.
+    producers: HashMap<String, String>,
+}
+
+impl Pipes {
+    pub fn new(processes: &[WatchProcess]) -> Self {
+        let mut pipes = Pipes::default();
+        for process in processes {
+            if let Some(consumer) = &process.pipe_to {
+                pipes
+                    .consumers
+                    .entry(consumer.clone())
+                    .or_insert_with(|| broadcast::channel(PIPE_BUFFER).0);
+                pipes
+                    .producers
+                    .insert(process.title.clone(), consumer.clone());
+            }
+        }
+
+        pipes
+    }
+
+    /// Stdin of the process if other processes are piped to it.
+    pub fn stdin(&self, title: &str) -> Option<broadcast::Receiver<String>> {
+        self.consumers.get(title).map(broadcast::Sender::subscribe)
+    }
+
+    /// Write stdout line of a producer to stdin of its consumer.
+    pub fn write(&self, line: &Line) {
+        if line.stream != Stream::Stdout {
+            return;
+        }
+        let consumer = self
+            .producers
+            .get(&line.title)
+            .and_then(|consumer| self.consumers.get(consumer));
+        if let Some(consumer) = consumer {
+            // consumer might not be running to receive the line
+            let _ = consumer.send(format!("{}\n", line.text));
+        }
+    }
+}
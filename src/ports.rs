@@ -0,0 +1,132 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    net::TcpListener,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::WatchProcess;
+
+/// Port given to the process in the env variable of `ports`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PortAllocation {
+    /// Free port chosen by the operating system at startup.
+    Auto,
+}
+
+/// Ports allocated for `ports` of the processes by title.
+#[derive(Debug, Default)]
+pub struct Ports {
+    allocated: HashMap<String, Vec<(String, u16)>>,
+    /// `depends_on` of the processes by title.
+    dependencies: HashMap<String, Vec<String>>,
+}
+
+impl Ports {
+    /// Allocate a free port for each `auto` port of the processes. Listeners are held until
+    /// every port is allocated so that the ports differ, then closed before any process is
+    /// started: the ports are not reserved, and another program may take one before the process
+    /// binds it.
+    pub fn allocate(processes: &[WatchProcess]) -> io::Result<Self> {
+        let mut listeners = Vec::new();
+        let mut allocated = HashMap::new();
+        let mut dependencies = HashMap::new();
+        for process in processes {
+            dependencies.insert(process.title.clone(), process.depends_on.clone());
+            let mut ports = Vec::new();
+            for (name, PortAllocation::Auto) in &process.ports {
+                let listener = TcpListener::bind(("127.0.0.1", 0))?;
+                ports.push((name.clone(), listener.local_addr()?.port()));
+                listeners.push(listener);
+            }
+            ports.sort();
+            allocated.insert(process.title.clone(), ports);
+        }
+
+        Ok(Self {
+            allocated,
+            dependencies,
+        })
+    }
+
+    /// Ports of the process itself.
+    pub fn own(&self, title: &str) -> &[(String, u16)] {
+        self.allocated
+            .get(title)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Ports of the process followed by ports of the processes it depends on, directly or through
+    /// other processes, nearest first.
+    pub fn env(&self, process: &WatchProcess) -> Vec<(String, u16)> {
+        let mut ports = self.own(&process.title).to_vec();
+        let mut visited = HashSet::from([process.title.as_str()]);
+        let mut queue = process
+            .depends_on
+            .iter()
+            .map(String::as_str)
+            .collect::<VecDeque<_>>();
+        while let Some(title) = queue.pop_front() {
+            if !visited.insert(title) {
+                continue;
+            }
+            ports.extend_from_slice(self.own(title));
+            if let Some(depends_on) = self.dependencies.get(title) {
+                queue.extend(depends_on.iter().map(String::as_str));
+            }
+        }
+
+        ports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processes(yaml: &str) -> Vec<WatchProcess> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn ports_are_given_to_transitive_dependents() {
+        let processes = processes(
+            "
+- { title: db, cmd: db, ports: { DB_PORT: auto } }
+- { title: api, cmd: api, ports: { API_PORT: auto }, depends_on: [db] }
+- { title: web, cmd: web, depends_on: [api] }
+- { title: other, cmd: other, ports: { OTHER_PORT: auto } }
+",
+        );
+        let ports = Ports::allocate(&processes).unwrap();
+
+        let names = |process: &WatchProcess| {
+            ports
+                .env(process)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&processes[0]), ["DB_PORT"]);
+        assert_eq!(names(&processes[1]), ["API_PORT", "DB_PORT"]);
+        assert_eq!(names(&processes[2]), ["API_PORT", "DB_PORT"]);
+        assert_eq!(names(&processes[3]), ["OTHER_PORT"]);
+        assert_eq!(ports.env(&processes[2])[1], ports.own("db")[0]);
+    }
+
+    #[test]
+    fn allocated_ports_differ() {
+        let processes = processes("- { title: a, cmd: a, ports: { A: auto, B: auto, C: auto } }");
+        let ports = Ports::allocate(&processes).unwrap();
+
+        let allocated = ports
+            .own("a")
+            .iter()
+            .map(|(_, port)| *port)
+            .collect::<HashSet<_>>();
+        assert_eq!(allocated.len(), 3);
+    }
+}
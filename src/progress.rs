@@ -0,0 +1,105 @@
+use crate::{config::WatchProcess, event::Event};
+
+/// Status of the processes shown on a single line updated in place until every process has
+/// started e.g. `starting: db ✓, redis …, api waiting on db`.
+pub struct StartupProgress {
+    processes: Vec<Starting>,
+    unicode: bool,
+}
+
+struct Starting {
+    title: String,
+    depends_on: Vec<String>,
+    state: State,
+}
+
+#[derive(PartialEq, Eq)]
+enum State {
+    Starting,
+    Started,
+    Failed,
+    /// Waiting for the machine to have headroom, which does not hold the status line.
+    Deferred,
+}
+
+impl StartupProgress {
+    pub fn new(processes: &[WatchProcess]) -> Self {
+        Self {
+            processes: processes
+                .iter()
+                .map(|process| Starting {
+                    title: process.title.clone(),
+                    depends_on: process.depends_on.clone(),
+                    state: State::Starting,
+                })
+                .collect(),
+            unicode: false,
+        }
+    }
+
+    /// Mark started processes with glyphs instead of words in terminals able to show them.
+    pub fn with_unicode(mut self, unicode: bool) -> Self {
+        self.unicode = unicode;
+        self
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        let state = match event {
+            Event::Started { .. }
+            | Event::Exited { .. }
+            | Event::Restarted { .. }
+            | Event::Skipped { .. } => State::Started,
+            Event::Failed { .. } | Event::Blocked { .. } => State::Failed,
+            Event::Deferred { .. } => State::Deferred,
+        };
+        if let Some(process) = self.processes.iter_mut().find(|process| {
+            process.title == event.title()
+                && matches!(process.state, State::Starting | State::Deferred)
+        }) {
+            process.state = state;
+        }
+    }
+
+    /// Status line without line ending, `None` once no process is starting.
+    pub fn line(&self) -> Option<String> {
+        if self
+            .processes
+            .iter()
+            .all(|process| process.state != State::Starting)
+        {
+            return None;
+        }
+
+        let (started, failed, starting) = match self.unicode {
+            true => ("✓", "✗", "…"),
+            false => ("ok", "failed", "..."),
+        };
+        let statuses = self
+            .processes
+            .iter()
+            .map(|process| {
+                let waiting_on = process
+                    .depends_on
+                    .iter()
+                    .filter(|dependency| {
+                        self.processes.iter().any(|other| {
+                            other.title == **dependency && other.state == State::Starting
+                        })
+                    })
+                    .map(String::as_str)
+                    .collect::<Vec<_>>();
+                match process.state {
+                    State::Started => format!("{} {started}", process.title),
+                    State::Failed => format!("{} {failed}", process.title),
+                    State::Deferred => format!("{} deferred", process.title),
+                    State::Starting if !waiting_on.is_empty() => {
+                        format!("{} waiting on {}", process.title, waiting_on.join(", "))
+                    }
+                    State::Starting => format!("{} {starting}", process.title),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Some(format!("starting: {}", statuses.join(", ")))
+    }
+}
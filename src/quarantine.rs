@@ -0,0 +1,82 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{oneshot, watch};
+
+use crate::{event::Line, shutdown::Shutdown};
+
+/// Amount of the latest output lines of each process shown with `o<number>`.
+const SCROLLBACK_LINES: usize = 200;
+
+/// What to do with a quarantined process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Run the process again.
+    Retry,
+    /// Treat the process as failed, stopping its dependents.
+    Skip,
+}
+
+/// Processes held after failing with `--interactive-failures` until the user decides to retry
+/// or skip them.
+#[derive(Debug, Clone, Default)]
+pub struct Quarantine {
+    held: Arc<Mutex<HashMap<String, oneshot::Sender<Decision>>>>,
+}
+
+impl Quarantine {
+    /// Hold the failed process until it is decided or watchmux is stopping. Stopping is decided
+    /// as skip.
+    pub async fn hold(&self, title: &str, mut shutdown: watch::Receiver<Shutdown>) -> Decision {
+        let (tx, rx) = oneshot::channel();
+        self.held
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .insert(title.to_string(), tx);
+
+        let decision = tokio::select! {
+            decision = rx => decision.unwrap_or(Decision::Skip),
+            _ = shutdown.wait_for(|state| *state != Shutdown::Running) => Decision::Skip,
+        };
+        self.held
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .remove(title);
+
+        decision
+    }
+
+    /// Decide on the process, `false` when it is not quarantined.
+    pub fn decide(&self, title: &str, decision: Decision) -> bool {
+        self.held
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .remove(title)
+            .is_some_and(|held| held.send(decision).is_ok())
+    }
+}
+
+/// Latest output lines of the processes by title.
+#[derive(Debug, Default)]
+pub struct Scrollback {
+    lines: HashMap<String, VecDeque<Line>>,
+}
+
+impl Scrollback {
+    pub fn record(&mut self, line: &Line) {
+        let lines = self.lines.entry(line.title.clone()).or_default();
+        if lines.len() == SCROLLBACK_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line.clone());
+    }
+
+    pub fn lines(&self, title: &str) -> Vec<Line> {
+        self.lines
+            .get(title)
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt},
+    time,
+};
+
+use crate::config::Multiline;
+
+/// Time partial line without line ending is waited for more output before it is flushed.
+const PARTIAL_LINE_IDLE: Duration = Duration::from_millis(500);
+/// Time a multi-line block is waited for more continuation lines before it is complete.
+const BLOCK_IDLE: Duration = Duration::from_millis(100);
+/// Maximum amount of lines in a block so endless continuation lines are still forwarded.
+const MAX_BLOCK_LINES: usize = 1000;
+
+/// Reads lines of process output. Partial line is returned when no more output arrives within
+/// [`PARTIAL_LINE_IDLE`] so prompts without line ending are shown. Both `\n` and `\r\n` end a
+/// line, and bare `\r` overwrites the line like in a terminal so only text after it is kept.
+pub struct LineReader<T> {
+    inner: T,
+    buffer: Vec<u8>,
+    /// Start and continuation patterns of multi-line blocks.
+    multiline: Option<Multiline>,
+    /// Line read after the previous block which did not continue it.
+    pending: Option<String>,
+}
+
+impl<T: AsyncRead + Unpin> LineReader<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            buffer: Vec::new(),
+            multiline: None,
+            pending: None,
+        }
+    }
+
+    /// Read lines matching the start pattern followed by lines matching the continuation pattern as a single block.
+    pub fn with_multiline(mut self, multiline: Option<&Multiline>) -> Self {
+        self.multiline = multiline.cloned();
+        self
+    }
+
+    /// Next block of lines, which is a single line unless it starts a multi-line block, or `None`
+    /// when the output has ended.
+    pub async fn next_block(&mut self) -> io::Result<Option<Vec<String>>> {
+        let line = match self.pending.take() {
+            Some(line) => line,
+            None => match self.next_line().await? {
+                Some(line) => line,
+                None => return Ok(None),
+            },
+        };
+        let continuation = match &self.multiline {
+            Some(multiline) if multiline.start_pattern.is_match(&line) => {
+                multiline.continuation_pattern.clone()
+            }
+            _ => return Ok(Some(vec![line])),
+        };
+
+        let mut block = vec![line];
+        while block.len() < MAX_BLOCK_LINES {
+            match time::timeout(BLOCK_IDLE, self.next_line()).await {
+                Ok(Ok(Some(line))) if continuation.is_match(&line) => block.push(line),
+                Ok(Ok(Some(line))) => {
+                    self.pending = Some(line);
+                    break;
+                }
+                // end of output and errors are returned on the next read
+                _ => break,
+            }
+        }
+
+        Ok(Some(block))
+    }
+
+    /// Next line without the line ending, or `None` when the output has ended.
+    pub async fn next_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line = self.take(end + 1);
+                return Ok(Some(line));
+            }
+
+            let read = if self.buffer.is_empty() {
+                self.inner.read_buf(&mut self.buffer).await?
+            } else {
+                match time::timeout(PARTIAL_LINE_IDLE, self.inner.read_buf(&mut self.buffer)).await
+                {
+                    Ok(read) => read?,
+                    Err(_) => return Ok(Some(self.take(self.buffer.len()))),
+                }
+            };
+
+            if read == 0 {
+                return Ok((!self.buffer.is_empty()).then(|| self.take(self.buffer.len())));
+            }
+        }
+    }
+
+    /// Take `len` bytes from the buffer as text without the line ending and the text overwritten
+    /// by carriage returns.
+    fn take(&mut self, len: usize) -> String {
+        let bytes = self.buffer.drain(..len).collect::<Vec<_>>();
+        let line = bytes.strip_suffix(b"\n").unwrap_or(&bytes);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        // progress bars redraw the line after carriage return, trailing ones have nothing to show
+        let line = line
+            .rsplit(|byte| *byte == b'\r')
+            .find(|segment| !segment.is_empty())
+            .unwrap_or_default();
+
+        String::from_utf8_lossy(line).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(buffer: &[u8]) -> LineReader<&[u8]> {
+        let mut reader = LineReader::new(&[][..]);
+        reader.buffer = buffer.to_vec();
+        reader
+    }
+
+    #[test]
+    fn take_leaves_rest_of_the_buffer() {
+        let mut reader = reader(b"first\nsecond");
+        assert_eq!(reader.take(6), "first");
+        assert_eq!(reader.take(6), "second");
+        assert!(reader.buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn partial_line_is_returned_when_output_ends() {
+        let mut reader = LineReader::new(&b"line\nprompt> "[..]);
+        assert_eq!(reader.next_line().await.unwrap().as_deref(), Some("line"));
+        assert_eq!(
+            reader.next_line().await.unwrap().as_deref(),
+            Some("prompt> ")
+        );
+        assert_eq!(reader.next_line().await.unwrap(), None);
+    }
+
+    #[test]
+    fn take_strips_crlf_and_keeps_text_after_carriage_return() {
+        assert_eq!(reader(b"line\r\n").take(6), "line");
+        assert_eq!(reader(b" 10%\r 50%\r100%\n").take(15), "100%");
+        assert_eq!(reader(b"done\r\r\n").take(7), "done");
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{
+    dependency::Exports,
+    event::{EndedBy, Event},
+    shutdown::Shutdown,
+};
+
+/// State of another process waited for with `wait_for: { process: db, state: ready }`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessState {
+    /// Process has started, after its `wait_for` has passed, and published the variables it
+    /// exports.
+    #[default]
+    Ready,
+    /// Process has exited successfully.
+    Finished,
+}
+
+/// State a process has reached according to its events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reached {
+    Started,
+    Finished,
+    Failed,
+}
+
+/// States the processes have reached, shared with the processes waiting for them and with the
+/// output held back until the processes are ready.
+#[derive(Debug, Clone)]
+pub struct Readiness {
+    /// Titles of the running processes, others are not waited for.
+    titles: Arc<HashSet<String>>,
+    reached: watch::Sender<HashMap<String, Reached>>,
+    exports: Exports,
+}
+
+impl Readiness {
+    pub fn new(titles: &[String], exports: &Exports) -> Self {
+        Self {
+            titles: Arc::new(titles.iter().cloned().collect()),
+            reached: watch::Sender::new(HashMap::new()),
+            exports: exports.clone(),
+        }
+    }
+
+    /// Track the state of the process of the event.
+    pub fn record(&self, event: &Event) {
+        let reached = match event {
+            Event::Started { .. } | Event::Restarted { .. } | Event::Skipped { .. } => {
+                Reached::Started
+            }
+            // process stopped on purpose is as finished as it gets
+            Event::Exited { success: true, .. }
+            | Event::Exited {
+                ended_by: Some(EndedBy::Stop),
+                ..
+            } => Reached::Finished,
+            Event::Exited { ended_by: None, .. } | Event::Failed { .. } | Event::Blocked { .. } => {
+                Reached::Failed
+            }
+            // process killed to be restarted is started again
+            Event::Exited {
+                ended_by: Some(EndedBy::Restart),
+                ..
+            }
+            | Event::Deferred { .. } => return,
+        };
+        self.reached.send_if_modified(|states| {
+            states.insert(event.title().to_string(), reached) != Some(reached)
+        });
+    }
+
+    /// Whether the process has reached the state, `true` for processes which are not running.
+    pub fn reached(&self, title: &str, state: ProcessState) -> bool {
+        !self.titles.contains(title) || self.is_reached(title, state)
+    }
+
+    /// Wait for the process to reach the state. Returns `Ok(false)` when the waiting process is
+    /// stopped before that, and the process as the error when it fails instead.
+    pub async fn wait(
+        &self,
+        title: &str,
+        state: ProcessState,
+        mut shutdown: watch::Receiver<Shutdown>,
+    ) -> Result<bool, String> {
+        if !self.titles.contains(title) {
+            return Ok(true);
+        }
+        let mut reached = self.reached.subscribe();
+        let titles = [title.to_string()];
+        loop {
+            if self.is_reached(title, state) {
+                return Ok(true);
+            }
+            if self.reached.borrow().get(title) == Some(&Reached::Failed) {
+                return Err(title.to_string());
+            }
+            let exported = self.exports.ready(&titles);
+            tokio::select! {
+                // sender is held by `self` so the channel cannot close while waiting
+                _ = reached.changed() => (),
+                _ = self.exports.wait(&titles), if !exported => (),
+                _ = shutdown.wait_for(|state| *state != Shutdown::Running) => return Ok(false),
+            }
+        }
+    }
+
+    /// Whether the running process has reached the state. Process is ready once it has started
+    /// and published the variables it exports, or has finished.
+    fn is_reached(&self, title: &str, state: ProcessState) -> bool {
+        match (state, self.reached.borrow().get(title)) {
+            (_, Some(Reached::Finished)) => true,
+            (ProcessState::Ready, Some(Reached::Started)) => {
+                self.exports.ready(&[title.to_string()])
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::WatchProcess,
+        event::{Line, Stream},
+    };
+
+    fn processes() -> Vec<WatchProcess> {
+        serde_yaml::from_str(
+            "
+- { title: db, cmd: db }
+- { title: migrate, cmd: migrate }
+- { title: api, cmd: api, exports: [API_URL] }
+",
+        )
+        .unwrap()
+    }
+
+    fn readiness_with(exports: &Exports) -> Readiness {
+        Readiness::new(
+            &["db".to_string(), "migrate".to_string(), "api".to_string()],
+            exports,
+        )
+    }
+
+    fn readiness() -> Readiness {
+        readiness_with(&Exports::new(&processes()))
+    }
+
+    fn started(title: &str) -> Event {
+        Event::Started {
+            title: title.to_string(),
+            pid: None,
+        }
+    }
+
+    fn exited(title: &str, success: bool) -> Event {
+        Event::Exited {
+            title: title.to_string(),
+            code: Some((!success).into()),
+            success,
+            ended_by: None,
+        }
+    }
+
+    fn stopped(title: &str, ended_by: EndedBy) -> Event {
+        Event::Exited {
+            title: title.to_string(),
+            code: None,
+            success: false,
+            ended_by: Some(ended_by),
+        }
+    }
+
+    #[test]
+    fn started_process_is_ready_but_not_finished() {
+        let readiness = readiness();
+        assert!(!readiness.reached("db", ProcessState::Ready));
+
+        readiness.record(&started("db"));
+        assert!(readiness.reached("db", ProcessState::Ready));
+        assert!(!readiness.reached("db", ProcessState::Finished));
+    }
+
+    #[test]
+    fn successfully_exited_process_is_finished() {
+        let readiness = readiness();
+
+        readiness.record(&exited("migrate", true));
+        assert!(readiness.reached("migrate", ProcessState::Ready));
+        assert!(readiness.reached("migrate", ProcessState::Finished));
+    }
+
+    #[test]
+    fn process_not_running_is_not_waited_for() {
+        assert!(readiness().reached("cache", ProcessState::Finished));
+    }
+
+    #[tokio::test]
+    async fn waiting_for_failed_process_returns_it() {
+        let readiness = readiness();
+        let (_shutdown_tx, shutdown) = watch::channel(Shutdown::Running);
+
+        let waiting = readiness.clone();
+        let wait = tokio::spawn(async move {
+            waiting
+                .wait("migrate", ProcessState::Finished, shutdown)
+                .await
+        });
+        readiness.record(&exited("migrate", false));
+        assert_eq!(wait.await.unwrap(), Err("migrate".to_string()));
+    }
+
+    #[test]
+    fn process_stopped_on_purpose_is_finished_rather_than_failed() {
+        let readiness = readiness();
+
+        readiness.record(&stopped("migrate", EndedBy::Stop));
+        assert!(readiness.reached("migrate", ProcessState::Finished));
+    }
+
+    #[test]
+    fn process_killed_to_be_restarted_is_not_failed() {
+        let readiness = readiness();
+
+        readiness.record(&started("db"));
+        readiness.record(&stopped("db", EndedBy::Restart));
+        assert!(readiness.reached("db", ProcessState::Ready));
+    }
+
+    #[tokio::test]
+    async fn started_process_is_ready_once_it_has_published_its_exports() {
+        let exports = Exports::new(&processes());
+        let readiness = readiness_with(&exports);
+        let (_shutdown_tx, shutdown) = watch::channel(Shutdown::Running);
+
+        readiness.record(&started("api"));
+        assert!(!readiness.reached("api", ProcessState::Ready));
+
+        let waiting = readiness.clone();
+        let wait =
+            tokio::spawn(async move { waiting.wait("api", ProcessState::Ready, shutdown).await });
+        exports.capture(&Line::new(
+            "api",
+            Stream::Stdout,
+            "WATCHMUX_EXPORT API_URL=http://localhost:8080",
+        ));
+        assert_eq!(wait.await.unwrap(), Ok(true));
+        assert!(readiness.reached("api", ProcessState::Ready));
+    }
+}
@@ -0,0 +1,112 @@
+use std::{env, path::PathBuf, time::Duration};
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::fs;
+
+/// Timeout of fetching config from a URL.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("https://") || path.starts_with("http://")
+}
+
+/// Fetch config from the URL pinned to a checksum with `#sha256=<hex>` fragment. Fetched config
+/// is cached and used when fetching fails, or without fetching when it matches the checksum.
+pub async fn fetch(url: &str) -> Result<String, RemoteError> {
+    let (url, checksum) = match url.split_once('#') {
+        Some((url, fragment)) => {
+            let checksum = fragment
+                .strip_prefix("sha256=")
+                .ok_or_else(|| RemoteError::Fragment(fragment.to_string()))?;
+            (url, Some(checksum.to_lowercase()))
+        }
+        None => (url, None),
+    };
+    let cache = cache_dir().map(|dir| dir.join(format!("{}.yaml", sha256(url))));
+    let cached = match &cache {
+        Some(cache) => fs::read_to_string(cache).await.ok(),
+        None => None,
+    };
+
+    if let (Some(cached), Some(checksum)) = (&cached, &checksum) {
+        if sha256(cached) == *checksum {
+            return Ok(cached.clone());
+        }
+    }
+
+    let config = match get(url).await {
+        Ok(config) => config,
+        Err(error) => {
+            return match cached.filter(|_| checksum.is_none()) {
+                Some(cached) => {
+                    eprintln!("warning: failed to fetch {url}: {error}, using cached config");
+                    Ok(cached)
+                }
+                None => Err(RemoteError::Fetch(url.to_string(), error)),
+            }
+        }
+    };
+    if let Some(checksum) = checksum {
+        let found = sha256(&config);
+        if found != checksum {
+            return Err(RemoteError::Checksum {
+                url: url.to_string(),
+                expected: checksum,
+                found,
+            });
+        }
+    }
+
+    if let Some(cache) = cache {
+        // config is still used when it cannot be cached
+        if let Some(dir) = cache.parent() {
+            let _ = fs::create_dir_all(dir).await;
+        }
+        let _ = fs::write(cache, &config).await;
+    }
+
+    Ok(config)
+}
+
+async fn get(url: &str) -> Result<String, reqwest::Error> {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()?
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|cache| cache.join("watchmux").join("configs"))
+}
+
+fn sha256(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum RemoteError {
+    #[error("failed to fetch config from {0}: {1}")]
+    Fetch(String, reqwest::Error),
+
+    #[error("checksum of config from {url} is {found}, expected {expected}")]
+    Checksum {
+        url: String,
+        expected: String,
+        found: String,
+    },
+
+    #[error("unsupported fragment #{0} of config URL, expected #sha256=<hex>")]
+    Fragment(String),
+}
@@ -0,0 +1,162 @@
+use std::{cmp::Ordering, process::Command};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::doctor;
+
+/// Program the process requires checked before it is started e.g.
+/// `requires: { bin: node, version: ">=18" }`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Requires {
+    pub bin: String,
+    /// Comma separated comparisons the version printed by `bin --version` must satisfy.
+    pub version: Option<String>,
+}
+
+impl Requires {
+    /// Check the program is found from the `path` and its version satisfies the requirement.
+    pub fn check(&self, path: &str) -> Result<(), RequiresError> {
+        let bin = doctor::find_executable(&self.bin, path)
+            .ok_or_else(|| RequiresError::NotFound(self.bin.clone()))?;
+        let Some(requirement) = &self.version else {
+            return Ok(());
+        };
+
+        let output = Command::new(&bin)
+            .arg("--version")
+            .output()
+            .map_err(|_| RequiresError::UnknownVersion(self.bin.clone()))?;
+        // some programs such as older java print the version to stderr
+        let output = [output.stdout, output.stderr].concat();
+        let found = version_in(&String::from_utf8_lossy(&output))
+            .ok_or_else(|| RequiresError::UnknownVersion(self.bin.clone()))?;
+
+        match satisfies(&found, requirement) {
+            Some(true) => Ok(()),
+            Some(false) => Err(RequiresError::Version {
+                bin: self.bin.clone(),
+                requirement: requirement.clone(),
+                found,
+            }),
+            None => Err(RequiresError::InvalidRequirement(requirement.clone())),
+        }
+    }
+}
+
+/// First version in the output, without its prerelease or build suffix e.g. `18.2.0` of
+/// `v18.2.0-nightly`.
+fn version_in(output: &str) -> Option<String> {
+    let pattern = Regex::new(r"\d+(?:\.\d+)*").expect("version pattern must be valid");
+    pattern
+        .find(output)
+        .map(|version| version.as_str().to_string())
+}
+
+/// Whether the version satisfies every comparison of the requirement e.g. `>=18, <21`, or `None`
+/// when the requirement is invalid. Version is compared by as many components as the
+/// comparison has so `18` matches `18.2.0` and `<21` does not match `21.1`.
+fn satisfies(version: &str, requirement: &str) -> Option<bool> {
+    let version = components(version)?;
+
+    requirement
+        .split(',')
+        .try_fold(true, |satisfied, comparison| {
+            let comparison = comparison.trim();
+            let (operator, expected) = [">=", "<=", ">", "<", "="]
+                .into_iter()
+                .find_map(|operator| Some((operator, comparison.strip_prefix(operator)?)))
+                .unwrap_or(("=", comparison));
+            let expected = components(expected.trim())?;
+            let found = &version[..expected.len().min(version.len())];
+
+            let ordering = found.cmp(&expected[..]);
+            let matches = match operator {
+                ">=" => ordering != Ordering::Less,
+                "<=" => ordering != Ordering::Greater,
+                ">" => ordering == Ordering::Greater,
+                "<" => ordering == Ordering::Less,
+                _ => ordering == Ordering::Equal,
+            };
+            Some(satisfied && matches)
+        })
+}
+
+fn components(version: &str) -> Option<Vec<u64>> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|component| component.parse().ok())
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum RequiresError {
+    #[error("requires {0} which is not found from PATH")]
+    NotFound(String),
+
+    #[error("requires {bin} {requirement}, found {found}")]
+    Version {
+        bin: String,
+        requirement: String,
+        found: String,
+    },
+
+    #[error("failed to read version of {0} with --version")]
+    UnknownVersion(String),
+
+    #[error("invalid version requirement {0}")]
+    InvalidRequirement(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_is_read_without_prerelease() {
+        assert_eq!(version_in("v18.2.0\n").as_deref(), Some("18.2.0"));
+        assert_eq!(
+            version_in("go version go1.22rc1 linux/amd64").as_deref(),
+            Some("1.22")
+        );
+        assert_eq!(
+            version_in("openjdk 21-ea 2023-09-19").as_deref(),
+            Some("21")
+        );
+        assert_eq!(version_in("no version here"), None);
+    }
+
+    #[test]
+    fn versions_are_compared_by_components_of_requirement() {
+        assert_eq!(satisfies("18.2.0", ">=18"), Some(true));
+        assert_eq!(satisfies("18.2.0", "18"), Some(true));
+        assert_eq!(satisfies("18.2.0", "=18.2"), Some(true));
+        assert_eq!(satisfies("18.2.0", ">=18, <21"), Some(true));
+        assert_eq!(satisfies("21.1", "<21"), Some(false));
+        assert_eq!(satisfies("17.9.1", ">=18"), Some(false));
+        assert_eq!(satisfies("18.10", ">18.9"), Some(true));
+        // version shorter than the requirement is compared by the components it has
+        assert_eq!(satisfies("18", ">=18.2"), Some(false));
+        assert_eq!(satisfies("18", "<=18.2"), Some(true));
+    }
+
+    #[test]
+    fn invalid_requirement_is_not_satisfied() {
+        assert_eq!(satisfies("1.2.3", ">=1.2.3-beta"), None);
+        assert_eq!(satisfies("1.2.3", "~1.2"), None);
+        assert_eq!(satisfies("1.2.3", ">=1.2, "), None);
+    }
+
+    #[test]
+    fn missing_program_is_not_found() {
+        let requires = Requires {
+            bin: "watchmux-missing-program".to_string(),
+            version: Some(">=1".to_string()),
+        };
+
+        let error = requires.check("").unwrap_err();
+        assert!(matches!(error, RequiresError::NotFound(_)), "{error}");
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::{sync::watch, time};
+
+use crate::{shutdown::Shutdown, size::ByteSize};
+
+/// Interval of checking again whether the machine has headroom for a deferred process.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Headroom of the machine a heavyweight process needs before it is started e.g. a local LLM.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StartWhen {
+    /// Memory available for starting new processes e.g. `2GB`.
+    pub free_memory: Option<ByteSize>,
+    /// Load average of the last minute.
+    pub load_below: Option<f64>,
+}
+
+impl StartWhen {
+    /// What the machine lacks to start the process, `None` when it has headroom. Resources which
+    /// cannot be read on the system do not hold the process.
+    pub fn unmet(&self) -> Option<String> {
+        if let (Some(free), Some(available)) = (self.free_memory, available_memory()) {
+            if available < free.0 {
+                return Some(format!(
+                    "free memory is {free}, {}MB available",
+                    available / 1_000_000
+                ));
+            }
+        }
+        if let (Some(below), Some(load)) = (self.load_below, load_average()) {
+            if load >= below {
+                return Some(format!("load is below {below}, load is {load:.2}"));
+            }
+        }
+
+        None
+    }
+
+    /// Wait for the machine to have headroom checking it periodically. Returns `false` when the
+    /// process is stopped before that.
+    pub async fn wait(&self, mut shutdown: watch::Receiver<Shutdown>) -> bool {
+        let mut interval = time::interval(RECHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if self.unmet().is_none() {
+                        return true;
+                    }
+                }
+                _ = shutdown.wait_for(|state| *state != Shutdown::Running) => return false,
+            }
+        }
+    }
+}
+
+/// `MemAvailable` of `/proc/meminfo` in bytes.
+fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let kilobytes = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kilobytes * 1024)
+}
+
+#[cfg(unix)]
+fn load_average() -> Option<f64> {
+    let mut load = [0.0];
+    // SAFETY: getloadavg writes at most the given amount of samples to the array
+    (unsafe { libc::getloadavg(load.as_mut_ptr(), 1) } == 1).then_some(load[0])
+}
+
+#[cfg(not(unix))]
+fn load_average() -> Option<f64> {
+    None
+}
@@ -0,0 +1,138 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::config::WatchProcess;
+
+/// Key of the last successful run of a `run_once` process. The key covers the command, the env
+/// and contents of the `fingerprint` files so the process is run again when any of them change.
+pub struct RunOnce {
+    key: String,
+    path: PathBuf,
+}
+
+impl RunOnce {
+    /// Key of the process before watchmux adds its own env variables to it.
+    pub fn new(process: &WatchProcess) -> io::Result<Self> {
+        let mut hasher = Sha256::new();
+        hasher.update(format!(
+            "{:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+            process.run_type,
+            process.cmd,
+            process.args,
+            process.script,
+            process.file,
+            process.selector,
+            process.target,
+            process.directory,
+            process.wait_for.command().unwrap_or_default(),
+        ));
+        let mut envs = process.envs().into_iter().collect::<Vec<_>>();
+        envs.sort();
+        for (name, value) in envs {
+            hasher.update(format!("{name}={value}\n"));
+        }
+        hash_files(&process.fingerprint, &mut hasher)?;
+
+        let directory = env::current_dir()?;
+        let name = hex(&Sha256::digest(format!(
+            "{}\n{}",
+            directory.display(),
+            process.title
+        )));
+
+        Ok(Self {
+            key: hex(&hasher.finalize()),
+            path: cache_dir().join(name),
+        })
+    }
+
+    /// Whether the last successful run had the same key.
+    pub fn is_cached(&self) -> bool {
+        fs::read_to_string(&self.path).is_ok_and(|key| key == self.key)
+    }
+
+    /// Store the key after a successful run.
+    pub fn store(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, &self.key)
+    }
+}
+
+/// Contents of the `fingerprint` files of a process, letting restart-all skip the process when
+/// they have not changed since it was started or last restarted.
+pub struct Fingerprint {
+    paths: Vec<PathBuf>,
+    /// `None` when the files could not be read.
+    digest: Option<String>,
+}
+
+impl Fingerprint {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        Self {
+            paths: paths.to_vec(),
+            digest: Self::digest(paths),
+        }
+    }
+
+    /// Whether the contents have changed since the previous check, or cannot be read.
+    pub fn changed(&mut self) -> bool {
+        let digest = Self::digest(&self.paths);
+        let changed = digest.is_none() || digest != self.digest;
+        self.digest = digest;
+
+        changed
+    }
+
+    fn digest(paths: &[PathBuf]) -> Option<String> {
+        let mut hasher = Sha256::new();
+        hash_files(paths, &mut hasher).ok()?;
+        Some(hex(&hasher.finalize()))
+    }
+}
+
+/// Hash names and contents of the files of the paths in order of their names.
+fn hash_files(paths: &[PathBuf], hasher: &mut Sha256) -> io::Result<()> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_files(path, &mut files)?;
+    }
+    files.sort();
+    for file in files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(fs::read(&file)?);
+    }
+
+    Ok(())
+}
+
+/// Files of the path, and of its subdirectories when it is a directory.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(path)? {
+        collect_files(&entry?.path(), files)?;
+    }
+    Ok(())
+}
+
+fn cache_dir() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("watchmux")
+        .join("run-once")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
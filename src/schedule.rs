@@ -0,0 +1,123 @@
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::time::Instant;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Local time of the day in `HH:MM` format.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    hour: u8,
+    minute: u8,
+}
+
+impl TimeOfDay {
+    /// Time until the next occurrence of the time of the day.
+    pub fn until(&self) -> Duration {
+        let now = seconds_of_day();
+        let target = self.hour as u64 * 60 * 60 + self.minute as u64 * 60;
+
+        Duration::from_secs(match target > now {
+            true => target - now,
+            false => target + DAY - now,
+        })
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = InvalidTimeOfDay;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || InvalidTimeOfDay(value.clone());
+        let (hour, minute) = value.split_once(':').ok_or_else(invalid)?;
+        let hour = hour.parse::<u8>().map_err(|_| invalid())?;
+        let minute = minute.parse::<u8>().map_err(|_| invalid())?;
+        if hour > 23 || minute > 59 {
+            return Err(invalid());
+        }
+
+        Ok(Self { hour, minute })
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(value: TimeOfDay) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("invalid time of the day {0}, expected HH:MM")]
+pub struct InvalidTimeOfDay(String);
+
+/// Time processes are stopped at by `--max-runtime` or `stop_at` with the reason, whichever is
+/// earlier.
+pub fn deadline(
+    max_runtime: Option<Duration>,
+    stop_at: Option<TimeOfDay>,
+) -> Option<(Instant, String)> {
+    let now = Instant::now();
+    let max_runtime = max_runtime.map(|max_runtime| {
+        (
+            now + max_runtime,
+            format!(
+                "max runtime {} reached",
+                humantime::format_duration(max_runtime)
+            ),
+        )
+    });
+    let stop_at =
+        stop_at.map(|stop_at| (now + stop_at.until(), format!("stop_at {stop_at} reached")));
+
+    match (max_runtime, stop_at) {
+        (Some(max_runtime), Some(stop_at)) => Some(if max_runtime.0 <= stop_at.0 {
+            max_runtime
+        } else {
+            stop_at
+        }),
+        (max_runtime, stop_at) => max_runtime.or(stop_at),
+    }
+}
+
+/// Local time of the day in `HH:MM:SS` format.
+pub fn local_time() -> String {
+    let seconds = seconds_of_day();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Seconds elapsed since the local midnight.
+fn seconds_of_day() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+
+    #[cfg(unix)]
+    {
+        let time = now as libc::time_t;
+        // SAFETY: localtime_r only writes to the provided tm struct
+        let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+        if !unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+            return tm.tm_hour as u64 * 60 * 60 + tm.tm_min as u64 * 60 + tm.tm_sec as u64;
+        }
+    }
+
+    now % DAY
+}
@@ -0,0 +1,191 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use regex::Regex;
+use tokio::time::Instant;
+
+use crate::{
+    event::{Line, Stream},
+    size::ByteSize,
+    summary::WATCHMUX_TITLE,
+};
+
+/// Memory of the output lines kept for `ctl search` unless `buffer_memory` is given.
+pub const DEFAULT_BUFFER_MEMORY: ByteSize = ByteSize(16 << 20);
+
+/// Latest output lines of the processes searched with `ctl search`, kept within the memory
+/// budget. Memory is shared fairly by evicting the oldest lines of the process using the most of
+/// it.
+#[derive(Debug)]
+pub struct SearchIndex {
+    buffers: HashMap<String, Buffer>,
+    budget: u64,
+    used: u64,
+    /// Order of the next line among the lines of every process.
+    seq: u64,
+}
+
+#[derive(Debug, Default)]
+struct Buffer {
+    lines: VecDeque<(u64, Instant, Line)>,
+    bytes: u64,
+}
+
+impl SearchIndex {
+    pub fn new(budget: ByteSize) -> Self {
+        Self {
+            buffers: HashMap::new(),
+            budget: budget.0,
+            used: 0,
+            seq: 0,
+        }
+    }
+
+    pub fn record(&mut self, line: &Line) {
+        let bytes = size_of(line);
+        let buffer = self.buffers.entry(line.title.clone()).or_default();
+        buffer
+            .lines
+            .push_back((self.seq, Instant::now(), line.clone()));
+        buffer.bytes += bytes;
+        self.used += bytes;
+        self.seq += 1;
+
+        while self.used > self.budget {
+            let Some(buffer) = self.buffers.values_mut().max_by_key(|buffer| buffer.bytes) else {
+                break;
+            };
+            let Some((_, _, evicted)) = buffer.lines.pop_front() else {
+                break;
+            };
+            let bytes = size_of(&evicted);
+            buffer.bytes -= bytes;
+            self.used -= bytes;
+        }
+    }
+
+    /// Lines matching the pattern, only of the process with the title when given and only the
+    /// ones received within `since` when given.
+    pub fn search(
+        &self,
+        pattern: &Regex,
+        title: Option<&str>,
+        since: Option<Duration>,
+    ) -> Vec<Line> {
+        let now = Instant::now();
+        let mut lines = self
+            .buffers
+            .iter()
+            .filter(|(buffer_title, _)| title.is_none_or(|title| *buffer_title == title))
+            .flat_map(|(_, buffer)| &buffer.lines)
+            .filter(|(_, received, _)| {
+                since.is_none_or(|since| now.duration_since(*received) <= since)
+            })
+            .filter(|(_, _, line)| pattern.is_match(&line.text))
+            .collect::<Vec<_>>();
+        lines.sort_by_key(|(seq, _, _)| *seq);
+
+        lines.into_iter().map(|(_, _, line)| line.clone()).collect()
+    }
+
+    /// Lines describing memory used by the lines of each process and in total.
+    pub fn usage(&self) -> Vec<Line> {
+        let mut buffers = self.buffers.iter().collect::<Vec<_>>();
+        buffers.sort_by_key(|(title, _)| *title);
+
+        let mut lines = vec![Line::new(
+            WATCHMUX_TITLE,
+            Stream::Notice,
+            format!(
+                "buffers: {} of {} used",
+                human(self.used),
+                human(self.budget)
+            ),
+        )];
+        lines.extend(buffers.into_iter().map(|(title, buffer)| {
+            Line::new(
+                WATCHMUX_TITLE,
+                Stream::Notice,
+                format!(
+                    "{title}: {} lines in {}",
+                    buffer.lines.len(),
+                    human(buffer.bytes)
+                ),
+            )
+        }));
+
+        lines
+    }
+}
+
+/// Memory taken by the line in the index.
+fn size_of(line: &Line) -> u64 {
+    (std::mem::size_of::<(u64, Instant, Line)>() + line.title.len() + line.text.len()) as u64
+}
+
+/// Amount of bytes in the largest unit not exceeding it, with one decimal e.g. `1.5MiB`.
+fn human(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes}B"),
+        1024..1_048_576 => format!("{:.1}KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1}MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_searched_by_pattern_and_title() {
+        let mut index = SearchIndex::new(DEFAULT_BUFFER_MEMORY);
+        index.record(&Line::new(
+            "api",
+            Stream::Stderr,
+            "error: connection refused",
+        ));
+        index.record(&Line::new("api", Stream::Stdout, "listening"));
+        index.record(&Line::new("db", Stream::Stderr, "error: disk full"));
+        index.record(&Line::new("api", Stream::Stderr, "error: timeout"));
+
+        let texts = |lines: Vec<Line>| lines.into_iter().map(|line| line.text).collect::<Vec<_>>();
+        let error = Regex::new("^error").unwrap();
+        assert_eq!(
+            texts(index.search(&error, None, None)),
+            [
+                "error: connection refused",
+                "error: disk full",
+                "error: timeout"
+            ]
+        );
+        assert_eq!(
+            texts(index.search(&error, Some("db"), None)),
+            ["error: disk full"]
+        );
+        let recent = index.search(&error, None, Some(Duration::from_secs(60)));
+        assert_eq!(recent.len(), 3);
+    }
+
+    #[test]
+    fn oldest_lines_of_process_using_most_memory_are_evicted() {
+        let line =
+            |title: &str, number: usize| Line::new(title, Stream::Stdout, number.to_string());
+        let budget = size_of(&line("api", 0)) * 4;
+        let mut index = SearchIndex::new(ByteSize(budget));
+
+        index.record(&line("db", 0));
+        for number in 0..4 {
+            index.record(&line("api", number));
+        }
+
+        let all = index.search(&Regex::new("").unwrap(), None, None);
+        let lines = all
+            .iter()
+            .map(|line| format!("{} {}", line.title, line.text))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["db 0", "api 1", "api 2", "api 3"]);
+        assert!(index.used <= budget);
+    }
+}
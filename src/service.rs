@@ -0,0 +1,160 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+use tokio::{fs, io, process::Command};
+
+use crate::{
+    config::{self, ConfigError},
+    remote,
+};
+
+#[derive(Error, Debug)]
+pub enum ServiceError {
+    #[error("io failed to manage service unit: {0:?}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid config: {0:?}")]
+    Config(#[from] ConfigError),
+
+    #[error("cannot resolve home directory for systemd user units")]
+    NoHome,
+
+    #[error("config from stdin cannot be installed as service")]
+    Stdin,
+
+    #[error("systemctl {0} failed")]
+    Systemctl(String),
+}
+
+/// Generate systemd user unit running watchmux with the given config and enable it.
+pub async fn install(config: Option<PathBuf>, name: Option<String>) -> Result<(), ServiceError> {
+    let current_dir = env::current_dir()?;
+    let config = match config {
+        Some(path) if path.as_os_str() == "-" => return Err(ServiceError::Stdin),
+        Some(path) if path.to_str().is_some_and(remote::is_url) => path,
+        Some(path) => fs::canonicalize(path).await?,
+        None => current_dir.join(".watchmuxrc.yaml"),
+    };
+    config::load(Some(config.clone())).await?;
+
+    let name = service_name(name, &current_dir);
+    let unit_name = format!("watchmux-{name}.service");
+    let unit_dir = unit_dir()?;
+    let exe = env::current_exe()?;
+    let unit = unit(&name, &current_dir, &exe, &config);
+
+    fs::create_dir_all(&unit_dir).await?;
+    let unit_path = unit_dir.join(&unit_name);
+    fs::write(&unit_path, unit).await?;
+    println!("wrote {}", unit_path.display());
+
+    systemctl(&["daemon-reload"]).await?;
+    systemctl(&["enable", "--now", &unit_name]).await
+}
+
+/// Disable and remove systemd user unit previously installed with [`install`].
+pub async fn uninstall(name: Option<String>) -> Result<(), ServiceError> {
+    let name = service_name(name, &env::current_dir()?);
+    let unit_name = format!("watchmux-{name}.service");
+
+    systemctl(&["disable", "--now", &unit_name]).await?;
+
+    let unit_path = unit_dir()?.join(&unit_name);
+    fs::remove_file(&unit_path).await?;
+    println!("removed {}", unit_path.display());
+
+    systemctl(&["daemon-reload"]).await
+}
+
+/// Unit running the executable with the config in the working directory.
+fn unit(name: &str, working_dir: &Path, exe: &Path, config: &Path) -> String {
+    format!(
+        "[Unit]
+Description=watchmux {name}
+
+[Service]
+Type=notify
+WorkingDirectory={}
+ExecStart={} -c {}
+Restart=on-failure
+
+[Install]
+WantedBy=default.target
+",
+        working_dir.display().to_string().replace('%', "%%"),
+        exec_arg(&exe.display().to_string()),
+        exec_arg(&config.display().to_string()),
+    )
+}
+
+/// Argument of `ExecStart` quoted, with the specifiers and variables systemd would expand in it
+/// escaped e.g. `%20` of a URL.
+fn exec_arg(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{escaped}\"")
+}
+
+fn service_name(name: Option<String>, current_dir: &Path) -> String {
+    name.unwrap_or_else(|| {
+        current_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    })
+}
+
+fn unit_dir() -> Result<PathBuf, ServiceError> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("systemd").join("user"))
+        .ok_or(ServiceError::NoHome)
+}
+
+async fn systemctl(args: &[&str]) -> Result<(), ServiceError> {
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .await?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(ServiceError::Systemctl(args.join(" ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_url_is_escaped_in_exec_start() {
+        let unit = unit(
+            "team",
+            Path::new("/home/dev/100%"),
+            Path::new("/usr/bin/watchmux"),
+            Path::new("https://example.com/a%20b.yaml?v=$1"),
+        );
+
+        assert!(
+            unit.contains("WorkingDirectory=/home/dev/100%%\n"),
+            "{unit}"
+        );
+        assert!(
+            unit.contains(
+                "ExecStart=\"/usr/bin/watchmux\" -c \"https://example.com/a%%20b.yaml?v=$$1\"\n"
+            ),
+            "{unit}"
+        );
+        assert_eq!(exec_arg(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+}
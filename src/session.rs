@@ -0,0 +1,227 @@
+use std::{
+    collections::HashSet,
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::event::Event;
+
+/// Running instance of watchmux written to the sessions directory.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionInfo {
+    pub name: String,
+    pub pid: u32,
+    pub directory: PathBuf,
+    pub processes: usize,
+    pub running: usize,
+    /// Processes which failed and have not started again.
+    #[serde(default)]
+    pub failed: usize,
+    /// Seconds since unix epoch.
+    pub started: u64,
+}
+
+impl SessionInfo {
+    /// Single line description of the session for `sessions list`.
+    pub fn describe(&self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let uptime =
+            humantime::format_duration(Duration::from_secs(now.saturating_sub(self.started)));
+
+        format!(
+            "{}\tpid {}\t{}/{} running\tup {uptime}\t{}",
+            self.name,
+            self.pid,
+            self.running,
+            self.processes,
+            self.directory.display()
+        )
+    }
+}
+
+/// Session of this instance registered under its name until it is dropped.
+pub struct Session {
+    path: PathBuf,
+    info: SessionInfo,
+    running: HashSet<String>,
+    failed: HashSet<String>,
+}
+
+impl Session {
+    /// Register the session failing when other running instance has the same name.
+    pub fn register(name: &str, processes: usize) -> Result<Self, SessionError> {
+        let dir = dir();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.json", file_name(name)));
+        if let Some(running) = read(&path).filter(|session| is_alive(session.pid)) {
+            return Err(SessionError::AlreadyRunning {
+                name: name.to_string(),
+                pid: running.pid,
+            });
+        }
+
+        let session = Self {
+            path,
+            info: SessionInfo {
+                name: name.to_string(),
+                pid: std::process::id(),
+                directory: env::current_dir()?,
+                processes,
+                running: 0,
+                failed: 0,
+                started: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs())
+                    .unwrap_or_default(),
+            },
+            running: HashSet::new(),
+            failed: HashSet::new(),
+        };
+        session.write()?;
+
+        Ok(session)
+    }
+
+    /// Track running processes of the session from the event.
+    pub fn record(&mut self, event: &Event) -> io::Result<()> {
+        let changed = match event {
+            Event::Started { title, .. } => {
+                self.running.insert(title.clone()) | self.failed.remove(title)
+            }
+            Event::Exited {
+                title,
+                success: false,
+                ended_by: None,
+                ..
+            }
+            | Event::Failed { title, .. } => {
+                self.running.remove(title) | self.failed.insert(title.clone())
+            }
+            Event::Exited { title, .. } => self.running.remove(title),
+            Event::Restarted { .. }
+            | Event::Blocked { .. }
+            | Event::Deferred { .. }
+            | Event::Skipped { .. } => false,
+        };
+        if changed {
+            self.info.running = self.running.len();
+            self.info.failed = self.failed.len();
+            self.write()?;
+        }
+
+        Ok(())
+    }
+
+    /// Count the process added to the running session.
+    pub fn add_process(&mut self) -> io::Result<()> {
+        self.info.processes += 1;
+        self.write()
+    }
+
+    fn write(&self) -> io::Result<()> {
+        fs::write(&self.path, serde_json::to_vec(&self.info)?)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        let _ = fs::remove_file(control_path(&self.info.name));
+    }
+}
+
+/// Socket the session listens `watchmux ctl` requests on.
+pub fn control_path(name: &str) -> PathBuf {
+    dir().join(format!("{}.sock", file_name(name)))
+}
+
+/// Running sessions of watchmux on the host. Sessions left behind by instances which did not
+/// exit cleanly are removed.
+pub fn list() -> io::Result<Vec<SessionInfo>> {
+    let entries = match fs::read_dir(dir()) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    let mut sessions = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        // sockets of the sessions are next to them
+        if !path.to_string_lossy().ends_with(".json") {
+            continue;
+        }
+        match read(&path) {
+            Some(session) if is_alive(session.pid) => sessions.push(session),
+            _ => {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+    sessions.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(sessions)
+}
+
+/// Running and failed processes of the sessions e.g. `✓4 ✗1` for shell prompts, ASCII
+/// `ok:4 failed:1` when the terminal cannot show glyphs. `None` without sessions.
+pub fn prompt_status(sessions: &[SessionInfo], unicode: bool) -> Option<String> {
+    if sessions.is_empty() {
+        return None;
+    }
+
+    let running = sessions
+        .iter()
+        .map(|session| session.running)
+        .sum::<usize>();
+    let failed = sessions.iter().map(|session| session.failed).sum::<usize>();
+    Some(match unicode {
+        true => format!("✓{running} ✗{failed}"),
+        false => format!("ok:{running} failed:{failed}"),
+    })
+}
+
+fn file_name(name: &str) -> String {
+    name.replace(['/', '\\'], "_")
+}
+
+fn read(path: &Path) -> Option<SessionInfo> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+/// Directory of the sessions in the runtime directory of the user.
+fn dir() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join("watchmux-sessions")
+}
+
+fn is_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        // SAFETY: signal 0 only checks the process exists
+        unsafe { libc::kill(pid as i32, 0) == 0 }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        true
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("session {name} is already running with pid {pid}")]
+    AlreadyRunning { name: String, pid: u32 },
+
+    #[error("failed to write session: {0}")]
+    Io(#[from] io::Error),
+}
@@ -0,0 +1,57 @@
+use std::{env, sync::OnceLock};
+
+use tokio::process::Command;
+
+use crate::doctor::find_executable;
+
+/// Env variable overriding the shell commands of the config are run with e.g. `WATCHMUX_SHELL=zsh`.
+pub const SHELL_ENV: &str = "WATCHMUX_SHELL";
+
+static SHELL: OnceLock<Shell> = OnceLock::new();
+
+/// Shell running `type: shell` processes, `wait_for`, `stop_cmd` and other commands of the config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Shell {
+    /// Shell given with `WATCHMUX_SHELL`.
+    Custom(String),
+    Bash,
+    /// Bash is not found in PATH e.g. in minimal Alpine images.
+    Sh,
+}
+
+impl Shell {
+    /// Shell of `WATCHMUX_SHELL`, or bash falling back to sh when bash is not found in PATH.
+    pub fn resolve() -> Self {
+        match env::var(SHELL_ENV) {
+            Ok(shell) if !shell.is_empty() => Shell::Custom(shell),
+            _ if find_executable("bash", &env::var("PATH").unwrap_or_default()).is_some() => {
+                Shell::Bash
+            }
+            _ => Shell::Sh,
+        }
+    }
+
+    pub fn program(&self) -> &str {
+        match self {
+            Shell::Custom(shell) => shell,
+            Shell::Bash => "bash",
+            Shell::Sh => "sh",
+        }
+    }
+}
+
+/// Resolve the shell commands are run with, returning a warning when it falls back to sh.
+pub fn init() -> Option<String> {
+    let shell = SHELL.get_or_init(Shell::resolve);
+
+    (*shell == Shell::Sh).then(|| {
+        format!("warning: bash not found in PATH, commands are run with sh, set {SHELL_ENV} to use other shell")
+    })
+}
+
+/// Command running the script with the shell resolved with [`init`].
+pub fn command(script: &str) -> Command {
+    let mut command = Command::new(SHELL.get_or_init(Shell::resolve).program());
+    command.arg("-c").arg(script);
+    command
+}
@@ -0,0 +1,111 @@
+use tokio::sync::watch;
+
+/// Shutdown state of watchmux broadcast to the processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shutdown {
+    Running,
+    /// Processes are asked to stop. Signal is forwarded to processes which did not receive it
+    /// from the terminal.
+    Stop {
+        forward: Option<i32>,
+    },
+    /// Processes are killed after second signal.
+    Kill,
+}
+
+impl Shutdown {
+    /// Signal forwarded to processes stopped by watchmux itself.
+    fn terminate_forward() -> Option<i32> {
+        #[cfg(unix)]
+        return Some(libc::SIGTERM);
+        #[cfg(not(unix))]
+        None
+    }
+}
+
+/// Listen for termination signals. First signal asks processes to stop gracefully and the
+/// second kills them. Processes stopped with the returned sender are killed by the first signal.
+pub fn listen() -> (watch::Sender<Shutdown>, watch::Receiver<Shutdown>) {
+    let (tx, rx) = watch::channel(Shutdown::Running);
+
+    let sender = tx.clone();
+    tokio::spawn(async move {
+        loop {
+            let forward = signal().await;
+            if !sender.send_if_modified(|state| stop(state, forward)) {
+                sender.send_replace(Shutdown::Kill);
+                break;
+            }
+        }
+    });
+
+    (tx, rx)
+}
+
+/// Ask processes to stop as if watchmux was terminated. Returns `false` when already stopping.
+pub fn terminate(shutdown: &watch::Sender<Shutdown>) -> bool {
+    shutdown.send_if_modified(|state| stop(state, Shutdown::terminate_forward()))
+}
+
+fn stop(state: &mut Shutdown, forward: Option<i32>) -> bool {
+    let running = *state == Shutdown::Running;
+    if running {
+        *state = Shutdown::Stop { forward };
+    }
+    running
+}
+
+/// Shutdown channel of single process following the shutdown of watchmux. Process can be stopped
+/// alone with the returned sender.
+pub fn follow(
+    mut shutdown: watch::Receiver<Shutdown>,
+) -> (watch::Sender<Shutdown>, watch::Receiver<Shutdown>) {
+    let (tx, rx) = watch::channel(Shutdown::Running);
+
+    let sender = tx.clone();
+    tokio::spawn(async move {
+        while shutdown.changed().await.is_ok() {
+            let global = *shutdown.borrow_and_update();
+            sender.send_if_modified(|state| {
+                // process stopped alone is not asked to stop again
+                let forward = match global {
+                    Shutdown::Stop { .. } => *state == Shutdown::Running,
+                    Shutdown::Kill => *state != Shutdown::Kill,
+                    Shutdown::Running => false,
+                };
+                if forward {
+                    *state = global;
+                }
+                forward
+            });
+        }
+    });
+
+    (tx, rx)
+}
+
+/// Wait for the next termination signal returning the signal to forward to processes.
+#[cfg(unix)]
+async fn signal() -> Option<i32> {
+    use tokio::signal::unix::{self, SignalKind};
+
+    let mut terminate = unix::signal(SignalKind::terminate()).ok();
+    let terminated = async {
+        match terminate.as_mut() {
+            Some(terminate) => terminate.recv().await,
+            None => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        // processes in the terminal foreground process group already got SIGINT from <C-c>
+        _ = tokio::signal::ctrl_c() => None,
+        _ = terminated => Some(libc::SIGTERM),
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() -> Option<i32> {
+    let _ = tokio::signal::ctrl_c().await;
+    None
+}
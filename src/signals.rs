@@ -0,0 +1,44 @@
+use tokio::sync::mpsc;
+
+/// Signal asking watchmux to act on the processes like other supervisors do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlSignal {
+    /// `SIGUSR1` writes the state of the processes.
+    Dump,
+    /// `SIGUSR2` restarts every running process.
+    RestartAll,
+    /// `SIGHUP` reloads the config by stopping the processes and running watchmux again.
+    Reload,
+}
+
+/// Listen for control signals, never receiving any on platforms without them.
+pub fn listen() -> mpsc::Receiver<ControlSignal> {
+    let (tx, rx) = mpsc::channel(4);
+
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let (Ok(mut dump), Ok(mut restart_all), Ok(mut reload)) = (
+            signal(SignalKind::user_defined1()),
+            signal(SignalKind::user_defined2()),
+            signal(SignalKind::hangup()),
+        ) else {
+            return;
+        };
+        loop {
+            let signal = tokio::select! {
+                _ = dump.recv() => ControlSignal::Dump,
+                _ = restart_all.recv() => ControlSignal::RestartAll,
+                _ = reload.recv() => ControlSignal::Reload,
+            };
+            if tx.send(signal).await.is_err() {
+                break;
+            }
+        }
+    });
+    #[cfg(not(unix))]
+    drop(tx);
+
+    rx
+}
@@ -0,0 +1,1268 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::File,
+    io::{self, AsyncReadExt, AsyncWrite, AsyncWriteExt, Stdout},
+    net::TcpStream,
+    sync::mpsc,
+    task::{JoinHandle, JoinSet},
+    time::{self, Instant},
+};
+
+use crate::{
+    event::{Event, Line, Message, Stream},
+    output::{Formatter, OutputMode, Terminal},
+    permissions::FilePermissions,
+    size::ByteSize,
+};
+
+/// Move to the start of the line and clear it.
+const CLEAR_LINE: &str = "\r\x1b[K";
+/// Time webhook request is waited for before giving up.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time connecting to message broker is waited for before dropping the message.
+const BROKER_TIMEOUT: Duration = Duration::from_secs(2);
+/// Delay before connecting again to unreachable message broker.
+const BROKER_RECONNECT: Duration = Duration::from_secs(5);
+/// Messages buffered for the message broker before dropping them.
+const BROKER_BUFFER: usize = 1024;
+/// Interval of exporting the records buffered for OpenTelemetry collector.
+const OTLP_FLUSH: Duration = Duration::from_secs(1);
+/// Records buffered for OpenTelemetry collector before dropping them.
+const OTLP_BUFFER: usize = 4096;
+/// Interval of the lines telling how much output of the muted processes was left out.
+pub const MUTED_HEARTBEAT: Duration = Duration::from_secs(30);
+
+/// Destination of output lines and lifecycle events of the processes.
+#[async_trait]
+pub trait Sink: Send {
+    async fn write(&mut self, message: &Message) -> io::Result<()>;
+
+    /// Whether the sink is the terminal watchmux is used from, which leaves out output of muted
+    /// processes.
+    fn interactive(&self) -> bool {
+        false
+    }
+
+    /// Write anything left buffered when watchmux is about to exit.
+    async fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sink of the config defined by its `type`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    /// Formatted output to the terminal.
+    Stdout,
+    /// Output lines as plain text appended to the file.
+    File {
+        path: PathBuf,
+        #[serde(flatten)]
+        permissions: FilePermissions,
+        rotate: Option<Rotation>,
+    },
+    /// Output lines and events as NDJSON appended to the file.
+    Json {
+        path: PathBuf,
+        #[serde(flatten)]
+        permissions: FilePermissions,
+        rotate: Option<Rotation>,
+    },
+    /// Output lines to the local syslog daemon.
+    Syslog {
+        #[serde(default = "default_ident")]
+        ident: String,
+    },
+    /// Lifecycle events posted as JSON to the url.
+    Webhook { url: String },
+    /// Output lines and events as JSON published to subject `{subject}.{title}` of NATS server.
+    Nats {
+        address: String,
+        #[serde(default = "default_ident")]
+        subject: String,
+    },
+    /// Output lines and events as JSON published to topic `{topic}/{title}` of MQTT broker.
+    Mqtt {
+        address: String,
+        #[serde(default = "default_ident")]
+        topic: String,
+    },
+    /// Output lines as OpenTelemetry log records and runs of the processes as spans exported with
+    /// OTLP/HTTP to the collector, each process as service `{title}` of the namespace.
+    Otlp {
+        #[serde(default = "default_otlp_endpoint")]
+        endpoint: String,
+        #[serde(default = "default_ident")]
+        namespace: String,
+    },
+}
+
+/// Rotation of the file of a sink once it would grow past `max_size`. The file is renamed to
+/// `{path}.1`, shifting the older ones up to `{path}.{keep}`, and a new file is started.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rotation {
+    pub max_size: ByteSize,
+    /// Amount of the rotated files kept.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+    /// Whether the rotated files are compressed with gzip to `{path}.{n}.gz`.
+    #[serde(default)]
+    pub compress: bool,
+}
+
+fn default_keep() -> usize {
+    5
+}
+
+fn default_ident() -> String {
+    "watchmux".to_string()
+}
+
+fn default_otlp_endpoint() -> String {
+    "http://localhost:4318".to_string()
+}
+
+impl SinkConfig {
+    /// Open the sink, `formatter` formats the output of stdout sink.
+    pub async fn open(&self, formatter: Formatter) -> io::Result<Box<dyn Sink>> {
+        Ok(match self {
+            SinkConfig::Stdout => Box::new(StdoutSink::new(formatter)),
+            SinkConfig::File {
+                path,
+                permissions,
+                rotate,
+            } => Box::new(FileSink::open(path, permissions, rotate.clone()).await?),
+            SinkConfig::Json {
+                path,
+                permissions,
+                rotate,
+            } => Box::new(JsonSink::open(path, true, permissions, rotate.clone()).await?),
+            SinkConfig::Syslog { ident } => Box::new(SyslogSink::connect(ident.clone())?),
+            SinkConfig::Webhook { url } => Box::new(WebhookSink::new(url.clone())?),
+            SinkConfig::Nats { address, subject } => Box::new(BrokerSink::new(
+                Broker::Nats,
+                address.clone(),
+                subject.clone(),
+            )),
+            SinkConfig::Mqtt { address, topic } => Box::new(BrokerSink::new(
+                Broker::Mqtt,
+                address.clone(),
+                topic.clone(),
+            )),
+            SinkConfig::Otlp {
+                endpoint,
+                namespace,
+            } => Box::new(OtlpSink::new(endpoint, namespace.clone())?),
+        })
+    }
+}
+
+/// Sinks every message is written to in order of registration.
+#[derive(Default)]
+pub struct Sinks {
+    sinks: Vec<Box<dyn Sink>>,
+    /// Lines left out of interactive sinks since the last heartbeat by title of the muted
+    /// processes.
+    muted: HashMap<String, usize>,
+    /// Process having the terminal, interactive sinks are not written until it exits.
+    foreground: Option<String>,
+    /// Titles processes are shown with after retitling them at runtime.
+    titles: HashMap<String, String>,
+}
+
+impl Sinks {
+    pub fn register(&mut self, sink: Box<dyn Sink>) {
+        self.sinks.push(sink);
+    }
+
+    pub async fn write(&mut self, message: &Message) -> io::Result<()> {
+        let muted_lines = message
+            .lines()
+            .first()
+            .and_then(|line| self.muted.get_mut(&line.title));
+        let muted = muted_lines.is_some() || self.foreground.is_some();
+        if let Some(muted_lines) = muted_lines {
+            *muted_lines += message.lines().len();
+        }
+        let retitled;
+        let message = match self.titles.is_empty() {
+            true => message,
+            false => {
+                retitled = self.retitled(message);
+                &retitled
+            }
+        };
+        for sink in &mut self.sinks {
+            if !(muted && sink.interactive()) {
+                sink.write(message).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Mute or unmute output of the process in interactive sinks, returning whether it is muted.
+    pub fn toggle_mute(&mut self, title: &str) -> bool {
+        let muted = !self.muted.contains_key(title);
+        self.set_muted(title, muted);
+        muted
+    }
+
+    /// Mute or unmute output of the process in interactive sinks, returning whether it changed.
+    pub fn set_muted(&mut self, title: &str, muted: bool) -> bool {
+        match muted {
+            true => self.muted.insert(title.to_string(), 0).is_none(),
+            false => self.muted.remove(title).is_some(),
+        }
+    }
+
+    /// Tell interactive sinks how many lines of each muted process were left out of them since
+    /// the last heartbeat, so they are known to be alive.
+    pub async fn heartbeat(&mut self) -> io::Result<()> {
+        let mut lines = Vec::new();
+        for (title, muted_lines) in &mut self.muted {
+            if *muted_lines > 0 {
+                let text = format!("{muted_lines} lines muted");
+                lines.push(Line::new(
+                    self.titles.get(title).unwrap_or(title),
+                    Stream::Notice,
+                    text,
+                ));
+                *muted_lines = 0;
+            }
+        }
+        lines.sort_by(|a, b| a.title.cmp(&b.title));
+        for line in lines {
+            let message = Message::Line(line);
+            for sink in self.sinks.iter_mut().filter(|sink| sink.interactive()) {
+                sink.write(&message).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Show the process with the new title from now on.
+    pub fn retitle(&mut self, title: &str, new_title: String) {
+        self.titles.insert(title.to_string(), new_title);
+    }
+
+    /// Title the process is shown with.
+    pub fn title<'a>(&'a self, title: &'a str) -> &'a str {
+        self.titles.get(title).map(String::as_str).unwrap_or(title)
+    }
+
+    fn retitled(&self, message: &Message) -> Message {
+        let mut message = message.clone();
+        match &mut message {
+            Message::Line(line) => line.title = self.title(&line.title).to_string(),
+            Message::Block(lines) => {
+                for line in lines {
+                    line.title = self.title(&line.title).to_string();
+                }
+            }
+            Message::Event(event) => {
+                let title = self.title(event.title()).to_string();
+                *event.title_mut() = title;
+            }
+        }
+        message
+    }
+
+    /// Give the terminal to the process, or back to the interactive sinks with `None`.
+    pub fn set_foreground(&mut self, title: Option<String>) {
+        self.foreground = title;
+    }
+
+    pub async fn finish(&mut self) -> io::Result<()> {
+        for sink in &mut self.sinks {
+            sink.finish().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Formatted output of the terminal, or of another writer written like it e.g. by `bench`.
+pub struct StdoutSink<W = Stdout> {
+    formatter: Formatter,
+    stdout: W,
+    /// Status line of starting processes shown below the output.
+    status: Option<String>,
+}
+
+impl StdoutSink {
+    pub fn new(formatter: Formatter) -> Self {
+        Self::with_writer(formatter, io::stdout())
+    }
+}
+
+impl<W> StdoutSink<W> {
+    pub fn with_writer(formatter: Formatter, writer: W) -> Self {
+        Self {
+            formatter,
+            stdout: writer,
+            status: None,
+        }
+    }
+}
+
+#[async_trait]
+impl<W: AsyncWrite + Unpin + Send> Sink for StdoutSink<W> {
+    async fn write(&mut self, message: &Message) -> io::Result<()> {
+        let formatted = match message {
+            Message::Event(event) => self.formatter.format_event(event),
+            _ => Some(
+                message
+                    .lines()
+                    .iter()
+                    .map(|line| self.formatter.format(line))
+                    .collect(),
+            ),
+        };
+        let event = match message {
+            Message::Event(event) => Some(event),
+            _ => None,
+        };
+        let status = self.formatter.status(event);
+        if formatted.is_none() && status == self.status {
+            return Ok(());
+        }
+
+        // status line is written again below the output
+        let mut output = match self.status {
+            Some(_) => CLEAR_LINE.to_string(),
+            None => String::new(),
+        };
+        output.push_str(formatted.as_deref().unwrap_or_default());
+        if let Some(status) = &status {
+            output.push_str(status);
+        }
+        self.stdout.write_all(output.as_bytes()).await?;
+        if status.is_some() {
+            self.stdout.flush().await?;
+        }
+        self.status = status;
+
+        Ok(())
+    }
+
+    fn interactive(&self) -> bool {
+        true
+    }
+
+    async fn finish(&mut self) -> io::Result<()> {
+        if self.status.take().is_some() {
+            self.stdout.write_all(CLEAR_LINE.as_bytes()).await?;
+        }
+        self.stdout
+            .write_all(self.formatter.finish().as_bytes())
+            .await?;
+        self.stdout.flush().await
+    }
+}
+
+/// File appended to by a sink, rotated when writing would grow it past `max_size` of the
+/// rotation.
+struct LogFile {
+    path: PathBuf,
+    permissions: FilePermissions,
+    rotation: Option<Rotation>,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    async fn open(
+        path: &Path,
+        permissions: &FilePermissions,
+        rotation: Option<Rotation>,
+    ) -> io::Result<Self> {
+        let file = permissions.append(path).await?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            permissions: permissions.clone(),
+            rotation,
+            size: file.metadata().await?.len(),
+            file,
+        })
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let full = self.rotation.as_ref().is_some_and(|rotation| {
+            self.size > 0 && self.size + bytes.len() as u64 > rotation.max_size.0
+        });
+        if full {
+            self.rotate().await?;
+        }
+        self.file.write_all(bytes).await?;
+        self.size += bytes.len() as u64;
+        Ok(())
+    }
+
+    async fn rotate(&mut self) -> io::Result<()> {
+        let Some(rotation) = &self.rotation else {
+            return Ok(());
+        };
+        self.file.flush().await?;
+        let extension = match rotation.compress {
+            true => ".gz",
+            false => "",
+        };
+        let rotated = |n: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{n}{extension}"));
+            PathBuf::from(path)
+        };
+
+        // oldest file is dropped and the others shifted up by one
+        if rotation.keep == 0 {
+            tokio::fs::remove_file(&self.path).await?;
+        } else {
+            remove_if_exists(&rotated(rotation.keep)).await?;
+            for n in (1..rotation.keep).rev() {
+                match tokio::fs::rename(rotated(n), rotated(n + 1)).await {
+                    Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+                    _ => (),
+                }
+            }
+            match rotation.compress {
+                true => {
+                    let contents = tokio::fs::read(&self.path).await?;
+                    let compressed = tokio::task::spawn_blocking(move || gzip(&contents))
+                        .await
+                        .map_err(io::Error::other)?;
+                    let mut file = self.permissions.append(&rotated(1)).await?;
+                    file.write_all(&compressed).await?;
+                    file.flush().await?;
+                    tokio::fs::remove_file(&self.path).await?;
+                }
+                false => tokio::fs::rename(&self.path, rotated(1)).await?,
+            }
+        }
+
+        self.file = self.permissions.append(&self.path).await?;
+        self.size = 0;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        self.file.flush().await
+    }
+}
+
+async fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Bytes compressed to gzip format.
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    // magic, deflate method, no flags, no modification time, no extra flags, unknown os
+    let mut gzip = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    gzip.extend(miniz_oxide::deflate::compress_to_vec(bytes, 6));
+    gzip.extend(crc32(bytes).to_le_bytes());
+    gzip.extend((bytes.len() as u32).to_le_bytes());
+    gzip
+}
+
+/// CRC-32 checksum of gzip trailer.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Output lines prefixed with the title without colors.
+pub struct FileSink {
+    formatter: Formatter,
+    file: LogFile,
+}
+
+impl FileSink {
+    pub async fn open(
+        path: &Path,
+        permissions: &FilePermissions,
+        rotation: Option<Rotation>,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            formatter: Formatter::new(Terminal::plain(), OutputMode::Stream),
+            file: LogFile::open(path, permissions, rotation).await?,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    async fn write(&mut self, message: &Message) -> io::Result<()> {
+        let formatted = message
+            .lines()
+            .iter()
+            .map(|line| self.formatter.format(line))
+            .collect::<String>();
+        self.file.write(formatted.as_bytes()).await
+    }
+
+    async fn finish(&mut self) -> io::Result<()> {
+        self.file.flush().await
+    }
+}
+
+/// Messages as NDJSON, optionally only the lifecycle events.
+pub struct JsonSink {
+    file: LogFile,
+    lines: bool,
+}
+
+impl JsonSink {
+    pub async fn open(
+        path: &Path,
+        lines: bool,
+        permissions: &FilePermissions,
+        rotation: Option<Rotation>,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            file: LogFile::open(path, permissions, rotation).await?,
+            lines,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for JsonSink {
+    async fn write(&mut self, message: &Message) -> io::Result<()> {
+        if !matches!(message, Message::Event(_)) && !self.lines {
+            return Ok(());
+        }
+        self.file.write(message.to_json_line().as_bytes()).await
+    }
+
+    async fn finish(&mut self) -> io::Result<()> {
+        self.file.flush().await
+    }
+}
+
+/// Output lines sent to `/dev/log` with stderr lines logged as errors.
+pub struct SyslogSink {
+    #[cfg(unix)]
+    socket: tokio::net::UnixDatagram,
+    ident: String,
+}
+
+impl SyslogSink {
+    #[cfg(unix)]
+    pub fn connect(ident: String) -> io::Result<Self> {
+        let socket = tokio::net::UnixDatagram::unbound()?;
+        socket.connect("/dev/log")?;
+
+        Ok(Self { socket, ident })
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect(_: String) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "syslog sink is only supported on unix",
+        ))
+    }
+}
+
+#[async_trait]
+impl Sink for SyslogSink {
+    async fn write(&mut self, message: &Message) -> io::Result<()> {
+        for line in message.lines() {
+            // facility user with severity error or info
+            let priority = match line.stream {
+                Stream::Stderr => 8 + 3,
+                Stream::Stdout | Stream::Notice => 8 + 6,
+            };
+            let record = format!(
+                "<{priority}>{}[{}]: [ {} ] {}",
+                self.ident,
+                std::process::id(),
+                line.title,
+                line.text
+            );
+
+            #[cfg(unix)]
+            self.socket.send(record.as_bytes()).await?;
+            #[cfg(not(unix))]
+            let _ = record;
+        }
+        Ok(())
+    }
+}
+
+/// Lifecycle events posted as JSON.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    requests: JoinSet<()>,
+}
+
+impl WebhookSink {
+    pub fn new(url: String) -> io::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .map_err(io::Error::other)?;
+
+        Ok(Self {
+            client,
+            url,
+            requests: JoinSet::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    async fn write(&mut self, message: &Message) -> io::Result<()> {
+        if !matches!(message, Message::Event(_)) {
+            return Ok(());
+        }
+        let request = self
+            .client
+            .post(&self.url)
+            .header("content-type", "application/json")
+            .body(message.to_json_line());
+        // slow or failing webhook must not hold the output of the processes
+        while self.requests.try_join_next().is_some() {}
+        self.requests.spawn(async move {
+            let _ = request.send().await;
+        });
+
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> io::Result<()> {
+        while self.requests.join_next().await.is_some() {}
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Broker {
+    Nats,
+    Mqtt,
+}
+
+impl Broker {
+    /// Connect to the broker, returning the connection once it is able to publish.
+    async fn connect(self, address: &str) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect(address).await?;
+        match self {
+            Broker::Nats => {
+                stream
+                    .write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false}\r\n")
+                    .await?
+            }
+            Broker::Mqtt => {
+                // MQTT 3.1.1 with clean session, no keep alive and client id of the process
+                let client_id = format!("watchmux-{}", std::process::id());
+                let mut variable = vec![0, 4, b'M', b'Q', b'T', b'T', 4, 0x02, 0, 0];
+                variable.extend(mqtt_string(&client_id));
+                stream.write_all(&mqtt_packet(0x10, &variable)).await?;
+
+                let mut connack = [0; 4];
+                stream.read_exact(&mut connack).await?;
+                if connack[0] != 0x20 || connack[3] != 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionRefused,
+                        format!("mqtt broker refused connection with code {}", connack[3]),
+                    ));
+                }
+            }
+        }
+
+        Ok(stream)
+    }
+
+    /// Topic of the process under the `topic` of the sink.
+    fn topic(self, topic: &str, title: &str) -> String {
+        // separators and wildcards of the topics are replaced in the title
+        let title = title
+            .chars()
+            .map(|char| match char {
+                char if char.is_alphanumeric() || char == '-' || char == '_' => char,
+                _ => '_',
+            })
+            .collect::<String>();
+        match self {
+            Broker::Nats => format!("{topic}.{title}"),
+            Broker::Mqtt => format!("{topic}/{title}"),
+        }
+    }
+
+    fn publish(self, topic: &str, payload: &str) -> Vec<u8> {
+        match self {
+            Broker::Nats => format!("PUB {topic} {}\r\n{payload}\r\n", payload.len()).into_bytes(),
+            Broker::Mqtt => {
+                let mut variable = mqtt_string(topic);
+                variable.extend(payload.as_bytes());
+                mqtt_packet(0x30, &variable)
+            }
+        }
+    }
+}
+
+fn mqtt_string(value: &str) -> Vec<u8> {
+    let mut bytes = (value.len() as u16).to_be_bytes().to_vec();
+    bytes.extend(value.as_bytes());
+    bytes
+}
+
+/// Packet of the type with remaining length encoded 7 bits per byte.
+fn mqtt_packet(packet_type: u8, content: &[u8]) -> Vec<u8> {
+    let mut packet = vec![packet_type];
+    let mut length = content.len();
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        if length == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend(content);
+    packet
+}
+
+/// Output lines and events as JSON published to topic of the process of NATS or MQTT.
+pub struct BrokerSink {
+    broker: Broker,
+    topic: String,
+    messages: Option<mpsc::Sender<(String, String)>>,
+    publisher: Option<JoinHandle<()>>,
+}
+
+impl BrokerSink {
+    fn new(broker: Broker, address: String, topic: String) -> Self {
+        let (messages, receiver) = mpsc::channel(BROKER_BUFFER);
+
+        Self {
+            broker,
+            topic,
+            messages: Some(messages),
+            publisher: Some(tokio::spawn(BrokerSink::publish(broker, address, receiver))),
+        }
+    }
+
+    /// Publish the messages, connecting again when the connection is lost. Messages are dropped
+    /// while the broker is unreachable.
+    async fn publish(
+        broker: Broker,
+        address: String,
+        mut messages: mpsc::Receiver<(String, String)>,
+    ) {
+        let mut connection: Option<TcpStream> = None;
+        let mut reconnect_at = Instant::now();
+        let mut buffer = [0; 512];
+        loop {
+            let (topic, payload) = match &mut connection {
+                Some(stream) => tokio::select! {
+                    message = messages.recv() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
+                    // NATS server closes connections not answering its pings
+                    read = stream.read(&mut buffer) => {
+                        match read {
+                            Ok(read) if read > 0 => {
+                                if buffer[..read].windows(4).any(|window| window == b"PING")
+                                    && stream.write_all(b"PONG\r\n").await.is_err()
+                                {
+                                    connection = None;
+                                }
+                            }
+                            _ => connection = None,
+                        }
+                        continue;
+                    }
+                },
+                None => match messages.recv().await {
+                    Some(message) => message,
+                    None => break,
+                },
+            };
+
+            if connection.is_none() && Instant::now() >= reconnect_at {
+                reconnect_at = Instant::now() + BROKER_RECONNECT;
+                connection = time::timeout(BROKER_TIMEOUT, broker.connect(&address))
+                    .await
+                    .ok()
+                    .and_then(Result::ok);
+            }
+            if let Some(stream) = &mut connection {
+                if stream
+                    .write_all(&broker.publish(&topic, &payload))
+                    .await
+                    .is_err()
+                {
+                    connection = None;
+                }
+            }
+        }
+
+        if let Some(mut stream) = connection {
+            let _ = stream.shutdown().await;
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for BrokerSink {
+    async fn write(&mut self, message: &Message) -> io::Result<()> {
+        let records = match message {
+            Message::Event(event) => vec![(event.title(), message.to_json_line())],
+            _ => message
+                .lines()
+                .iter()
+                .map(|line| (line.title.as_str(), line.to_json_line()))
+                .collect(),
+        };
+        if let Some(messages) = &self.messages {
+            for (title, payload) in records {
+                // unreachable or slow broker must not hold the output of the processes
+                let _ = messages.try_send((
+                    self.broker.topic(&self.topic, title),
+                    payload.trim_end().to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> io::Result<()> {
+        self.messages.take();
+        if let Some(publisher) = self.publisher.take() {
+            let _ = time::timeout(BROKER_TIMEOUT, publisher).await;
+        }
+        Ok(())
+    }
+}
+
+/// Signal of OpenTelemetry exported to its own path of the collector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Signal {
+    Logs,
+    Traces,
+}
+
+impl Signal {
+    fn path(self) -> &'static str {
+        match self {
+            Signal::Logs => "/v1/logs",
+            Signal::Traces => "/v1/traces",
+        }
+    }
+
+    /// Request of the records grouped by the process they are of.
+    fn request(self, namespace: &str, records: Vec<(String, Value)>) -> Value {
+        let (resources, scopes, items) = match self {
+            Signal::Logs => ("resourceLogs", "scopeLogs", "logRecords"),
+            Signal::Traces => ("resourceSpans", "scopeSpans", "spans"),
+        };
+        let mut processes: Vec<(String, Vec<Value>)> = Vec::new();
+        for (title, record) in records {
+            match processes.iter_mut().find(|(process, _)| *process == title) {
+                Some((_, records)) => records.push(record),
+                None => processes.push((title, vec![record])),
+            }
+        }
+
+        json!({
+            resources: processes
+                .into_iter()
+                .map(|(title, records)| json!({
+                    "resource": {
+                        "attributes": [
+                            attribute("service.name", json!({ "stringValue": title })),
+                            attribute("service.namespace", json!({ "stringValue": namespace })),
+                        ],
+                    },
+                    scopes: [{
+                        "scope": { "name": "watchmux", "version": env!("CARGO_PKG_VERSION") },
+                        items: records,
+                    }],
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+fn unix_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default()
+}
+
+/// Output lines as log records and lifecycle events as spans exported to OpenTelemetry collector
+/// with OTLP/HTTP JSON. A span covers a run of the process from its start to its exit, other
+/// events are spans without duration. Spans of watchmux share a trace.
+pub struct OtlpSink {
+    trace_id: String,
+    spans: u64,
+    /// Span id and start of the current run of each process.
+    runs: HashMap<String, (String, u128)>,
+    records: Option<mpsc::Sender<(Signal, String, Value)>>,
+    exporter: Option<JoinHandle<()>>,
+}
+
+impl OtlpSink {
+    fn new(endpoint: &str, namespace: String) -> io::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .map_err(io::Error::other)?;
+        let (records, receiver) = mpsc::channel(OTLP_BUFFER);
+        let seed = format!("{}-{}", std::process::id(), unix_nanos());
+
+        Ok(Self {
+            trace_id: hex(&seed)[..32].to_string(),
+            spans: 0,
+            runs: HashMap::new(),
+            records: Some(records),
+            exporter: Some(tokio::spawn(OtlpSink::export(
+                client,
+                endpoint.trim_end_matches('/').to_string(),
+                namespace,
+                receiver,
+            ))),
+        })
+    }
+
+    fn span_id(&mut self) -> String {
+        self.spans += 1;
+        hex(&format!("{}-{}", self.trace_id, self.spans))[..16].to_string()
+    }
+
+    /// Span of the event, `None` when the event starts a run.
+    fn span(&mut self, event: &Event, now: u128) -> Option<Value> {
+        let (name, span_id, start, attributes, error) = match event {
+            Event::Started { title, .. } => {
+                let span_id = self.span_id();
+                self.runs.insert(title.clone(), (span_id, now));
+                return None;
+            }
+            Event::Exited {
+                title,
+                code,
+                success,
+                ..
+            } => {
+                let (span_id, start) = match self.runs.remove(title) {
+                    Some(run) => run,
+                    None => (self.span_id(), now),
+                };
+                let error = match code {
+                    _ if *success => None,
+                    Some(code) => Some(format!("exited with code {code}")),
+                    None => Some("terminated by signal".to_string()),
+                };
+                let attributes = code
+                    .iter()
+                    .map(|code| {
+                        attribute("process.exit.code", json!({ "intValue": code.to_string() }))
+                    })
+                    .collect();
+                ("run", span_id, start, attributes, error)
+            }
+            Event::Restarted {
+                reason, flap_score, ..
+            } => (
+                "restarted",
+                self.span_id(),
+                now,
+                vec![
+                    attribute("watchmux.restart.reason", json!({ "stringValue": reason })),
+                    attribute(
+                        "watchmux.flap_score",
+                        json!({ "intValue": flap_score.to_string() }),
+                    ),
+                ],
+                None,
+            ),
+            Event::Failed { error, .. } => (
+                "failed",
+                self.span_id(),
+                now,
+                Vec::new(),
+                Some(error.clone()),
+            ),
+            Event::Blocked { dependency, .. } => (
+                "blocked",
+                self.span_id(),
+                now,
+                vec![attribute(
+                    "watchmux.dependency",
+                    json!({ "stringValue": dependency }),
+                )],
+                Some(format!("blocked by dependency {dependency}")),
+            ),
+            Event::Deferred { reason, .. } => (
+                "deferred",
+                self.span_id(),
+                now,
+                vec![attribute(
+                    "watchmux.deferred.reason",
+                    json!({ "stringValue": reason }),
+                )],
+                None,
+            ),
+            Event::Skipped { .. } => ("skipped", self.span_id(), now, Vec::new(), None),
+        };
+
+        Some(json!({
+            "traceId": self.trace_id,
+            "spanId": span_id,
+            "name": name,
+            // internal
+            "kind": 1,
+            "startTimeUnixNano": start.to_string(),
+            "endTimeUnixNano": now.to_string(),
+            "attributes": attributes,
+            "status": match error {
+                Some(message) => json!({ "code": 2, "message": message }),
+                None => json!({ "code": 1 }),
+            },
+        }))
+    }
+
+    /// Export the records in batches every [`OTLP_FLUSH`]. Batches failing to export are dropped.
+    async fn export(
+        client: reqwest::Client,
+        endpoint: String,
+        namespace: String,
+        mut records: mpsc::Receiver<(Signal, String, Value)>,
+    ) {
+        let mut logs = Vec::new();
+        let mut spans = Vec::new();
+        let mut flush = time::interval(OTLP_FLUSH);
+        loop {
+            let open = tokio::select! {
+                record = records.recv() => match record {
+                    Some((Signal::Logs, title, record)) => {
+                        logs.push((title, record));
+                        continue;
+                    }
+                    Some((Signal::Traces, title, record)) => {
+                        spans.push((title, record));
+                        continue;
+                    }
+                    None => false,
+                },
+                _ = flush.tick() => true,
+            };
+
+            for (signal, records) in [
+                (Signal::Logs, std::mem::take(&mut logs)),
+                (Signal::Traces, std::mem::take(&mut spans)),
+            ] {
+                if records.is_empty() {
+                    continue;
+                }
+                let request = signal.request(&namespace, records);
+                let _ = client
+                    .post(format!("{endpoint}{}", signal.path()))
+                    .json(&request)
+                    .send()
+                    .await;
+            }
+            if !open {
+                break;
+            }
+        }
+    }
+}
+
+fn hex(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[async_trait]
+impl Sink for OtlpSink {
+    async fn write(&mut self, message: &Message) -> io::Result<()> {
+        let now = unix_nanos();
+        let records = match message {
+            Message::Event(event) => self
+                .span(event, now)
+                .map(|span| (Signal::Traces, event.title().to_string(), span))
+                .into_iter()
+                .collect::<Vec<_>>(),
+            _ => message
+                .lines()
+                .iter()
+                .map(|line| {
+                    let mut attributes = vec![attribute(
+                        "watchmux.stream",
+                        json!({ "stringValue": line.stream }),
+                    )];
+                    if let Some(pid) = line.pid {
+                        attributes.push(attribute(
+                            "process.pid",
+                            json!({ "intValue": pid.to_string() }),
+                        ));
+                    }
+                    // received time of the line is not wall clock time with --deterministic
+                    let record = json!({
+                        "timeUnixNano": now.to_string(),
+                        "observedTimeUnixNano": now.to_string(),
+                        "body": { "stringValue": line.text },
+                        "attributes": attributes,
+                    });
+                    (Signal::Logs, line.title.clone(), record)
+                })
+                .collect(),
+        };
+        if let Some(sender) = &self.records {
+            for record in records {
+                // unreachable or slow collector must not hold the output of the processes
+                let _ = sender.try_send(record);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> io::Result<()> {
+        self.records.take();
+        if let Some(exporter) = self.exporter.take() {
+            let _ = time::timeout(WEBHOOK_TIMEOUT, exporter).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotation(max_size: u64, keep: usize, compress: bool) -> Option<Rotation> {
+        Some(Rotation {
+            max_size: ByteSize(max_size),
+            keep,
+            compress,
+        })
+    }
+
+    fn log_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("watchmux-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Interactive sink recording the texts of the lines written to it.
+    struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl Sink for Recorder {
+        async fn write(&mut self, message: &Message) -> io::Result<()> {
+            let mut written = self.0.lock().unwrap();
+            written.extend(message.lines().iter().map(|line| line.text.clone()));
+            Ok(())
+        }
+
+        fn interactive(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn muted_process_is_left_out_apart_from_heartbeat() {
+        let written = std::sync::Arc::default();
+        let mut sinks = Sinks::default();
+        sinks.register(Box::new(Recorder(std::sync::Arc::clone(&written))));
+        let line = |text: &str| Message::Line(Line::new("worker", Stream::Stdout, text));
+
+        assert!(sinks.set_muted("worker", true));
+        assert!(!sinks.set_muted("worker", true));
+        sinks.write(&line("first")).await.unwrap();
+        sinks.write(&line("second")).await.unwrap();
+        sinks.heartbeat().await.unwrap();
+        sinks.heartbeat().await.unwrap();
+        assert!(sinks.set_muted("worker", false));
+        sinks.write(&line("third")).await.unwrap();
+
+        assert_eq!(*written.lock().unwrap(), ["2 lines muted", "third"]);
+    }
+
+    #[test]
+    fn crc32_is_the_one_of_gzip() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[tokio::test]
+    async fn file_is_rotated_once_it_would_grow_past_max_size() {
+        let dir = log_dir("rotate");
+        let path = dir.join("out.log");
+        let mut file = LogFile::open(&path, &FilePermissions::default(), rotation(10, 2, false))
+            .await
+            .unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write(line.as_bytes()).await.unwrap();
+        }
+        file.flush().await.unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("out.log"), "fourth\n");
+        assert_eq!(read("out.log.1"), "third\n");
+        assert_eq!(read("out.log.2"), "second\n");
+        assert!(!dir.join("out.log.3").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rotated_file_is_compressed() {
+        let dir = log_dir("compress");
+        let path = dir.join("out.log");
+        let mut file = LogFile::open(&path, &FilePermissions::default(), rotation(10, 1, true))
+            .await
+            .unwrap();
+
+        file.write(b"first line\n").await.unwrap();
+        file.write(b"second line\n").await.unwrap();
+        file.flush().await.unwrap();
+
+        let gzip = std::fs::read(dir.join("out.log.1.gz")).unwrap();
+        assert_eq!(gzip[..2], [0x1f, 0x8b]);
+        let deflated = &gzip[10..gzip.len() - 8];
+        let inflated = miniz_oxide::inflate::decompress_to_vec(deflated).unwrap();
+        assert_eq!(inflated, b"first line\n");
+        assert_eq!(
+            gzip[gzip.len() - 8..gzip.len() - 4],
+            crc32(&inflated).to_le_bytes()
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"second line\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Units of the sizes, decimal and binary.
+const UNITS: [(&str, u64); 7] = [
+    ("GiB", 1 << 30),
+    ("MiB", 1 << 20),
+    ("KiB", 1 << 10),
+    ("GB", 1_000_000_000),
+    ("MB", 1_000_000),
+    ("KB", 1_000),
+    ("B", 1),
+];
+
+/// Amount of bytes given with optional unit in any case e.g. `50MB`, `512KiB`, `10mb` or `1024`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(size: &str) -> Result<Self, Self::Err> {
+        let size = size.trim();
+        let (number, multiplier) = UNITS
+            .iter()
+            .find_map(|(unit, multiplier)| {
+                let number = size.get(..size.len().checked_sub(unit.len())?)?;
+                size[number.len()..]
+                    .eq_ignore_ascii_case(unit)
+                    .then(|| (number.trim(), *multiplier))
+            })
+            .unwrap_or((size, 1));
+
+        number
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| number.checked_mul(multiplier))
+            .map(ByteSize)
+            .ok_or_else(|| format!("invalid size {size}, expected e.g. 50MB, 512KiB or 1024"))
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // largest unit the size is a whole number of
+        match UNITS
+            .iter()
+            .filter(|(_, multiplier)| self.0 >= *multiplier && self.0.is_multiple_of(*multiplier))
+            .max_by_key(|(_, multiplier)| *multiplier)
+        {
+            Some((unit, multiplier)) => write!(f, "{}{unit}", self.0 / multiplier),
+            None => write!(f, "{}B", self.0),
+        }
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Size {
+            Bytes(u64),
+            Text(String),
+        }
+
+        match Size::deserialize(deserializer)? {
+            Size::Bytes(bytes) => Ok(ByteSize(bytes)),
+            Size::Text(size) => size.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(size: &str) -> Result<u64, String> {
+        size.parse::<ByteSize>().map(|size| size.0)
+    }
+
+    #[test]
+    fn sizes_are_parsed_with_decimal_and_binary_units_in_any_case() {
+        assert_eq!(parse("1024"), Ok(1024));
+        assert_eq!(parse("0"), Ok(0));
+        assert_eq!(parse("10B"), Ok(10));
+        assert_eq!(parse("50MB"), Ok(50_000_000));
+        assert_eq!(parse("512KiB"), Ok(512 << 10));
+        assert_eq!(parse("2GiB"), Ok(2 << 30));
+        assert_eq!(parse("10mb"), Ok(10_000_000));
+        assert_eq!(parse("1kib"), Ok(1024));
+        assert_eq!(parse("3Gb"), Ok(3_000_000_000));
+        assert_eq!(parse(" 50 MB "), Ok(50_000_000));
+    }
+
+    #[test]
+    fn invalid_or_overflowing_sizes_are_rejected() {
+        for size in [
+            "",
+            "MB",
+            "-1MB",
+            "1.5MB",
+            "50TB",
+            "ten",
+            "50 M B",
+            "€",
+            "18446744073709551616",
+            "17179869184GiB",
+        ] {
+            assert_eq!(
+                parse(size),
+                Err(format!(
+                    "invalid size {}, expected e.g. 50MB, 512KiB or 1024",
+                    size.trim()
+                )),
+                "{size}"
+            );
+        }
+        assert_eq!(parse("18446744073709551615"), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn sizes_are_shown_in_largest_whole_unit() {
+        let shown = |bytes| ByteSize(bytes).to_string();
+        assert_eq!(shown(0), "0B");
+        assert_eq!(shown(1500), "1500B");
+        assert_eq!(shown(2_000), "2KB");
+        assert_eq!(shown(1 << 20), "1MiB");
+        assert_eq!(shown(3_000_000_000), "3GB");
+    }
+}
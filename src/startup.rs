@@ -0,0 +1,70 @@
+use tokio::sync::watch;
+
+use crate::{config::WatchProcess, event::Event, shutdown::Shutdown};
+
+/// Starts the processes one by one in order of the config with `--deterministic`. Process is
+/// started once the previous process has started, failed or exited. Processes waiting for
+/// `depends_on`, `wait_for` or their phase let the next process start right away as they might
+/// wait for it.
+pub struct StartupOrder {
+    titles: Vec<String>,
+    started: Vec<watch::Sender<bool>>,
+}
+
+/// Turn of a single process in the startup order.
+pub struct Turn {
+    previous: Option<watch::Receiver<bool>>,
+    /// Started of the process itself given to the next process when it waits for others.
+    waits: Option<watch::Sender<bool>>,
+}
+
+impl StartupOrder {
+    pub fn new(processes: &[WatchProcess]) -> Self {
+        Self {
+            titles: processes
+                .iter()
+                .map(|process| process.title.clone())
+                .collect(),
+            started: processes
+                .iter()
+                .map(|_| watch::Sender::new(false))
+                .collect(),
+        }
+    }
+
+    /// Turn of the process, which is `phased` when it waits for its phase to start.
+    pub fn turn(&self, index: usize, process: &WatchProcess, phased: bool) -> Turn {
+        Turn {
+            previous: index
+                .checked_sub(1)
+                .map(|previous| self.started[previous].subscribe()),
+            waits: (phased || !process.depends_on.is_empty() || !process.wait_for.is_empty())
+                .then(|| self.started[index].clone()),
+        }
+    }
+
+    /// Let the next process start after the first event of the process.
+    pub fn record(&self, event: &Event) {
+        if let Some(index) = self.titles.iter().position(|title| title == event.title()) {
+            self.started[index].send_replace(true);
+        }
+    }
+}
+
+impl Turn {
+    /// Wait for the previous process to start. Returns `false` when the process is stopped
+    /// before that.
+    pub async fn wait(self, mut shutdown: watch::Receiver<Shutdown>) -> bool {
+        if let Some(mut previous) = self.previous {
+            tokio::select! {
+                _ = previous.wait_for(|started| *started) => (),
+                _ = shutdown.wait_for(|state| *state != Shutdown::Running) => return false,
+            }
+        }
+        if let Some(waits) = self.waits {
+            waits.send_replace(true);
+        }
+
+        true
+    }
+}
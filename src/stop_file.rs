@@ -0,0 +1,53 @@
+use std::{path::PathBuf, time::Duration};
+
+use tokio::{fs, sync::mpsc, time};
+
+/// Interval of checking whether the stop file has appeared.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What the stop file asks to stop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopRequest {
+    /// Empty file stops watchmux gracefully.
+    All,
+    /// Processes titled by the lines of the file.
+    Processes(Vec<String>),
+}
+
+/// Watch for the stop file to appear, removing it once read so it can be created again. File
+/// left behind by an earlier run is removed first. Never receives any request without the file.
+pub fn watch(path: Option<PathBuf>) -> mpsc::Receiver<StopRequest> {
+    let (tx, rx) = mpsc::channel(4);
+    let Some(path) = path else {
+        return rx;
+    };
+
+    tokio::spawn(async move {
+        let _ = fs::remove_file(&path).await;
+        let mut interval = time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            // not created yet
+            let Ok(content) = fs::read(&path).await else {
+                continue;
+            };
+            let _ = fs::remove_file(&path).await;
+
+            let titles = String::from_utf8_lossy(&content)
+                .lines()
+                .map(str::trim)
+                .filter(|title| !title.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+            let request = match titles.is_empty() {
+                true => StopRequest::All,
+                false => StopRequest::Processes(titles),
+            };
+            if tx.send(request).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
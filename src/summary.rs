@@ -0,0 +1,240 @@
+use std::{collections::VecDeque, time::Duration};
+
+use serde_json::{json, Value};
+use tokio::time::Instant;
+
+use crate::event::{Event, Line, Stream};
+
+/// Title of the lines written by watchmux itself.
+pub const WATCHMUX_TITLE: &str = "watchmux";
+/// Amount of the latest run durations the statistics are calculated from.
+const DURATIONS_SIZE: usize = 100;
+
+/// Runs of a single process.
+#[derive(Debug)]
+struct ProcessSummary {
+    title: String,
+    runs: u32,
+    restarts: u32,
+    /// Exit code of the last run, `None` when terminated by signal.
+    code: Option<Option<i32>>,
+    success: bool,
+    /// Start of the first run and exit of the last run.
+    started: Option<Instant>,
+    exited: Option<Instant>,
+    /// Start of the current run.
+    run_started: Option<Instant>,
+    /// Durations of the latest runs which exited.
+    durations: VecDeque<Duration>,
+    /// Error the process failed with or dependency it was blocked by.
+    failure: Option<String>,
+    /// What the machine lacks to start the process while it is deferred.
+    deferred: Option<String>,
+    /// Note attached to the process at runtime with `watchmux ctl annotate`.
+    note: Option<String>,
+}
+
+impl ProcessSummary {
+    /// Duration of the last run with average and 95th percentile of the latest runs e.g.
+    /// `last: 4.2s, avg: 5.1s, p95: 9.0s`.
+    fn durations(&self) -> Option<String> {
+        let last = *self.durations.back()?;
+        let average = self.durations.iter().sum::<Duration>() / self.durations.len() as u32;
+        let mut sorted = self.durations.iter().copied().collect::<Vec<_>>();
+        sorted.sort();
+        // nearest rank
+        let p95 = sorted[(sorted.len() * 95).div_ceil(100) - 1];
+
+        Some(format!(
+            "last: {:.1}s, avg: {:.1}s, p95: {:.1}s",
+            last.as_secs_f64(),
+            average.as_secs_f64(),
+            p95.as_secs_f64()
+        ))
+    }
+}
+
+/// Summary of runs of the processes collected from their events.
+#[derive(Debug)]
+pub struct Summary {
+    started: Instant,
+    processes: Vec<ProcessSummary>,
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            processes: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        match event {
+            Event::Started { title, .. } => {
+                let process = self.process(title);
+                process.runs += 1;
+                process.started.get_or_insert_with(Instant::now);
+                process.run_started = Some(Instant::now());
+                process.deferred = None;
+            }
+            Event::Exited {
+                title,
+                code,
+                success,
+                ..
+            } => {
+                let process = self.process(title);
+                process.code = Some(*code);
+                process.success = *success;
+                process.exited = Some(Instant::now());
+                if let Some(run_started) = process.run_started.take() {
+                    if process.durations.len() == DURATIONS_SIZE {
+                        process.durations.pop_front();
+                    }
+                    process.durations.push_back(run_started.elapsed());
+                }
+            }
+            Event::Restarted { title, .. } => self.process(title).restarts += 1,
+            Event::Failed { title, error } => self.process(title).failure = Some(error.clone()),
+            Event::Blocked { title, dependency } => {
+                self.process(title).failure = Some(format!("blocked by dependency {dependency}"))
+            }
+            Event::Deferred { title, reason } => {
+                self.process(title).deferred = Some(reason.clone())
+            }
+            Event::Skipped { .. } => (),
+        }
+    }
+
+    pub fn annotate(&mut self, title: &str, note: String) {
+        self.process(title).note = Some(note);
+    }
+
+    fn process(&mut self, title: &str) -> &mut ProcessSummary {
+        let index = match self
+            .processes
+            .iter()
+            .position(|process| process.title == title)
+        {
+            Some(index) => index,
+            None => {
+                self.processes.push(ProcessSummary {
+                    title: title.to_string(),
+                    runs: 0,
+                    restarts: 0,
+                    code: None,
+                    success: false,
+                    started: None,
+                    exited: None,
+                    run_started: None,
+                    durations: VecDeque::new(),
+                    failure: None,
+                    deferred: None,
+                    note: None,
+                });
+                self.processes.len() - 1
+            }
+        };
+
+        &mut self.processes[index]
+    }
+
+    /// Lines describing uptime of watchmux and the last exit and note of each process.
+    pub fn lines(&self) -> Vec<Line> {
+        let uptime = humantime::format_duration(std::time::Duration::from_secs(
+            self.started.elapsed().as_secs(),
+        ));
+        let mut lines = vec![Line::new(
+            WATCHMUX_TITLE,
+            Stream::Notice,
+            format!("ran for {uptime}"),
+        )];
+
+        lines.extend(self.processes.iter().map(|process| {
+            let exit = match process.code {
+                Some(Some(code)) => format!("exited with code {code}"),
+                Some(None) => "terminated by signal".to_string(),
+                None => match &process.deferred {
+                    Some(reason) => format!("deferred until {reason}"),
+                    None => "did not exit".to_string(),
+                },
+            };
+            let durations = process
+                .durations()
+                .map(|durations| format!(", {durations}"))
+                .unwrap_or_default();
+            let note = process
+                .note
+                .as_ref()
+                .map(|note| format!(", note: {note}"))
+                .unwrap_or_default();
+            Line::new(
+                WATCHMUX_TITLE,
+                Stream::Notice,
+                format!(
+                    "{}: {exit} (runs: {}{durations}){note}",
+                    process.title, process.runs
+                ),
+            )
+        }));
+
+        lines
+    }
+
+    /// Lines describing durations of the runs of processes which have exited more than once.
+    pub fn durations(&self) -> Vec<Line> {
+        self.processes
+            .iter()
+            .filter(|process| process.durations.len() > 1)
+            .filter_map(|process| {
+                Some(Line::new(
+                    WATCHMUX_TITLE,
+                    Stream::Notice,
+                    format!("{} runs took {}", process.title, process.durations()?),
+                ))
+            })
+            .collect()
+    }
+
+    /// Machine readable summary of the runs of each process written with `--result-file`.
+    pub fn result(&self) -> Value {
+        let processes = self
+            .processes
+            .iter()
+            .map(|process| {
+                let duration = process.started.map(|started| {
+                    process
+                        .exited
+                        .filter(|exited| *exited >= started)
+                        .unwrap_or_else(Instant::now)
+                        - started
+                });
+                let failure = process.failure.clone().or_else(|| match process.code {
+                    Some(Some(code)) if !process.success => {
+                        Some(format!("exited with code {code}"))
+                    }
+                    Some(None) => Some("terminated by signal".to_string()),
+                    _ => None,
+                });
+                json!({
+                    "title": process.title,
+                    "runs": process.runs,
+                    "restarts": process.restarts,
+                    "exited": process.code.is_some(),
+                    "exit_code": process.code.flatten(),
+                    "success": process.success && process.failure.is_none(),
+                    "duration_ms": duration.map(|duration| duration.as_millis() as u64),
+                    "failure": failure,
+                    "deferred": process.deferred,
+                    "note": process.note,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "duration_ms": self.started.elapsed().as_millis() as u64,
+            "processes": processes,
+        })
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::{HashMap, VecDeque};
+
+use tokio::time::Instant;
+
+use crate::{
+    config::WatchProcess,
+    event::{EndedBy, Event, Line, Message, Stream},
+    readiness::{ProcessState, Readiness},
+};
+
+/// Amount of the latest startup lines of a process kept to be written if it fails to start.
+const STARTUP_LINES: usize = 1000;
+
+/// Startup output of the processes with `suppress_until_ready` held back until they are ready,
+/// then replaced with a single line, or written after all when they fail before that.
+#[derive(Debug, Default)]
+pub struct Suppressions {
+    /// Startup of the processes not ready yet by title.
+    pending: HashMap<String, Startup>,
+}
+
+#[derive(Debug, Default)]
+struct Startup {
+    lines: VecDeque<Line>,
+    /// First line held back or start of the process, whichever came first.
+    since: Option<Instant>,
+}
+
+impl Suppressions {
+    pub fn new(processes: &[WatchProcess]) -> Self {
+        Self {
+            pending: processes
+                .iter()
+                .filter(|process| process.suppress_until_ready)
+                .map(|process| (process.title.clone(), Startup::default()))
+                .collect(),
+        }
+    }
+
+    /// Hold back output lines of the message when its process is not ready yet, returning whether
+    /// they were held back. Notices about the process are never held back.
+    pub fn hold(&mut self, message: &Message) -> bool {
+        let Some(startup) = message
+            .lines()
+            .first()
+            .filter(|line| line.stream != Stream::Notice)
+            .and_then(|line| self.pending.get_mut(&line.title))
+        else {
+            return false;
+        };
+        startup.since.get_or_insert_with(Instant::now);
+        for line in message.lines() {
+            if startup.lines.len() == STARTUP_LINES {
+                startup.lines.pop_front();
+            }
+            startup.lines.push_back(line.clone());
+        }
+
+        true
+    }
+
+    /// Track the event, returning the held back lines to write before it when the process fails
+    /// to start.
+    pub fn record(&mut self, event: &Event) -> Option<Message> {
+        let title = event.title();
+        let startup = self.pending.get_mut(title)?;
+        match event {
+            Event::Started { .. } => {
+                startup.since.get_or_insert_with(Instant::now);
+                None
+            }
+            Event::Exited {
+                success: false,
+                ended_by: None,
+                ..
+            }
+            | Event::Failed { .. }
+            | Event::Blocked { .. } => {
+                let startup = self.pending.remove(title)?;
+                (!startup.lines.is_empty()).then(|| Message::Block(startup.lines.into()))
+            }
+            // process killed to be restarted keeps starting
+            Event::Exited {
+                ended_by: Some(EndedBy::Restart),
+                ..
+            } => None,
+            // process which exits before it is ready has nothing left to start
+            Event::Exited { .. } | Event::Skipped { .. } => {
+                self.pending.remove(title);
+                None
+            }
+            Event::Restarted { .. } | Event::Deferred { .. } => None,
+        }
+    }
+
+    /// Line replacing the held back lines once the process is ready.
+    pub fn ready(&mut self, title: &str, readiness: &Readiness) -> Option<Line> {
+        let startup = self.pending.get(title)?;
+        if !readiness.reached(title, ProcessState::Ready) {
+            return None;
+        }
+        let elapsed = startup
+            .since
+            .map(|since| since.elapsed())
+            .unwrap_or_default();
+        self.pending.remove(title);
+
+        Some(Line::new(
+            title,
+            Stream::Notice,
+            format!("ready in {:.1}s", elapsed.as_secs_f64()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dependency::Exports;
+
+    fn processes() -> Vec<WatchProcess> {
+        serde_yaml::from_str(
+            "
+- { title: db, cmd: db, suppress_until_ready: true }
+- { title: api, cmd: api }
+",
+        )
+        .unwrap()
+    }
+
+    fn line(title: &str, text: &str) -> Message {
+        Message::Line(Line::new(title, Stream::Stdout, text))
+    }
+
+    fn started(title: &str) -> Event {
+        Event::Started {
+            title: title.to_string(),
+            pid: None,
+        }
+    }
+
+    fn readiness(processes: &[WatchProcess]) -> Readiness {
+        let titles = processes
+            .iter()
+            .map(|process| process.title.clone())
+            .collect::<Vec<_>>();
+        Readiness::new(&titles, &Exports::new(processes))
+    }
+
+    #[test]
+    fn startup_lines_are_replaced_once_ready() {
+        let processes = processes();
+        let readiness = readiness(&processes);
+        let mut suppressions = Suppressions::new(&processes);
+
+        assert!(suppressions.hold(&line("db", "starting")));
+        assert!(!suppressions.hold(&line("api", "starting")));
+        assert!(!suppressions.hold(&Message::Line(Line::new("db", Stream::Notice, "note"))));
+        assert!(suppressions.ready("db", &readiness).is_none());
+
+        readiness.record(&started("db"));
+        assert!(suppressions.record(&started("db")).is_none());
+        let ready = suppressions.ready("db", &readiness).unwrap();
+        assert!(ready.text.starts_with("ready in "));
+        assert!(!suppressions.hold(&line("db", "serving")));
+    }
+
+    #[test]
+    fn startup_lines_are_written_when_process_fails() {
+        let processes = processes();
+        let mut suppressions = Suppressions::new(&processes);
+
+        suppressions.hold(&line("db", "starting"));
+        suppressions.hold(&line("db", "port in use"));
+        let failed = suppressions.record(&Event::Exited {
+            title: "db".to_string(),
+            code: Some(1),
+            success: false,
+            ended_by: None,
+        });
+        let texts = failed
+            .as_ref()
+            .map(Message::lines)
+            .unwrap_or_default()
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["starting", "port in use"]);
+        assert!(!suppressions.hold(&line("db", "after")));
+    }
+
+    #[test]
+    fn startup_lines_are_held_back_over_restart() {
+        let processes = processes();
+        let mut suppressions = Suppressions::new(&processes);
+
+        suppressions.hold(&line("db", "starting"));
+        let restarted = suppressions.record(&Event::Exited {
+            title: "db".to_string(),
+            code: None,
+            success: false,
+            ended_by: Some(EndedBy::Restart),
+        });
+        assert!(restarted.is_none());
+        assert!(suppressions.hold(&line("db", "starting again")));
+    }
+}
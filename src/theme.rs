@@ -0,0 +1,149 @@
+use std::{fs, io, path::PathBuf};
+
+use ansi_term::Color;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::output::ColorSupport;
+
+/// Colors of the output chosen with `theme` of the config or `--theme`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Theme {
+    /// Background of the title prefix of stdout lines.
+    pub stdout: ThemeColor,
+    /// Background of the title prefix of stderr lines.
+    pub stderr: ThemeColor,
+    /// Background of the title prefix of notices and rules written by watchmux.
+    pub notice: ThemeColor,
+    /// Text of the title prefixes, terminal default when not given.
+    pub prefix_text: Option<ThemeColor>,
+    /// Text of stderr lines, terminal default when not given.
+    pub stderr_text: Option<ThemeColor>,
+    /// Text of the origin of the process in the title prefix, same as the title when not given.
+    pub origin: Option<ThemeColor>,
+}
+
+/// Color in terminals with basic 8 colors and in terminals with 256 color palette.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct ThemeColor {
+    pub basic: BasicColor,
+    pub fixed: u8,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BasicColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Purple,
+    Cyan,
+    White,
+}
+
+impl ThemeColor {
+    const fn new(basic: BasicColor, fixed: u8) -> Self {
+        Self { basic, fixed }
+    }
+
+    pub fn color(&self, colors: ColorSupport) -> Option<Color> {
+        match colors {
+            ColorSupport::None => None,
+            ColorSupport::Basic => Some(match self.basic {
+                BasicColor::Black => Color::Black,
+                BasicColor::Red => Color::Red,
+                BasicColor::Green => Color::Green,
+                BasicColor::Yellow => Color::Yellow,
+                BasicColor::Blue => Color::Blue,
+                BasicColor::Purple => Color::Purple,
+                BasicColor::Cyan => Color::Cyan,
+                BasicColor::White => Color::White,
+            }),
+            ColorSupport::Fixed => Some(Color::Fixed(self.fixed)),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            stdout: ThemeColor::new(BasicColor::Yellow, 173),
+            stderr: ThemeColor::new(BasicColor::Red, 167),
+            notice: ThemeColor::new(BasicColor::Yellow, 173),
+            prefix_text: None,
+            stderr_text: None,
+            origin: Some(ThemeColor::new(BasicColor::Blue, 17)),
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            stdout: ThemeColor::new(BasicColor::Blue, 67),
+            stderr: ThemeColor::new(BasicColor::Red, 160),
+            notice: ThemeColor::new(BasicColor::Purple, 97),
+            prefix_text: Some(ThemeColor::new(BasicColor::White, 231)),
+            stderr_text: None,
+            origin: Some(ThemeColor::new(BasicColor::Yellow, 229)),
+        }
+    }
+
+    pub fn solarized() -> Self {
+        Self {
+            stdout: ThemeColor::new(BasicColor::Yellow, 136),
+            stderr: ThemeColor::new(BasicColor::Red, 160),
+            notice: ThemeColor::new(BasicColor::Cyan, 37),
+            prefix_text: Some(ThemeColor::new(BasicColor::Black, 234)),
+            stderr_text: Some(ThemeColor::new(BasicColor::Red, 160)),
+            origin: Some(ThemeColor::new(BasicColor::Blue, 33)),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            stdout: ThemeColor::new(BasicColor::White, 231),
+            stderr: ThemeColor::new(BasicColor::Red, 196),
+            notice: ThemeColor::new(BasicColor::Yellow, 226),
+            prefix_text: Some(ThemeColor::new(BasicColor::Black, 16)),
+            stderr_text: Some(ThemeColor::new(BasicColor::Red, 196)),
+            origin: Some(ThemeColor::new(BasicColor::Blue, 21)),
+        }
+    }
+}
+
+/// Built-in theme by name or theme read from YAML file at the path.
+pub fn load(name: &str) -> Result<Theme, ThemeError> {
+    match name {
+        "dark" => Ok(Theme::dark()),
+        "light" => Ok(Theme::light()),
+        "solarized" => Ok(Theme::solarized()),
+        "high-contrast" => Ok(Theme::high_contrast()),
+        path => {
+            let path = PathBuf::from(path);
+            let theme = fs::read_to_string(&path).map_err(|error| ThemeError::Read {
+                path: path.clone(),
+                error,
+            })?;
+            serde_yaml::from_str(&theme).map_err(|error| ThemeError::Parse { path, error })
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ThemeError {
+    #[error("theme is not dark, light, solarized, high-contrast or readable file {path}: {error}")]
+    Read { path: PathBuf, error: io::Error },
+
+    #[error("failed to parse theme file {path}: {error}")]
+    Parse {
+        path: PathBuf,
+        error: serde_yaml::Error,
+    },
+}
@@ -0,0 +1,154 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Transforms text of output lines of a process before it is written to the sinks.
+pub trait Transform: Send + Sync {
+    fn apply(&self, text: String) -> String;
+}
+
+/// Transform of `transforms` in the config e.g. `[strip_ansi, redact: { pattern: "token=\\w+" }]`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum TransformConfig {
+    /// Remove ANSI escape sequences e.g. colors.
+    StripAnsi,
+    /// Replace matches of the pattern with `***`.
+    Redact {
+        #[serde(with = "serde_regex")]
+        pattern: Regex,
+    },
+    /// Replace matches of the pattern with `to` which may refer to capture groups e.g. `$1`.
+    Replace {
+        #[serde(with = "serde_regex")]
+        from: Regex,
+        to: String,
+    },
+}
+
+impl TransformConfig {
+    fn build(&self) -> Box<dyn Transform> {
+        match self {
+            TransformConfig::StripAnsi => Box::new(StripAnsi::new()),
+            TransformConfig::Redact { pattern } => Box::new(Replace {
+                from: pattern.clone(),
+                to: "***".to_string(),
+            }),
+            TransformConfig::Replace { from, to } => Box::new(Replace {
+                from: from.clone(),
+                to: to.clone(),
+            }),
+        }
+    }
+}
+
+/// Chain of transforms applied in order.
+#[derive(Default)]
+pub struct Transforms(Vec<Box<dyn Transform>>);
+
+impl Transforms {
+    pub fn new(configs: &[TransformConfig]) -> Self {
+        Self(configs.iter().map(TransformConfig::build).collect())
+    }
+
+    /// Add transform applied before the others.
+    pub fn prepend(&mut self, transform: Box<dyn Transform>) {
+        self.0.insert(0, transform);
+    }
+
+    pub fn apply(&self, text: String) -> String {
+        self.0
+            .iter()
+            .fold(text, |text, transform| transform.apply(text))
+    }
+}
+
+pub struct StripAnsi {
+    pattern: Regex,
+}
+
+impl StripAnsi {
+    pub fn new() -> Self {
+        Self {
+            // CSI sequences and OSC sequences terminated by BEL or ST
+            pattern: Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)")
+                .expect("ANSI escape pattern must be valid"),
+        }
+    }
+}
+
+impl Transform for StripAnsi {
+    fn apply(&self, text: String) -> String {
+        match self.pattern.replace_all(&text, "") {
+            Cow::Borrowed(_) => text,
+            Cow::Owned(stripped) => stripped,
+        }
+    }
+}
+
+pub struct Replace {
+    from: Regex,
+    to: String,
+}
+
+impl Transform for Replace {
+    fn apply(&self, text: String) -> String {
+        match self.from.replace_all(&text, self.to.as_str()) {
+            Cow::Borrowed(_) => text,
+            Cow::Owned(replaced) => replaced,
+        }
+    }
+}
+
+/// Prefixes lines of recursive make with the directory make has entered relative to the
+/// directory make was started in e.g. `[lib] cc -c util.c`, tracking the `Entering directory`
+/// and `Leaving directory` lines of make.
+pub struct MakeDirectory {
+    pattern: Regex,
+    root: PathBuf,
+    directories: Mutex<Vec<PathBuf>>,
+}
+
+impl MakeDirectory {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            // older make quotes the directory with a backtick and an apostrophe
+            pattern: Regex::new(r"^\S*make(?:\[\d+\])?: (Entering|Leaving) directory [`'](.*)'$")
+                .expect("make directory pattern must be valid"),
+            root,
+            directories: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Transform for MakeDirectory {
+    fn apply(&self, text: String) -> String {
+        let mut directories = self
+            .directories
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        if let Some(captures) = self.pattern.captures(&text) {
+            match &captures[1] {
+                "Entering" => directories.push(PathBuf::from(&captures[2])),
+                _ => {
+                    directories.pop();
+                }
+            }
+            return text;
+        }
+
+        let directory = directories
+            .last()
+            .map(|directory| directory.strip_prefix(&self.root).unwrap_or(directory))
+            .filter(|directory| *directory != Path::new(""));
+        match directory {
+            Some(directory) => format!("[{}] {text}", directory.display()),
+            None => text,
+        }
+    }
+}